@@ -46,7 +46,6 @@
 //
 // Authors: I. Zeqiri, E. Gjergji
 
-use std::env;
 use std::fs;
 use std::path::Path;
 
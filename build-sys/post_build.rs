@@ -33,7 +33,7 @@ fn main() {
     let dest_path = Path::new(&out_dir);
 
     // Create the destination directory
-    fs::create_dir_all(&dest_path).expect("Failed to create output directory");
+    fs::create_dir_all(dest_path).expect("Failed to create output directory");
 
     // Copy the executable
     let exec_name = if cfg!(windows) { "zark_waf.exe" } else { "zark_waf" };
@@ -52,7 +52,7 @@ fn main() {
         for entry in fs::read_dir(deps_path).expect("Failed to read deps directory") {
             let entry = entry.expect("Failed to read directory entry");
             let path = entry.path();
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "dll" || ext == "so") {
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "dll" || ext == "so") {
                 let file_name = path.file_name().unwrap();
                 let dest_file = dest_deps_path.join(file_name);
                 fs::copy(&path, &dest_file).expect("Failed to copy dependency");
@@ -0,0 +1,61 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// pieces shared by the module and plugin library boundaries
+
+// compiler and target the core was built with. libraries share data
+// structures with the core, so they must be built by the same toolchain
+pub const TOOLCHAIN: &str = env!("ZARK_TOOLCHAIN");
+
+// a borrowed utf-8 string that can cross a library boundary
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct AbiStr {
+    ptr: *const u8,
+    len: usize,
+}
+
+// only ever built from a &'static str
+unsafe impl Send for AbiStr {}
+unsafe impl Sync for AbiStr {}
+
+impl AbiStr {
+    pub const fn new(value: &'static str) -> Self {
+        Self { ptr: value.as_ptr(), len: value.len() }
+    }
+
+    /// None if the string isn't valid utf-8.
+    ///
+    /// # Safety
+    ///
+    /// The string must point into memory that is still live, e.g. a library
+    /// that hasn't been unloaded.
+    pub unsafe fn read(self) -> Option<String> {
+        if self.ptr.is_null() {
+            return None;
+        }
+        let bytes = std::slice::from_raw_parts(self.ptr, self.len);
+        std::str::from_utf8(bytes).ok().map(str::to_string)
+    }
+}
@@ -0,0 +1,130 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// request and response bodies in serde. formats that read as text, like the
+// json modules inspect through execute, get a base64 string: Bytes on its own
+// comes out there as an array of numbers, about four times the size of the
+// body. binary formats get the bytes as they are.
+//
+// a json array of numbers still reads, as written before bodies were encoded
+
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserializer, Serializer};
+
+pub(super) fn serialize<S: Serializer>(body: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&STANDARD.encode(body))
+    } else {
+        serializer.serialize_bytes(body)
+    }
+}
+
+pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(BodyVisitor)
+    } else {
+        deserializer.deserialize_byte_buf(BodyVisitor)
+    }
+}
+
+struct BodyVisitor;
+
+impl<'de> Visitor<'de> for BodyVisitor {
+    type Value = Bytes;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a base64 string or bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, encoded: &str) -> Result<Bytes, E> {
+        STANDARD
+            .decode(encoded)
+            .map(Bytes::from)
+            .map_err(|e| E::custom(format!("body is not valid base64: {}", e)))
+    }
+
+    fn visit_bytes<E: de::Error>(self, body: &[u8]) -> Result<Bytes, E> {
+        Ok(Bytes::copy_from_slice(body))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, body: Vec<u8>) -> Result<Bytes, E> {
+        Ok(body.into())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Bytes, A::Error> {
+        let mut body = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            body.push(byte);
+        }
+        Ok(body.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{RequestContext, ResponseContext};
+    use crate::utils::serialization::Format;
+
+    fn request() -> RequestContext {
+        RequestContext::new("POST", "/upload").with_body(vec![0u8, 159, 146, 150, b'a', 255])
+    }
+
+    #[test]
+    fn json_bodies_are_base64() {
+        let json = serde_json::to_value(request()).unwrap();
+        assert_eq!(json["body"], "AJ+SlmH/");
+        let response = serde_json::to_value(ResponseContext::new(200).with_body("ok")).unwrap();
+        assert_eq!(response["body"], "b2s=");
+    }
+
+    #[test]
+    fn bodies_round_trip_in_every_format() {
+        let request = request();
+        for format in [Format::Bincode, Format::Json, Format::MessagePack, Format::Cbor] {
+            let encoded = format.encode(&request).unwrap();
+            assert_eq!(format.decode::<RequestContext>(&encoded).unwrap(), request, "{}", format);
+        }
+    }
+
+    #[test]
+    fn json_byte_arrays_still_read() {
+        let request = request();
+        let mut json = serde_json::to_value(&request).unwrap();
+        json["body"] = serde_json::json!([0, 159, 146, 150, 97, 255]);
+        assert_eq!(serde_json::from_value::<RequestContext>(json).unwrap(), request);
+    }
+
+    #[test]
+    fn invalid_base64_is_refused() {
+        let mut json = serde_json::to_value(request()).unwrap();
+        json["body"] = "not base64!".into();
+        let error = serde_json::from_value::<RequestContext>(json).unwrap_err();
+        assert!(error.to_string().contains("body is not valid base64"), "{}", error);
+    }
+}
@@ -0,0 +1,141 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use super::verdict::Modification;
+use crate::utils::uid::Uid;
+
+// tls details of the client connection, when the request arrived over tls
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TlsInfo {
+    // negotiated protocol version, e.g. "TLSv1.3"
+    pub version: Option<String>,
+    // negotiated cipher suite
+    pub cipher: Option<String>,
+    // server name sent by the client (sni)
+    pub server_name: Option<String>,
+}
+
+// a single http request as seen by the inspection pipeline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestContext {
+    // unique id of the request, used to correlate logs and events
+    pub id: String,
+    pub method: String,
+    // request target as sent by the client (path and query, or absolute uri)
+    pub uri: String,
+    // header names keep the case they were received with
+    pub headers: Vec<(String, String)>,
+    // shared with the buffer it was read into; cloning doesn't copy it
+    #[serde(with = "super::body")]
+    pub body: Bytes,
+    pub remote_addr: Option<SocketAddr>,
+    pub tls: Option<TlsInfo>,
+}
+
+impl RequestContext {
+    pub fn new(method: impl Into<String>, uri: impl Into<String>) -> Self {
+        Self {
+            id: Uid::new().to_string(),
+            method: method.into(),
+            uri: uri.into(),
+            headers: Vec::new(),
+            body: Bytes::new(),
+            remote_addr: None,
+            tls: None,
+        }
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn with_remote_addr(mut self, remote_addr: SocketAddr) -> Self {
+        self.remote_addr = Some(remote_addr);
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsInfo) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    // path component of the uri, without scheme, authority or query
+    pub fn path(&self) -> &str {
+        let uri = match self.uri.find("://") {
+            Some(scheme_end) => {
+                let rest = &self.uri[scheme_end + 3..];
+                rest.find('/').map(|i| &rest[i..]).unwrap_or("/")
+            }
+            None => self.uri.as_str(),
+        };
+        uri.split(['?', '#']).next().unwrap_or(uri)
+    }
+
+    // raw query string of the uri, if any
+    pub fn query(&self) -> Option<&str> {
+        let (_, query) = self.uri.split_once('?')?;
+        Some(query.split('#').next().unwrap_or(query))
+    }
+
+    // first value of the header with the given name (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // apply the modifications of a verdict to this request
+    pub fn apply(&mut self, modifications: &[Modification]) {
+        for modification in modifications {
+            match modification {
+                Modification::SetHeader { name, value } => {
+                    self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+                    self.headers.push((name.clone(), value.clone()));
+                }
+                Modification::RemoveHeader { name } => {
+                    self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+                }
+                Modification::Sanitize { value } => {
+                    self.uri = self.uri.replace(value.as_str(), "");
+                    self.body = modification.apply_to_body(&self.body).into();
+                }
+                Modification::Mask { .. } => {
+                    self.body = modification.apply_to_body(&self.body).into();
+                }
+            }
+        }
+    }
+}
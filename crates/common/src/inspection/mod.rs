@@ -0,0 +1,40 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// inspection module: types shared by the core, modules and plugins to describe
+// a request (and its response) under inspection and the verdict reached for it.
+// modules and plugins inspect them through their inspect_request and
+// inspect_response methods, or as json through execute, where bodies are
+// base64 strings
+
+mod body;
+mod context;
+mod outcome;
+mod response;
+mod verdict;
+
+pub use context::{RequestContext, TlsInfo};
+pub use outcome::{HttpRequest, HttpResponse, Inspection, InspectionCall, InspectionPhase, MatchedRule};
+pub use response::ResponseContext;
+pub use verdict::{Modification, Verdict};
@@ -0,0 +1,229 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+use serde::{Deserialize, Serialize};
+
+use super::context::RequestContext;
+use super::response::ResponseContext;
+use super::verdict::Verdict;
+
+// names modules and plugins use for the request and response they inspect
+pub type HttpRequest = RequestContext;
+pub type HttpResponse = ResponseContext;
+
+// which half of an exchange is being inspected
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InspectionPhase {
+    #[default]
+    Request,
+    Response,
+}
+
+impl InspectionPhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InspectionPhase::Request => "request",
+            InspectionPhase::Response => "response",
+        }
+    }
+}
+
+// a rule that matched, with what it matched on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchedRule {
+    pub rule: String,
+    pub phase: InspectionPhase,
+    #[serde(default)]
+    pub message: String,
+    // the part of the request or response that matched, when the rule reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+impl MatchedRule {
+    pub fn new(rule: impl Into<String>, phase: InspectionPhase) -> Self {
+        Self {
+            rule: rule.into(),
+            phase,
+            message: String::new(),
+            value: None,
+        }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    pub fn with_value(mut self, value: impl Into<String>) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+}
+
+// what a module or plugin concluded about a request or response. in json the
+// verdict's fields sit at the top level, so a bare verdict (or null, for
+// allow) is a valid inspection too
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Inspection {
+    #[serde(flatten)]
+    pub verdict: Verdict,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matched: Vec<MatchedRule>,
+}
+
+impl Inspection {
+    pub fn new(verdict: Verdict) -> Self {
+        Self { verdict, matched: Vec::new() }
+    }
+
+    pub fn with_match(mut self, matched: MatchedRule) -> Self {
+        self.matched.push(matched);
+        self
+    }
+
+    // combine with a later inspection of the same exchange, see Verdict::merge
+    pub fn merge(mut self, other: Inspection) -> Inspection {
+        self.matched.extend(other.matched);
+        Inspection {
+            verdict: self.verdict.merge(other.verdict),
+            matched: self.matched,
+        }
+    }
+
+    // read the output of an execute call made with an InspectionCall
+    pub fn from_output(output: serde_json::Value) -> Result<Self, serde_json::Error> {
+        if output.is_null() {
+            return Ok(Self::default());
+        }
+        serde_json::from_value(output)
+    }
+}
+
+impl From<Verdict> for Inspection {
+    fn from(verdict: Verdict) -> Self {
+        Self::new(verdict)
+    }
+}
+
+// the json input of execute that asks for an inspection, so modules and
+// plugins that only implement execute can still inspect traffic:
+//
+//   {"action": "inspect", "request": {...}}
+//   {"action": "inspect_response", "request": {...}, "response": {...}}
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum InspectionCall {
+    Inspect { request: HttpRequest },
+    InspectResponse { request: HttpRequest, response: HttpResponse },
+}
+
+// the same shape, borrowed for encoding
+#[derive(Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum InspectionCallRef<'a> {
+    Inspect { request: &'a HttpRequest },
+    InspectResponse { request: &'a HttpRequest, response: &'a HttpResponse },
+}
+
+impl InspectionCall {
+    pub fn request(request: &HttpRequest) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::to_value(InspectionCallRef::Inspect { request })
+    }
+
+    pub fn response(request: &HttpRequest, response: &HttpResponse) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::to_value(InspectionCallRef::InspectResponse { request, response })
+    }
+
+    // None if the input is some other execute call
+    pub fn from_input(input: &serde_json::Value) -> Option<Result<Self, serde_json::Error>> {
+        match input.get("action")?.as_str()? {
+            "inspect" | "inspect_response" => Some(Self::deserialize(input)),
+            _ => None,
+        }
+    }
+
+    pub fn phase(&self) -> InspectionPhase {
+        match self {
+            InspectionCall::Inspect { .. } => InspectionPhase::Request,
+            InspectionCall::InspectResponse { .. } => InspectionPhase::Response,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inspection::Modification;
+
+    #[test]
+    fn inspections_round_trip_with_the_verdict_flattened() {
+        let inspection = Inspection::new(Verdict::modify("strip", Modification::Sanitize { value: "<script>".to_string() }))
+            .with_match(MatchedRule::new("strip", InspectionPhase::Request).with_value("<script>"));
+        let json = serde_json::to_value(&inspection).unwrap();
+        assert_eq!(json["verdict"], "modify");
+        assert_eq!(json["rules"], serde_json::json!(["strip"]));
+        assert_eq!(json["modifications"][0]["type"], "sanitize");
+        assert_eq!(json["matched"][0]["rule"], "strip");
+        assert_eq!(Inspection::from_output(json).unwrap(), inspection);
+
+        let block = Inspection::new(Verdict::block("sqli", "no"));
+        assert_eq!(Inspection::from_output(serde_json::to_value(&block).unwrap()).unwrap(), block);
+    }
+
+    #[test]
+    fn null_and_bare_verdicts_are_inspections() {
+        assert_eq!(Inspection::from_output(serde_json::Value::Null).unwrap(), Inspection::default());
+        assert_eq!(
+            serde_json::to_value(Inspection::default()).unwrap(),
+            serde_json::json!({"verdict": "allow"})
+        );
+        let bare = serde_json::json!({"verdict": "block", "reason": "no"});
+        assert_eq!(
+            Inspection::from_output(bare).unwrap().verdict,
+            Verdict::Block { rules: Vec::new(), status: 403, reason: "no".to_string() }
+        );
+        assert!(Inspection::from_output(serde_json::json!({"verdict": "maybe"})).is_err());
+    }
+
+    #[test]
+    fn inspection_calls_round_trip() {
+        let request = HttpRequest::new("POST", "/login").with_header("Host", "example.com").with_body("user=a");
+        let response = HttpResponse::new(302).with_header("Location", "/home");
+
+        let input = InspectionCall::request(&request).unwrap();
+        assert_eq!(input["action"], "inspect");
+        let call = InspectionCall::from_input(&input).unwrap().unwrap();
+        assert_eq!(call.phase(), InspectionPhase::Request);
+        assert_eq!(call, InspectionCall::Inspect { request: request.clone() });
+
+        let input = InspectionCall::response(&request, &response).unwrap();
+        let call = InspectionCall::from_input(&input).unwrap().unwrap();
+        assert_eq!(call, InspectionCall::InspectResponse { request, response });
+
+        assert!(InspectionCall::from_input(&serde_json::json!({"action": "reload"})).is_none());
+        assert!(InspectionCall::from_input(&serde_json::json!({"input": 1})).is_none());
+    }
+}
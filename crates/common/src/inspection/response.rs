@@ -0,0 +1,86 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use super::verdict::Modification;
+
+// an http response as seen by the inspection pipeline, inspected together
+// with the request it answers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseContext {
+    pub status: u16,
+    // header names keep the case they were received with
+    pub headers: Vec<(String, String)>,
+    // shared with the buffer it was read into; cloning doesn't copy it
+    #[serde(with = "super::body")]
+    pub body: Bytes,
+}
+
+impl ResponseContext {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    // first value of the header with the given name (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // apply the modifications of a verdict to this response
+    pub fn apply(&mut self, modifications: &[Modification]) {
+        for modification in modifications {
+            match modification {
+                Modification::SetHeader { name, value } => {
+                    self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+                    self.headers.push((name.clone(), value.clone()));
+                }
+                Modification::RemoveHeader { name } => {
+                    self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+                }
+                Modification::Mask { .. } | Modification::Sanitize { .. } => {
+                    self.body = modification.apply_to_body(&self.body).into();
+                }
+            }
+        }
+    }
+}
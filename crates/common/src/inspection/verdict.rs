@@ -0,0 +1,194 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+
+// status code returned to the client when a block verdict doesn't specify one
+pub const DEFAULT_BLOCK_STATUS: u16 = 403;
+
+fn default_block_status() -> u16 {
+    DEFAULT_BLOCK_STATUS
+}
+
+// a change requested by a verdict, applied to the request or response in flight
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Modification {
+    // set a header, replacing any existing value
+    SetHeader { name: String, value: String },
+    // remove every header with this name
+    RemoveHeader { name: String },
+    // replace every match of a regex in the body with '*'
+    Mask { pattern: String },
+    // strip every occurrence of a value from the uri and body
+    Sanitize { value: String },
+}
+
+impl Modification {
+    // apply a body-level modification; header modifications leave the body untouched
+    pub fn apply_to_body(&self, body: &[u8]) -> Vec<u8> {
+        match self {
+            Modification::Mask { pattern } => match Regex::new(pattern) {
+                Ok(regex) => regex
+                    .replace_all(body, |caps: &regex::bytes::Captures| vec![b'*'; caps[0].len()])
+                    .into_owned(),
+                Err(_) => body.to_vec(),
+            },
+            Modification::Sanitize { value } if !value.is_empty() => {
+                let needle = value.as_bytes();
+                let mut out = Vec::with_capacity(body.len());
+                let mut i = 0;
+                while i < body.len() {
+                    if body[i..].starts_with(needle) {
+                        i += needle.len();
+                    } else {
+                        out.push(body[i]);
+                        i += 1;
+                    }
+                }
+                out
+            }
+            _ => body.to_vec(),
+        }
+    }
+}
+
+// outcome of inspecting a request; every variant but allow carries the names
+// of the rules (or modules) that produced it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "verdict", rename_all = "snake_case")]
+pub enum Verdict {
+    #[default]
+    Allow,
+    Log {
+        #[serde(default)]
+        rules: Vec<String>,
+    },
+    Modify {
+        #[serde(default)]
+        rules: Vec<String>,
+        modifications: Vec<Modification>,
+    },
+    Block {
+        #[serde(default)]
+        rules: Vec<String>,
+        #[serde(default = "default_block_status")]
+        status: u16,
+        #[serde(default)]
+        reason: String,
+    },
+}
+
+impl Verdict {
+    pub fn block(rule: impl Into<String>, reason: impl Into<String>) -> Self {
+        Verdict::Block {
+            rules: vec![rule.into()],
+            status: DEFAULT_BLOCK_STATUS,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn log(rule: impl Into<String>) -> Self {
+        Verdict::Log { rules: vec![rule.into()] }
+    }
+
+    pub fn modify(rule: impl Into<String>, modification: Modification) -> Self {
+        Verdict::Modify {
+            rules: vec![rule.into()],
+            modifications: vec![modification],
+        }
+    }
+
+    // short lowercase name of the verdict, as used in headers and logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Verdict::Allow => "allow",
+            Verdict::Log { .. } => "log",
+            Verdict::Modify { .. } => "modify",
+            Verdict::Block { .. } => "block",
+        }
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        matches!(self, Verdict::Block { .. })
+    }
+
+    // names of the rules that contributed to this verdict
+    pub fn rules(&self) -> &[String] {
+        match self {
+            Verdict::Allow => &[],
+            Verdict::Log { rules } | Verdict::Modify { rules, .. } | Verdict::Block { rules, .. } => rules,
+        }
+    }
+
+    pub fn modifications(&self) -> &[Modification] {
+        match self {
+            Verdict::Modify { modifications, .. } => modifications,
+            _ => &[],
+        }
+    }
+
+    fn into_rules(self) -> Vec<String> {
+        match self {
+            Verdict::Allow => Vec::new(),
+            Verdict::Log { rules } | Verdict::Modify { rules, .. } | Verdict::Block { rules, .. } => rules,
+        }
+    }
+
+    // combine two verdicts: block wins over modify, modify over log, log over
+    // allow. rules are accumulated in order and modifications are concatenated.
+    // when both verdicts block, the first one's status and reason are kept.
+    pub fn merge(self, other: Verdict) -> Verdict {
+        match (self, other) {
+            (Verdict::Block { mut rules, status, reason }, other) => {
+                rules.extend(other.into_rules());
+                Verdict::Block { rules, status, reason }
+            }
+            (first, Verdict::Block { rules: other_rules, status, reason }) => {
+                let mut rules = first.into_rules();
+                rules.extend(other_rules);
+                Verdict::Block { rules, status, reason }
+            }
+            (Verdict::Modify { mut rules, mut modifications }, other) => {
+                if let Verdict::Modify { modifications: more, .. } = &other {
+                    modifications.extend(more.iter().cloned());
+                }
+                rules.extend(other.into_rules());
+                Verdict::Modify { rules, modifications }
+            }
+            (first, Verdict::Modify { rules: other_rules, modifications }) => {
+                let mut rules = first.into_rules();
+                rules.extend(other_rules);
+                Verdict::Modify { rules, modifications }
+            }
+            (Verdict::Allow, other) => other,
+            (first, Verdict::Allow) => first,
+            (Verdict::Log { mut rules }, other) => {
+                rules.extend(other.into_rules());
+                Verdict::Log { rules }
+            }
+        }
+    }
+}
@@ -22,14 +22,14 @@
 //
 // Authors: I. Zeqiri, E. Gjergji
 
+pub mod abi;
+pub mod inspection;
 pub mod messenger;
 pub mod utils;
 
 use std::ffi::c_void;
 
-
-
-
+#[async_trait::async_trait]
 pub trait Module: Send + Sync {
     fn name(&self) -> &str;
     fn version(&self) -> &str;
@@ -0,0 +1,49 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+use super::domain::{Callback, Message, SubscriberId, Topic};
+use super::queue::{Priority, SubscriptionOptions};
+use super::{MessengerError, SubscriptionStats};
+
+// transport behind a Messenger: the in-process native backend, or the
+// external zark_messenger library through ffi
+#[async_trait]
+pub trait MessengerBackend: Send + Sync {
+    // deliver a message to every subscriber of the topic; returns whether
+    // anyone was subscribed. backends without priority lanes may ignore it
+    async fn send(&self, topic: &str, message: &[u8], priority: Priority) -> Result<bool, MessengerError>;
+    async fn subscribe(&self, topic: &str, callback: Callback, options: SubscriptionOptions) -> Result<SubscriberId, MessengerError>;
+    // like subscribe, but messages are pulled from a stream, along with the
+    // topic each was sent to. the stream ends once the subscription is removed
+    async fn subscribe_stream(&self, topic: &str, options: SubscriptionOptions) -> Result<(SubscriberId, BoxStream<'static, (Topic, Message)>), MessengerError>;
+    async fn unsubscribe(&self, topic: &str, subscriber_id: &SubscriberId) -> Result<(), MessengerError>;
+
+    // queue depth and drop counts per subscription, where the backend keeps them
+    async fn stats(&self) -> Vec<SubscriptionStats> {
+        Vec::new()
+    }
+}
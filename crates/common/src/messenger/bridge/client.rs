@@ -0,0 +1,284 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// client side of the bridge, for sidecars written in rust
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::Stream;
+use parking_lot::Mutex;
+use tokio::io::BufReader;
+use tokio::net::UnixStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use super::{read_frame, write_frames, ClientFrame, ServerFrame, OUTGOING_FRAMES};
+use crate::messenger::domain::Message;
+use crate::messenger::{MessengerError, Priority, SubscriptionOptions};
+use crate::utils::serialization::Format;
+
+type Responder = Arc<dyn Fn(Message) -> BoxFuture<'static, Result<Message, String>> + Send + Sync>;
+
+#[derive(Default)]
+struct Shared {
+    // operations waiting for the server's answer
+    pending: Mutex<HashMap<u64, oneshot::Sender<ServerFrame>>>,
+    subscriptions: Mutex<HashMap<u64, mpsc::Sender<Message>>>,
+    responders: Mutex<HashMap<u64, Responder>>,
+}
+
+pub struct BridgeClient {
+    frames: mpsc::Sender<ClientFrame>,
+    shared: Arc<Shared>,
+    next_id: AtomicU64,
+    reader: JoinHandle<()>,
+    writer: JoinHandle<()>,
+}
+
+impl BridgeClient {
+    pub async fn connect(path: impl AsRef<Path>) -> Result<Self, MessengerError> {
+        Self::connect_as(path, Format::default()).await
+    }
+
+    // as connect, writing frames in the given format. the server answers in
+    // the same one
+    pub async fn connect_as(path: impl AsRef<Path>, format: Format) -> Result<Self, MessengerError> {
+        let path = path.as_ref();
+        let stream = UnixStream::connect(path)
+            .await
+            .map_err(|e| MessengerError::InitializationError(format!("{}: {}", path.display(), e)))?;
+        let (reader, writer) = stream.into_split();
+        let (frames, outgoing) = mpsc::channel(OUTGOING_FRAMES);
+        let shared = Arc::new(Shared::default());
+
+        let writer = tokio::spawn(write_frames(writer, outgoing, Arc::new(AtomicU8::new(format.tag()))));
+        let reader = tokio::spawn(read_frames(BufReader::new(reader), shared.clone(), frames.clone()));
+
+        Ok(Self {
+            frames,
+            shared,
+            next_id: AtomicU64::new(0),
+            reader,
+            writer,
+        })
+    }
+
+    pub async fn publish(&self, topic: &str, message: &[u8]) -> Result<bool, MessengerError> {
+        self.publish_with_priority(topic, message, Priority::Data).await
+    }
+
+    pub async fn publish_with_priority(&self, topic: &str, message: &[u8], priority: Priority) -> Result<bool, MessengerError> {
+        let id = self.next_id();
+        let frame = ClientFrame::Publish {
+            id,
+            topic: topic.to_string(),
+            message: message.to_vec(),
+            priority,
+        };
+        match self.call(id, frame).await? {
+            ServerFrame::Published { delivered, .. } => Ok(delivered),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn subscribe(&self, pattern: &str) -> Result<BridgeSubscription, MessengerError> {
+        self.subscribe_with(pattern, SubscriptionOptions::default()).await
+    }
+
+    // the options apply to the subscription inside the core, except that
+    // it never blocks or fails publishers: Block and Error act as DropOldest.
+    // messages that reach this process are queued up to the same capacity,
+    // and dropped once that queue is full
+    pub async fn subscribe_with(&self, pattern: &str, options: SubscriptionOptions) -> Result<BridgeSubscription, MessengerError> {
+        options.validate()?;
+        let id = self.next_id();
+        let (sender, messages) = mpsc::channel(options.capacity);
+        self.shared.subscriptions.lock().insert(id, sender);
+        let subscription = BridgeSubscription {
+            id,
+            messages,
+            shared: self.shared.clone(),
+            frames: self.frames.clone(),
+        };
+        match self.call(id, ClientFrame::Subscribe { id, pattern: pattern.to_string(), options }).await? {
+            ServerFrame::Ok { .. } => Ok(subscription),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // answer requests sent to topics matching the pattern; returns an id for
+    // unsubscribe
+    pub async fn respond<F, Fut>(&self, pattern: &str, handler: F) -> Result<u64, MessengerError>
+    where
+        F: Fn(Message) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Message, String>> + Send + 'static,
+    {
+        let id = self.next_id();
+        let handler: Responder = Arc::new(move |payload| Box::pin(handler(payload)));
+        self.shared.responders.lock().insert(id, handler);
+        match self.call(id, ClientFrame::Respond { id, pattern: pattern.to_string() }).await {
+            Ok(ServerFrame::Ok { .. }) => Ok(id),
+            result => {
+                self.shared.responders.lock().remove(&id);
+                Err(result.map_or_else(|e| e, unexpected))
+            }
+        }
+    }
+
+    pub async fn unsubscribe(&self, subscription: u64) -> Result<(), MessengerError> {
+        self.shared.responders.lock().remove(&subscription);
+        self.shared.subscriptions.lock().remove(&subscription);
+        let id = self.next_id();
+        match self.call(id, ClientFrame::Unsubscribe { id, subscription }).await? {
+            ServerFrame::Ok { .. } => Ok(()),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn request(&self, topic: &str, payload: &[u8], timeout: Duration) -> Result<Message, MessengerError> {
+        let id = self.next_id();
+        let frame = ClientFrame::Request {
+            id,
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+            timeout_ms: timeout.as_millis() as u64,
+        };
+        match self.call(id, frame).await? {
+            ServerFrame::Reply { payload, .. } => Ok(payload),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // send a frame and wait for the server's answer to it. errors reported by
+    // the server come back as BridgeError
+    async fn call(&self, id: u64, frame: ClientFrame) -> Result<ServerFrame, MessengerError> {
+        let (answer, answered) = oneshot::channel();
+        self.shared.pending.lock().insert(id, answer);
+        if self.frames.send(frame).await.is_err() {
+            self.shared.pending.lock().remove(&id);
+            return Err(disconnected());
+        }
+        match answered.await.map_err(|_| disconnected())? {
+            ServerFrame::Error { error, .. } => Err(MessengerError::BridgeError(error)),
+            answer => Ok(answer),
+        }
+    }
+}
+
+impl Drop for BridgeClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+        self.writer.abort();
+    }
+}
+
+// messages for one subscription; dropping it unsubscribes
+pub struct BridgeSubscription {
+    id: u64,
+    messages: mpsc::Receiver<Message>,
+    shared: Arc<Shared>,
+    frames: mpsc::Sender<ClientFrame>,
+}
+
+impl BridgeSubscription {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Stream for BridgeSubscription {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        self.messages.poll_recv(cx)
+    }
+}
+
+impl Drop for BridgeSubscription {
+    fn drop(&mut self) {
+        // nobody waits for the answer, the reader discards it
+        if self.shared.subscriptions.lock().remove(&self.id).is_some() {
+            let _ = self.frames.try_send(ClientFrame::Unsubscribe { id: u64::MAX, subscription: self.id });
+        }
+    }
+}
+
+async fn read_frames(mut reader: BufReader<tokio::net::unix::OwnedReadHalf>, shared: Arc<Shared>, frames: mpsc::Sender<ClientFrame>) {
+    while let Ok(Some((frame, _))) = read_frame::<ServerFrame>(&mut reader).await {
+        match frame {
+            ServerFrame::Message { subscription, message } => {
+                let subscriber = shared.subscriptions.lock().get(&subscription).cloned();
+                // never wait on a subscription: answers to calls arrive on the
+                // same connection and would queue up behind it
+                if let Some(subscriber) = subscriber {
+                    if let Err(mpsc::error::TrySendError::Full(_)) = subscriber.try_send(message) {
+                        log::debug!("Bridge subscription {} is full, dropping a message", subscription);
+                    }
+                }
+            }
+            ServerFrame::Request { subscription, request, payload } => {
+                let responder = shared.responders.lock().get(&subscription).cloned();
+                let frames = frames.clone();
+                tokio::spawn(async move {
+                    let result = match responder {
+                        Some(responder) => responder(payload).await,
+                        None => Err(format!("no responder {}", subscription)),
+                    };
+                    let _ = frames.send(ClientFrame::Reply { request, result }).await;
+                });
+            }
+            ServerFrame::Ok { id }
+            | ServerFrame::Published { id, .. }
+            | ServerFrame::Reply { id, .. }
+            | ServerFrame::Error { id, .. } => {
+                if let Some(answer) = shared.pending.lock().remove(&id) {
+                    let _ = answer.send(frame);
+                }
+            }
+        }
+    }
+    // the server went away: fail pending calls and end every subscription
+    shared.pending.lock().clear();
+    shared.subscriptions.lock().clear();
+}
+
+fn disconnected() -> MessengerError {
+    MessengerError::BridgeError("disconnected from the bridge".to_string())
+}
+
+fn unexpected(frame: ServerFrame) -> MessengerError {
+    MessengerError::BridgeError(format!("unexpected answer from the bridge: {:?}", frame))
+}
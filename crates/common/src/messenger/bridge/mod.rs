@@ -0,0 +1,136 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// the message bus over a unix domain socket, so modules can run as separate
+// processes: a sidecar that crashes takes its own process down, not the core.
+//
+// every frame is a 4-byte big-endian length followed by that many bytes of a
+// ClientFrame or ServerFrame encoded with utils::serialization. the client
+// picks the format of its frames and the server answers in the same one, so
+// tools in other languages can speak json or messagepack. clients pick
+// the ids of their operations; the server answers each one with Ok,
+// Published, Reply or Error carrying the same id. subscriptions are
+// identified by the id of the Subscribe or Respond that created them.
+
+mod client;
+mod server;
+
+pub use client::{BridgeClient, BridgeSubscription};
+pub use server::BridgeServer;
+
+use std::io;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::domain::Message;
+use super::queue::{Priority, SubscriptionOptions};
+use crate::utils::serialization::{self, Format};
+
+// larger frames are a protocol error and close the connection
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+// frames waiting to be written to one peer
+const OUTGOING_FRAMES: usize = 256;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientFrame {
+    Publish { id: u64, topic: String, message: Message, priority: Priority },
+    Subscribe { id: u64, pattern: String, options: SubscriptionOptions },
+    // answer requests sent to topics matching the pattern
+    Respond { id: u64, pattern: String },
+    // ends a Subscribe or Respond
+    Unsubscribe { id: u64, subscription: u64 },
+    Request { id: u64, topic: String, payload: Message, timeout_ms: u64 },
+    // the client's answer to a ServerFrame::Request
+    Reply { request: u64, result: Result<Message, String> },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServerFrame {
+    Ok { id: u64 },
+    Published { id: u64, delivered: bool },
+    Reply { id: u64, payload: Message },
+    Error { id: u64, error: String },
+    // a message for one of the client's subscriptions
+    Message { subscription: u64, message: Message },
+    // a request for one of the client's responders, answered with
+    // ClientFrame::Reply
+    Request { subscription: u64, request: u64, payload: Message },
+}
+
+// a frame and the format it was written in; None once the peer has closed
+// the connection
+pub async fn read_frame<T: DeserializeOwned>(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<(T, Format)>> {
+    let mut length = [0u8; 4];
+    match reader.read_exact(&mut length).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the {} byte limit", length, MAX_FRAME_SIZE),
+        ));
+    }
+    let mut frame = vec![0u8; length];
+    reader.read_exact(&mut frame).await?;
+    let invalid = |e: serialization::SerializationError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    let format = serialization::detect(&frame).map_err(invalid)?;
+    format.decode(&frame[1..]).map(|frame| Some((frame, format))).map_err(invalid)
+}
+
+pub async fn write_frame<T: Serialize>(writer: &mut (impl AsyncWrite + Unpin), frame: &T, format: Format) -> io::Result<()> {
+    let frame = serialization::encode(format, frame).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    if frame.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the {} byte limit", frame.len(), MAX_FRAME_SIZE),
+        ));
+    }
+    let mut buffer = Vec::with_capacity(4 + frame.len());
+    buffer.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(&frame);
+    writer.write_all(&buffer).await
+}
+
+// drain a connection's outgoing frames onto its socket, in the format last
+// stored as a tag in format
+async fn write_frames<T: Serialize>(
+    mut writer: impl AsyncWrite + Unpin,
+    mut frames: tokio::sync::mpsc::Receiver<T>,
+    format: Arc<AtomicU8>,
+) {
+    while let Some(frame) = frames.recv().await {
+        let format = Format::from_tag(format.load(Ordering::Relaxed)).unwrap_or_default();
+        if write_frame(&mut writer, &frame, format).await.is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,283 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+use std::collections::HashMap;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use parking_lot::Mutex;
+use tokio::io::BufReader;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{AbortHandle, JoinSet};
+
+use super::{read_frame, write_frames, ClientFrame, ServerFrame, OUTGOING_FRAMES};
+use crate::messenger::domain::{Message, SubscriberId};
+use crate::messenger::{Messenger, MessengerError, OverflowPolicy, SubscriptionOptions};
+use crate::utils::serialization::Format;
+
+// how long a bridged responder gets to answer before the request fails
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct BridgeServer {
+    listener: UnixListener,
+    path: PathBuf,
+    messenger: Arc<Messenger>,
+}
+
+impl BridgeServer {
+    // the socket is created owner-only; a socket left behind by an earlier
+    // run is replaced, any other file at the path is an error
+    pub fn bind(path: impl AsRef<Path>, messenger: Arc<Messenger>) -> Result<Self, MessengerError> {
+        let path = path.as_ref().to_path_buf();
+        let init_error = |e: std::io::Error| MessengerError::InitializationError(format!("{}: {}", path.display(), e));
+
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                return Err(MessengerError::InitializationError(format!(
+                    "{} exists and is not a socket",
+                    path.display()
+                )));
+            }
+            std::fs::remove_file(&path).map_err(init_error)?;
+        }
+        let listener = UnixListener::bind(&path).map_err(init_error)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).map_err(init_error)?;
+        log::info!("Message bus bridge listening on {}", path.display());
+
+        Ok(Self { listener, path, messenger })
+    }
+
+    // serve until the task is dropped, which also closes every connection
+    pub async fn serve(self) {
+        let mut connections = JoinSet::new();
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        connections.spawn(Connection::run(self.messenger.clone(), stream));
+                    }
+                    Err(e) => log::error!("Message bus bridge failed to accept a connection: {}", e),
+                },
+                Some(_) = connections.join_next() => {}
+            }
+        }
+    }
+}
+
+impl Drop for BridgeServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+enum Registration {
+    Stream(AbortHandle),
+    Responder(String, SubscriberId),
+}
+
+// requests forwarded to the client's responders, by request number
+type PendingReplies = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Message, String>>>>>;
+
+struct Connection {
+    messenger: Arc<Messenger>,
+    frames: mpsc::Sender<ServerFrame>,
+    subscriptions: HashMap<u64, Registration>,
+    // subscription forwarders and requests in flight; dropped with the connection
+    tasks: JoinSet<()>,
+    pending: PendingReplies,
+    next_request: Arc<AtomicU64>,
+}
+
+impl Connection {
+    async fn run(messenger: Arc<Messenger>, stream: UnixStream) {
+        log::debug!("Message bus bridge client connected");
+        let (reader, writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let (frames, outgoing) = mpsc::channel(OUTGOING_FRAMES);
+        // answer in whatever format the client last wrote
+        let format = Arc::new(AtomicU8::new(Format::default().tag()));
+        let writer = tokio::spawn(write_frames(writer, outgoing, format.clone()));
+        let mut connection = Self {
+            messenger,
+            frames,
+            subscriptions: HashMap::new(),
+            tasks: JoinSet::new(),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            next_request: Arc::new(AtomicU64::new(0)),
+        };
+
+        loop {
+            match read_frame::<ClientFrame>(&mut reader).await {
+                Ok(Some((frame, client_format))) => {
+                    format.store(client_format.tag(), Ordering::Relaxed);
+                    connection.handle(frame).await
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    log::warn!("Message bus bridge client sent an invalid frame: {}", e);
+                    break;
+                }
+            }
+        }
+
+        connection.close().await;
+        writer.abort();
+        log::debug!("Message bus bridge client disconnected");
+    }
+
+    async fn handle(&mut self, frame: ClientFrame) {
+        // finished requests and forwarders would otherwise pile up in the set
+        // for as long as the client stays connected
+        while self.tasks.try_join_next().is_some() {}
+        let answer = match frame {
+            ClientFrame::Publish { id, topic, message, priority } => match self.messenger.send_with_priority(&topic, &message, priority).await {
+                Ok(delivered) => ServerFrame::Published { id, delivered },
+                Err(e) => error(id, e),
+            },
+            ClientFrame::Subscribe { id, pattern, options } => self.subscribe(id, &pattern, options).await,
+            ClientFrame::Respond { id, pattern } => self.respond(id, &pattern).await,
+            ClientFrame::Unsubscribe { id, subscription } => self.unsubscribe(id, subscription).await,
+            ClientFrame::Request { id, topic, payload, timeout_ms } => {
+                let messenger = self.messenger.clone();
+                let frames = self.frames.clone();
+                self.tasks.spawn(async move {
+                    let answer = match messenger.request(&topic, &payload, Duration::from_millis(timeout_ms)).await {
+                        Ok(payload) => ServerFrame::Reply { id, payload },
+                        Err(e) => error(id, e),
+                    };
+                    let _ = frames.send(answer).await;
+                });
+                return;
+            }
+            ClientFrame::Reply { request, result } => {
+                // the request may already have timed out
+                if let Some(reply) = self.pending.lock().remove(&request) {
+                    let _ = reply.send(result);
+                }
+                return;
+            }
+        };
+        let _ = self.frames.clone().send(answer).await;
+    }
+
+    async fn subscribe(&mut self, id: u64, pattern: &str, mut options: SubscriptionOptions) -> ServerFrame {
+        if self.subscriptions.contains_key(&id) {
+            return ServerFrame::Error { id, error: format!("subscription {} already exists", id) };
+        }
+        // a client that stops reading must not hold up publishers in the
+        // core: its queue drops the oldest messages, which go to the dead
+        // letter topic, instead of blocking senders or failing them
+        if matches!(options.overflow, OverflowPolicy::Block | OverflowPolicy::Error) {
+            options.overflow = OverflowPolicy::DropOldest;
+        }
+        let mut messages = match self.messenger.subscribe_stream_with(pattern, options).await {
+            Ok(messages) => messages,
+            Err(e) => return error(id, e),
+        };
+        let frames = self.frames.clone();
+        let forwarder = self.tasks.spawn(async move {
+            while let Some(message) = messages.next().await {
+                if frames.send(ServerFrame::Message { subscription: id, message }).await.is_err() {
+                    break;
+                }
+            }
+        });
+        self.subscriptions.insert(id, Registration::Stream(forwarder));
+        ServerFrame::Ok { id }
+    }
+
+    async fn respond(&mut self, id: u64, pattern: &str) -> ServerFrame {
+        if self.subscriptions.contains_key(&id) {
+            return ServerFrame::Error { id, error: format!("subscription {} already exists", id) };
+        }
+        let frames = self.frames.clone();
+        let pending = self.pending.clone();
+        let next_request = self.next_request.clone();
+        let responder = self.messenger.respond(pattern, move |payload| {
+            let frames = frames.clone();
+            let pending = pending.clone();
+            let request = next_request.fetch_add(1, Ordering::Relaxed);
+            async move {
+                let (reply, replied) = oneshot::channel();
+                pending.lock().insert(request, reply);
+                let forwarded = frames.send(ServerFrame::Request { subscription: id, request, payload }).await;
+                let result = match forwarded {
+                    Ok(()) => match tokio::time::timeout(REPLY_TIMEOUT, replied).await {
+                        Ok(Ok(result)) => result,
+                        Ok(Err(_)) => Err("bridge client disconnected".to_string()),
+                        Err(_) => Err(format!("bridge client did not reply within {:?}", REPLY_TIMEOUT)),
+                    },
+                    Err(_) => Err("bridge client disconnected".to_string()),
+                };
+                pending.lock().remove(&request);
+                result
+            }
+        });
+        match responder.await {
+            Ok(subscriber_id) => {
+                self.subscriptions.insert(id, Registration::Responder(pattern.to_string(), subscriber_id));
+                ServerFrame::Ok { id }
+            }
+            Err(e) => error(id, e),
+        }
+    }
+
+    async fn unsubscribe(&mut self, id: u64, subscription: u64) -> ServerFrame {
+        let result = match self.subscriptions.remove(&subscription) {
+            // dropping the forwarder drops its stream, which unsubscribes
+            Some(Registration::Stream(forwarder)) => {
+                forwarder.abort();
+                Ok(())
+            }
+            Some(Registration::Responder(pattern, subscriber_id)) => {
+                self.messenger.unsubscribe(&pattern, &subscriber_id).await
+            }
+            None => Err(MessengerError::InvalidSubscriberId(subscription.to_string())),
+        };
+        match result {
+            Ok(()) => ServerFrame::Ok { id },
+            Err(e) => error(id, e),
+        }
+    }
+
+    async fn close(&mut self) {
+        for (_, registration) in self.subscriptions.drain() {
+            if let Registration::Responder(pattern, subscriber_id) = registration {
+                let _ = self.messenger.unsubscribe(&pattern, &subscriber_id).await;
+            }
+        }
+        self.tasks.shutdown().await;
+        // fail whatever was waiting on this client
+        self.pending.lock().clear();
+    }
+}
+
+fn error(id: u64, error: MessengerError) -> ServerFrame {
+    ServerFrame::Error { id, error: error.to_string() }
+}
@@ -24,7 +24,7 @@
 
 use std::sync::Arc;
 use crate::utils::uid::Uid;
-use tokio::sync::mpsc;
+use super::queue::SubscriberQueue;
 
 // type alias for a topic, which is just a string
 pub type Topic = String;
@@ -45,8 +45,8 @@ pub struct Subscriber {
     pub id: SubscriberId,
     // topic the subscriber is interested in
     pub topic: Topic,
-    // channel sender for sending messages to this subscriber
-    pub sender: mpsc::Sender<Message>,
+    // bounded queue the subscriber's delivery task reads from
+    pub queue: Arc<SubscriberQueue>,
 }
 
 // struct representing a subscription
@@ -54,24 +54,4 @@ pub struct Subscriber {
 pub struct Subscription {
     // the subscriber
     pub subscriber: Subscriber,
-    // the callback function to be called when a message is received
-    pub callback: Callback,
 }
-
-// implement Clone for SubscriberId (which is just an alias for Uid)
-impl Clone for SubscriberId {
-    fn clone(&self) -> Self {
-        // create a new SubscriberId from the string representation of the current one
-        SubscriberId::from_string(&self.to_string()).unwrap()
-    }
-}
-
-// implement PartialEq for SubscriberId to allow comparison
-impl PartialEq for SubscriberId {
-    fn eq(&self, other: &Self) -> bool {
-        self.to_string() == other.to_string()
-    }
-}
-
-// implement Eq for SubscriberId to allow use in hash maps
-impl Eq for SubscriberId {}
\ No newline at end of file
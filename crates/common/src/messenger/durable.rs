@@ -0,0 +1,837 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// durable topics: every message sent to one is persisted before it is
+// delivered, and named consumers read it from the store rather than from a
+// live subscription. a consumer acknowledges each message by offset; after a
+// restart it resumes at the first offset it hadn't acknowledged, and messages
+// it was handed but didn't acknowledge in time are handed out again. delivery
+// is at least once, so consumers must tolerate duplicates.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{Mutex, Notify, OwnedMutexGuard};
+use tokio::time::Instant;
+
+use super::domain::Message;
+use super::{topic, Messenger, MessengerError};
+
+// storage behind durable topics. offsets count messages per topic from zero
+#[async_trait]
+pub trait DurableStore: Send + Sync {
+    // persist a message, returning its offset
+    async fn append(&self, topic: &str, message: &[u8]) -> Result<u64, MessengerError>;
+    // the message at the offset, or None past the end and below the first
+    async fn read(&self, topic: &str, offset: u64) -> Result<Option<Message>, MessengerError>;
+    // the oldest offset still stored; stores that delete old messages move it up
+    async fn first_offset(&self, _topic: &str) -> Result<u64, MessengerError> {
+        Ok(0)
+    }
+    // the first offset the consumer has not acknowledged
+    async fn committed(&self, topic: &str, consumer: &str) -> Result<u64, MessengerError>;
+    async fn commit(&self, topic: &str, consumer: &str, offset: u64) -> Result<(), MessengerError>;
+}
+
+// how a durable consumer is fed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsumerOptions {
+    // a message not acknowledged within this long is delivered again
+    pub redelivery_timeout: Duration,
+    // unacknowledged messages handed out at once
+    pub max_in_flight: usize,
+}
+
+impl Default for ConsumerOptions {
+    fn default() -> Self {
+        Self {
+            redelivery_timeout: Duration::from_secs(30),
+            max_in_flight: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub offset: u64,
+    pub message: Message,
+    // whether this offset was handed out before without being acknowledged
+    pub redelivered: bool,
+}
+
+struct DurableTopic {
+    store: Arc<dyn DurableStore>,
+    // woken after every append
+    appended: Arc<Notify>,
+    // consumers currently reading the topic
+    consumers: Arc<parking_lot::Mutex<HashSet<String>>>,
+}
+
+#[derive(Default)]
+pub(super) struct DurableTopics {
+    topics: RwLock<HashMap<String, DurableTopic>>,
+}
+
+impl DurableTopics {
+    // persist the message if the topic is durable; returns whether it was
+    pub(super) async fn append(&self, topic: &str, message: &[u8]) -> Result<bool, MessengerError> {
+        let durable = self
+            .topics
+            .read()
+            .get(topic)
+            .map(|durable| (durable.store.clone(), durable.appended.clone()));
+        let Some((store, appended)) = durable else {
+            return Ok(false);
+        };
+        store.append(topic, message).await?;
+        appended.notify_waiters();
+        Ok(true)
+    }
+}
+
+impl Messenger {
+    // persist every message sent to the topic from now on. durable topics are
+    // concrete topics, not patterns
+    pub fn make_durable(&self, topic: &str, store: Arc<dyn DurableStore>) -> Result<(), MessengerError> {
+        topic::validate_topic(topic)?;
+        self.durable.topics.write().entry(topic.to_string()).or_insert_with(|| DurableTopic {
+            store,
+            appended: Arc::new(Notify::new()),
+            consumers: Arc::default(),
+        });
+        Ok(())
+    }
+
+    // read a durable topic as the named consumer, starting at its first
+    // unacknowledged message. one subscription per consumer name at a time
+    pub async fn consume(&self, topic: &str, consumer: &str, options: ConsumerOptions) -> Result<DurableSubscription, MessengerError> {
+        if consumer.is_empty() {
+            return Err(MessengerError::SubscribeError("consumer name must not be empty".to_string()));
+        }
+        if options.max_in_flight == 0 {
+            return Err(MessengerError::SubscribeError("max in flight must be at least 1".to_string()));
+        }
+        let (store, appended, consumers) = self
+            .durable
+            .topics
+            .read()
+            .get(topic)
+            .map(|durable| (durable.store.clone(), durable.appended.clone(), durable.consumers.clone()))
+            .ok_or_else(|| MessengerError::SubscribeError(format!("{} is not a durable topic", topic)))?;
+        if !consumers.lock().insert(consumer.to_string()) {
+            return Err(MessengerError::SubscribeError(format!(
+                "consumer {} is already reading {}",
+                consumer, topic
+            )));
+        }
+
+        let committed = match start_offset(store.as_ref(), topic, consumer).await {
+            Ok(committed) => committed,
+            Err(e) => {
+                consumers.lock().remove(consumer);
+                return Err(e);
+            }
+        };
+        Ok(DurableSubscription {
+            topic: topic.to_string(),
+            consumer: consumer.to_string(),
+            options,
+            store,
+            appended,
+            consumers,
+            committed,
+            next: committed,
+            in_flight: BTreeMap::new(),
+            acked: BTreeSet::new(),
+        })
+    }
+}
+
+// where a consumer resumes: its committed offset, or the first one still
+// stored if the messages it hadn't acknowledged were deleted. committed right
+// away, so a new consumer holds back deletion from its first message on
+async fn start_offset(store: &dyn DurableStore, topic: &str, consumer: &str) -> Result<u64, MessengerError> {
+    let committed = store.committed(topic, consumer).await?;
+    let start = committed.max(store.first_offset(topic).await?);
+    store.commit(topic, consumer, start).await?;
+    Ok(start)
+}
+
+pub struct DurableSubscription {
+    topic: String,
+    consumer: String,
+    options: ConsumerOptions,
+    store: Arc<dyn DurableStore>,
+    appended: Arc<Notify>,
+    consumers: Arc<parking_lot::Mutex<HashSet<String>>>,
+    // every offset below this is acknowledged and stored as such
+    committed: u64,
+    // the next offset not handed out yet
+    next: u64,
+    // handed out, not acknowledged, by redelivery deadline
+    in_flight: BTreeMap<u64, Instant>,
+    // acknowledged above the committed offset, waiting for the gap to close
+    acked: BTreeSet<u64>,
+}
+
+impl DurableSubscription {
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn consumer(&self) -> &str {
+        &self.consumer
+    }
+
+    // wait for the next message: an expired unacknowledged one first, then
+    // the next one in the topic
+    pub async fn next(&mut self) -> Result<Delivery, MessengerError> {
+        loop {
+            // registered before looking at the store so an append in between
+            // still wakes us
+            let appended = self.appended.notified();
+            tokio::pin!(appended);
+            appended.as_mut().enable();
+
+            let now = Instant::now();
+            let expired = self
+                .in_flight
+                .iter()
+                .find(|(_, deadline)| **deadline <= now)
+                .map(|(offset, _)| *offset);
+            if let Some(offset) = expired {
+                if let Some(message) = self.store.read(&self.topic, offset).await? {
+                    self.in_flight.insert(offset, now + self.options.redelivery_timeout);
+                    return Ok(Delivery { offset, message, redelivered: true });
+                }
+            }
+
+            if self.in_flight.len() < self.options.max_in_flight {
+                if let Some(message) = self.store.read(&self.topic, self.next).await? {
+                    let offset = self.next;
+                    self.next += 1;
+                    self.in_flight.insert(offset, now + self.options.redelivery_timeout);
+                    return Ok(Delivery { offset, message, redelivered: false });
+                }
+            }
+
+            match self.in_flight.values().min().copied() {
+                Some(deadline) => {
+                    tokio::select! {
+                        _ = appended => {}
+                        _ = tokio::time::sleep_until(deadline) => {}
+                    }
+                }
+                None => appended.await,
+            }
+        }
+    }
+
+    // acknowledge a delivered message. acknowledging an offset twice, or one
+    // that was never handed out, does nothing
+    pub async fn ack(&mut self, offset: u64) -> Result<(), MessengerError> {
+        if self.in_flight.remove(&offset).is_none() {
+            return Ok(());
+        }
+        self.acked.insert(offset);
+        let before = self.committed;
+        while self.acked.remove(&self.committed) {
+            self.committed += 1;
+        }
+        if self.committed != before {
+            self.store.commit(&self.topic, &self.consumer, self.committed).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DurableSubscription {
+    fn drop(&mut self) {
+        self.consumers.lock().remove(&self.consumer);
+    }
+}
+
+// how hard a FileStore tries to keep what it was handed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    // every append and commit reaches the disk before it returns
+    #[default]
+    Always,
+    // writes are left to the operating system to flush, so a power loss can
+    // take the latest messages and commits with it
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStoreOptions {
+    pub sync: SyncPolicy,
+    // a topic's log moves on to a new segment once the current one is this big
+    pub segment_size: u64,
+    // delete segments every consumer has committed past
+    pub delete_consumed: bool,
+}
+
+impl Default for FileStoreOptions {
+    fn default() -> Self {
+        Self {
+            sync: SyncPolicy::Always,
+            segment_size: 64 * 1024 * 1024,
+            delete_consumed: true,
+        }
+    }
+}
+
+// a directory with an append-only log per topic, split into segments
+// "<topic>.<first offset>.log", and the committed offset of each consumer in
+// "<topic>.<consumer>.offset". offsets of a segment's messages are indexed in
+// memory when the log is first opened. once every consumer has committed
+// past a segment it is deleted, and offsets below it read as None
+struct Segment {
+    base: u64,
+    file: File,
+    // byte position of each message's record
+    positions: Vec<u64>,
+    end: u64,
+}
+
+// oldest segment first; appends go to the last one
+struct TopicLog {
+    segments: Vec<Segment>,
+}
+
+impl TopicLog {
+    fn next_offset(&self) -> u64 {
+        let active = self.segments.last().expect("a log has at least one segment");
+        active.base + active.positions.len() as u64
+    }
+}
+
+pub struct FileStore {
+    directory: PathBuf,
+    options: FileStoreOptions,
+    // one lock per topic, so topics don't wait on each other's disk writes
+    logs: parking_lot::Mutex<HashMap<String, Arc<Mutex<Option<TopicLog>>>>>,
+}
+
+impl FileStore {
+    pub async fn open(directory: impl AsRef<Path>) -> Result<Self, MessengerError> {
+        Self::open_with(directory, FileStoreOptions::default()).await
+    }
+
+    pub async fn open_with(directory: impl AsRef<Path>, options: FileStoreOptions) -> Result<Self, MessengerError> {
+        if options.segment_size == 0 {
+            return Err(MessengerError::StoreError("segment size must be at least 1".to_string()));
+        }
+        let directory = directory.as_ref().to_path_buf();
+        tokio::fs::create_dir_all(&directory)
+            .await
+            .map_err(|e| store_error(&directory, e))?;
+        Ok(Self {
+            directory,
+            options,
+            logs: parking_lot::Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn segment_path(&self, topic: &str, base: u64) -> PathBuf {
+        self.directory.join(format!("{}.{:020}.log", file_name(topic), base))
+    }
+
+    fn offset_path(&self, topic: &str, consumer: &str) -> PathBuf {
+        self.directory
+            .join(format!("{}.{}.offset", file_name(topic), file_name(consumer)))
+    }
+
+    // the topic's log, loaded on first use
+    async fn lock(&self, topic: &str) -> Result<OwnedMutexGuard<Option<TopicLog>>, MessengerError> {
+        let log = self.logs.lock().entry(topic.to_string()).or_default().clone();
+        let mut log = log.lock_owned().await;
+        if log.is_none() {
+            *log = Some(self.load(topic).await?);
+        }
+        Ok(log)
+    }
+
+    // open and index every segment of a topic's log
+    async fn load(&self, topic: &str) -> Result<TopicLog, MessengerError> {
+        // logs written before segments were one "<topic>.log" starting at 0
+        let unsegmented = self.directory.join(format!("{}.log", file_name(topic)));
+        if tokio::fs::try_exists(&unsegmented).await.map_err(|e| store_error(&unsegmented, e))? {
+            let path = self.segment_path(topic, 0);
+            tokio::fs::rename(&unsegmented, &path)
+                .await
+                .map_err(|e| store_error(&path, e))?;
+        }
+
+        let prefix = format!("{}.", file_name(topic));
+        let mut bases = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.directory)
+            .await
+            .map_err(|e| store_error(&self.directory, e))?;
+        while let Some(entry) = entries.next_entry().await.map_err(|e| store_error(&self.directory, e))? {
+            let name = entry.file_name();
+            let base = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|name| name.strip_suffix(".log"))
+                .and_then(|base| base.parse::<u64>().ok());
+            bases.extend(base);
+        }
+        bases.sort_unstable();
+        if bases.is_empty() {
+            bases.push(0);
+        }
+
+        let mut segments = Vec::with_capacity(bases.len());
+        for base in bases {
+            segments.push(self.open_segment(topic, base).await?);
+        }
+        Ok(TopicLog { segments })
+    }
+
+    // open and index a segment, cutting off a record left half-written
+    async fn open_segment(&self, topic: &str, base: u64) -> Result<Segment, MessengerError> {
+        let path = self.segment_path(topic, base);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .await
+            .map_err(|e| store_error(&path, e))?;
+        let length = file.metadata().await.map_err(|e| store_error(&path, e))?.len();
+
+        let mut positions = Vec::new();
+        let mut end = 0;
+        while end + 4 <= length {
+            let mut size = [0u8; 4];
+            file.seek(SeekFrom::Start(end)).await.map_err(|e| store_error(&path, e))?;
+            file.read_exact(&mut size).await.map_err(|e| store_error(&path, e))?;
+            let next = end + 4 + u32::from_be_bytes(size) as u64;
+            if next > length {
+                break;
+            }
+            positions.push(end);
+            end = next;
+        }
+        if end != length {
+            file.set_len(end).await.map_err(|e| store_error(&path, e))?;
+        }
+        Ok(Segment { base, file, positions, end })
+    }
+
+    // the lowest offset committed by any consumer of the topic, or None
+    // while it has none
+    async fn min_committed(&self, topic: &str) -> Result<Option<u64>, MessengerError> {
+        let prefix = format!("{}.", file_name(topic));
+        let mut min: Option<u64> = None;
+        let mut entries = tokio::fs::read_dir(&self.directory)
+            .await
+            .map_err(|e| store_error(&self.directory, e))?;
+        while let Some(entry) = entries.next_entry().await.map_err(|e| store_error(&self.directory, e))? {
+            let name = entry.file_name();
+            let is_offset = name
+                .to_str()
+                .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(".offset"));
+            if !is_offset {
+                continue;
+            }
+            let path = entry.path();
+            let offset = match tokio::fs::read_to_string(&path).await {
+                Ok(offset) => parse_offset(&path, &offset)?,
+                // a consumer's offset is replaced by renaming over it
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(store_error(&path, e)),
+            };
+            min = Some(min.map_or(offset, |min| min.min(offset)));
+        }
+        Ok(min)
+    }
+
+    // delete the segments below the topic's lowest committed offset. the
+    // segment being appended to always stays
+    async fn delete_consumed(&self, topic: &str) -> Result<(), MessengerError> {
+        let mut log = self.lock(topic).await?;
+        let log = log.as_mut().expect("log is loaded");
+        if log.segments.len() < 2 {
+            return Ok(());
+        }
+        let Some(min) = self.min_committed(topic).await? else {
+            return Ok(());
+        };
+        while log.segments.len() > 1 && log.segments[1].base <= min {
+            let segment = log.segments.remove(0);
+            let path = self.segment_path(topic, segment.base);
+            drop(segment);
+            tokio::fs::remove_file(&path).await.map_err(|e| store_error(&path, e))?;
+        }
+        Ok(())
+    }
+
+    // make a file created or renamed in the directory survive a crash
+    async fn sync_directory(&self) -> Result<(), MessengerError> {
+        let directory = File::open(&self.directory)
+            .await
+            .map_err(|e| store_error(&self.directory, e))?;
+        directory.sync_all().await.map_err(|e| store_error(&self.directory, e))
+    }
+}
+
+#[async_trait]
+impl DurableStore for FileStore {
+    async fn append(&self, topic: &str, message: &[u8]) -> Result<u64, MessengerError> {
+        let size = u32::try_from(message.len())
+            .map_err(|_| MessengerError::StoreError(format!("message of {} bytes is too large", message.len())))?;
+        let mut log = self.lock(topic).await?;
+        let log = log.as_mut().expect("log is loaded");
+
+        let active = log.segments.last().expect("a log has at least one segment");
+        if active.end >= self.options.segment_size && !active.positions.is_empty() {
+            let segment = self.open_segment(topic, log.next_offset()).await?;
+            log.segments.push(segment);
+            if self.options.sync == SyncPolicy::Always {
+                self.sync_directory().await?;
+            }
+        }
+        let active = log.segments.last_mut().expect("a log has at least one segment");
+
+        let mut record = Vec::with_capacity(4 + message.len());
+        record.extend_from_slice(&size.to_be_bytes());
+        record.extend_from_slice(message);
+        let path = self.segment_path(topic, active.base);
+        active.file.write_all(&record).await.map_err(|e| store_error(&path, e))?;
+        active.file.flush().await.map_err(|e| store_error(&path, e))?;
+        if self.options.sync == SyncPolicy::Always {
+            active.file.sync_data().await.map_err(|e| store_error(&path, e))?;
+        }
+
+        active.positions.push(active.end);
+        active.end += record.len() as u64;
+        Ok(log.next_offset() - 1)
+    }
+
+    async fn read(&self, topic: &str, offset: u64) -> Result<Option<Message>, MessengerError> {
+        let mut log = self.lock(topic).await?;
+        let log = log.as_mut().expect("log is loaded");
+        // the last segment starting at or before the offset
+        let index = log.segments.partition_point(|segment| segment.base <= offset);
+        let Some(segment) = index.checked_sub(1).map(|index| &mut log.segments[index]) else {
+            return Ok(None);
+        };
+        let Some(&position) = segment.positions.get((offset - segment.base) as usize) else {
+            return Ok(None);
+        };
+
+        let path = self.segment_path(topic, segment.base);
+        let mut size = [0u8; 4];
+        segment.file.seek(SeekFrom::Start(position)).await.map_err(|e| store_error(&path, e))?;
+        segment.file.read_exact(&mut size).await.map_err(|e| store_error(&path, e))?;
+        let mut message = vec![0u8; u32::from_be_bytes(size) as usize];
+        segment.file.read_exact(&mut message).await.map_err(|e| store_error(&path, e))?;
+        Ok(Some(message))
+    }
+
+    async fn first_offset(&self, topic: &str) -> Result<u64, MessengerError> {
+        let log = self.lock(topic).await?;
+        Ok(log.as_ref().expect("log is loaded").segments[0].base)
+    }
+
+    async fn committed(&self, topic: &str, consumer: &str) -> Result<u64, MessengerError> {
+        let path = self.offset_path(topic, consumer);
+        match tokio::fs::read_to_string(&path).await {
+            Ok(offset) => parse_offset(&path, &offset),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(store_error(&path, e)),
+        }
+    }
+
+    // written to a temporary file first so a crash never leaves a torn offset
+    async fn commit(&self, topic: &str, consumer: &str, offset: u64) -> Result<(), MessengerError> {
+        let path = self.offset_path(topic, consumer);
+        let temporary = path.with_extension("offset.tmp");
+        let mut file = File::create(&temporary).await.map_err(|e| store_error(&temporary, e))?;
+        file.write_all(offset.to_string().as_bytes())
+            .await
+            .map_err(|e| store_error(&temporary, e))?;
+        if self.options.sync == SyncPolicy::Always {
+            file.sync_data().await.map_err(|e| store_error(&temporary, e))?;
+        }
+        drop(file);
+        tokio::fs::rename(&temporary, &path)
+            .await
+            .map_err(|e| store_error(&path, e))?;
+        if self.options.sync == SyncPolicy::Always {
+            self.sync_directory().await?;
+        }
+
+        if self.options.delete_consumed {
+            self.delete_consumed(topic).await?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_offset(path: &Path, offset: &str) -> Result<u64, MessengerError> {
+    offset
+        .trim()
+        .parse()
+        .map_err(|_| MessengerError::StoreError(format!("{}: not an offset", path.display())))
+}
+
+// topics and consumer names may hold characters that don't belong in a file
+// name; everything but letters, digits, '-' and '_' is hex-escaped, including
+// the dots that separate the parts of the name
+fn file_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_') {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{:02x}", byte));
+        }
+    }
+    escaped
+}
+
+fn store_error(path: &Path, e: std::io::Error) -> MessengerError {
+    MessengerError::StoreError(format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOPIC: &str = "audit.events";
+
+    // small enough that every record starts a new segment
+    fn one_per_segment() -> FileStoreOptions {
+        FileStoreOptions {
+            sync: SyncPolicy::Never,
+            segment_size: 1,
+            delete_consumed: false,
+        }
+    }
+
+    fn record(message: &[u8]) -> Vec<u8> {
+        let mut record = (message.len() as u32).to_be_bytes().to_vec();
+        record.extend_from_slice(message);
+        record
+    }
+
+    async fn read_all(store: &FileStore) -> Vec<Option<Message>> {
+        let mut messages = Vec::new();
+        for offset in 0..4 {
+            messages.push(store.read(TOPIC, offset).await.unwrap());
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn segments_roll_over() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = FileStore::open_with(directory.path(), one_per_segment()).await.unwrap();
+        for (expected, message) in [b"zero", b"one_", b"two_"].iter().enumerate() {
+            assert_eq!(store.append(TOPIC, *message).await.unwrap(), expected as u64);
+        }
+        for base in 0..3 {
+            assert!(store.segment_path(TOPIC, base).exists());
+        }
+        let expected = vec![Some(b"zero".to_vec()), Some(b"one_".to_vec()), Some(b"two_".to_vec()), None];
+        assert_eq!(read_all(&store).await, expected);
+
+        // offsets carry on across segments after a restart
+        drop(store);
+        let store = FileStore::open_with(directory.path(), one_per_segment()).await.unwrap();
+        assert_eq!(read_all(&store).await, expected);
+        assert_eq!(store.append(TOPIC, b"three").await.unwrap(), 3);
+        assert!(store.segment_path(TOPIC, 3).exists());
+    }
+
+    #[tokio::test]
+    async fn torn_records_are_cut_off() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = FileStore::open(directory.path()).await.unwrap();
+        store.append(TOPIC, b"first").await.unwrap();
+        store.append(TOPIC, b"second").await.unwrap();
+        let path = store.segment_path(TOPIC, 0);
+        drop(store);
+
+        // a crash in the middle of the third append
+        let whole = std::fs::metadata(&path).unwrap().len();
+        let mut torn = std::fs::read(&path).unwrap();
+        torn.extend_from_slice(&record(b"third")[..6]);
+        std::fs::write(&path, torn).unwrap();
+
+        let store = FileStore::open(directory.path()).await.unwrap();
+        assert_eq!(store.read(TOPIC, 1).await.unwrap(), Some(b"second".to_vec()));
+        assert_eq!(store.read(TOPIC, 2).await.unwrap(), None);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), whole);
+        assert_eq!(store.append(TOPIC, b"third").await.unwrap(), 2);
+        assert_eq!(store.read(TOPIC, 2).await.unwrap(), Some(b"third".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn torn_size_prefixes_are_cut_off() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = FileStore::open(directory.path()).await.unwrap();
+        let path = store.segment_path(TOPIC, 0);
+        let mut torn = record(b"first");
+        torn.extend_from_slice(&[0, 0]);
+        std::fs::write(&path, &torn).unwrap();
+
+        assert_eq!(store.read(TOPIC, 0).await.unwrap(), Some(b"first".to_vec()));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), torn.len() as u64 - 2);
+    }
+
+    #[tokio::test]
+    async fn unsegmented_logs_are_migrated() {
+        let directory = tempfile::tempdir().unwrap();
+        let unsegmented = directory.path().join(format!("{}.log", file_name(TOPIC)));
+        let mut log = record(b"old");
+        log.extend(record(b"older"));
+        std::fs::write(&unsegmented, log).unwrap();
+
+        let store = FileStore::open(directory.path()).await.unwrap();
+        assert_eq!(store.read(TOPIC, 0).await.unwrap(), Some(b"old".to_vec()));
+        assert_eq!(store.read(TOPIC, 1).await.unwrap(), Some(b"older".to_vec()));
+        assert_eq!(store.append(TOPIC, b"new").await.unwrap(), 2);
+        assert!(!unsegmented.exists());
+        assert!(store.segment_path(TOPIC, 0).exists());
+    }
+
+    #[tokio::test]
+    async fn consumed_segments_are_deleted() {
+        let directory = tempfile::tempdir().unwrap();
+        let options = FileStoreOptions {
+            delete_consumed: true,
+            ..one_per_segment()
+        };
+        let store = FileStore::open_with(directory.path(), options).await.unwrap();
+        for message in [b"zero", b"one_", b"two_", b"thre"] {
+            store.append(TOPIC, message).await.unwrap();
+        }
+
+        // nothing goes while a consumer has yet to read it
+        store.commit(TOPIC, "slow", 0).await.unwrap();
+        store.commit(TOPIC, "fast", 3).await.unwrap();
+        assert_eq!(store.min_committed(TOPIC).await.unwrap(), Some(0));
+        assert_eq!(store.first_offset(TOPIC).await.unwrap(), 0);
+
+        store.commit(TOPIC, "slow", 1).await.unwrap();
+        assert_eq!(store.first_offset(TOPIC).await.unwrap(), 1);
+        assert!(!store.segment_path(TOPIC, 0).exists());
+        assert_eq!(store.read(TOPIC, 0).await.unwrap(), None);
+        assert_eq!(store.read(TOPIC, 1).await.unwrap(), Some(b"one_".to_vec()));
+
+        // the segment being appended to stays even once everything is read
+        store.commit(TOPIC, "slow", 4).await.unwrap();
+        assert_eq!(store.min_committed(TOPIC).await.unwrap(), Some(3));
+        assert_eq!(store.first_offset(TOPIC).await.unwrap(), 3);
+        store.commit(TOPIC, "fast", 4).await.unwrap();
+        assert_eq!(store.first_offset(TOPIC).await.unwrap(), 3);
+        assert!(store.segment_path(TOPIC, 3).exists());
+
+        // a consumer that fell behind the deleted segments starts at the first kept
+        assert_eq!(start_offset(&store, TOPIC, "late").await.unwrap(), 3);
+    }
+
+    async fn durable_messenger(directory: &Path) -> Messenger {
+        let messenger = Messenger::new();
+        let store = FileStore::open(directory).await.unwrap();
+        messenger.make_durable(TOPIC, Arc::new(store)).unwrap();
+        messenger
+    }
+
+    async fn next(subscription: &mut DurableSubscription) -> Delivery {
+        tokio::time::timeout(Duration::from_secs(5), subscription.next())
+            .await
+            .expect("a delivery")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn unacknowledged_messages_are_redelivered() {
+        let directory = tempfile::tempdir().unwrap();
+        let messenger = durable_messenger(directory.path()).await;
+        messenger.send(TOPIC, b"zero").await.unwrap();
+        messenger.send(TOPIC, b"one").await.unwrap();
+
+        let options = ConsumerOptions {
+            redelivery_timeout: Duration::from_millis(100),
+            max_in_flight: 1,
+        };
+        let mut subscription = messenger.consume(TOPIC, "worker", options).await.unwrap();
+        let first = next(&mut subscription).await;
+        assert_eq!((first.offset, first.redelivered), (0, false));
+
+        // with no room in flight, the only way on is the expired message
+        let started = Instant::now();
+        let again = next(&mut subscription).await;
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert_eq!((again.offset, again.message, again.redelivered), (0, b"zero".to_vec(), true));
+
+        subscription.ack(0).await.unwrap();
+        let second = next(&mut subscription).await;
+        assert_eq!((second.offset, second.redelivered), (1, false));
+
+        // a restarted consumer gets what it never acknowledged
+        drop(subscription);
+        let mut subscription = messenger.consume(TOPIC, "worker", options).await.unwrap();
+        let resumed = next(&mut subscription).await;
+        assert_eq!((resumed.offset, resumed.redelivered), (1, false));
+    }
+
+    #[tokio::test]
+    async fn out_of_order_acks_commit_once_the_gap_closes() {
+        let directory = tempfile::tempdir().unwrap();
+        let messenger = durable_messenger(directory.path()).await;
+        for message in [b"zero", b"one_", b"two_"] {
+            messenger.send(TOPIC, message).await.unwrap();
+        }
+        let mut subscription = messenger.consume(TOPIC, "worker", ConsumerOptions::default()).await.unwrap();
+        for expected in 0..3 {
+            assert_eq!(next(&mut subscription).await.offset, expected);
+        }
+
+        subscription.ack(2).await.unwrap();
+        assert_eq!(subscription.store.committed(TOPIC, "worker").await.unwrap(), 0);
+        subscription.ack(0).await.unwrap();
+        assert_eq!(subscription.store.committed(TOPIC, "worker").await.unwrap(), 1);
+        // twice, or never handed out: nothing happens
+        subscription.ack(0).await.unwrap();
+        subscription.ack(7).await.unwrap();
+        assert_eq!(subscription.store.committed(TOPIC, "worker").await.unwrap(), 1);
+        subscription.ack(1).await.unwrap();
+        assert_eq!(subscription.store.committed(TOPIC, "worker").await.unwrap(), 3);
+        assert!(subscription.acked.is_empty());
+        assert!(subscription.in_flight.is_empty());
+    }
+}
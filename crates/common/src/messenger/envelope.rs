@@ -0,0 +1,191 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// typed messages: a serde payload wrapped in an envelope that says who sent
+// it, when, what it is and which version of its schema it was written with.
+// envelope and payload are encoded with utils::serialization, so receivers
+// can tell a message of another type or schema version from a corrupt one.
+// both are written in the same format, bincode unless the sender asks for
+// another; receivers detect it from the format tag.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::domain::SubscriberId;
+use super::queue::Priority;
+use super::{Messenger, MessengerError};
+use crate::utils::serialization::{self, Format};
+use crate::utils::uid::Uid;
+
+// a payload type that can travel in an envelope. bump SCHEMA_VERSION on any
+// change that old receivers can't decode
+pub trait MessageSchema: Serialize + DeserializeOwned {
+    // stable name of the type on the bus, e.g. "zark.lifecycle"
+    const CONTENT_TYPE: &'static str;
+    const SCHEMA_VERSION: u32;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    // name of the module or component that sent the message
+    pub sender: String,
+    // milliseconds since the unix epoch
+    pub timestamp: u64,
+    pub content_type: String,
+    pub schema_version: u32,
+    // fresh for every message unless it continues an earlier exchange
+    pub correlation_id: String,
+    pub payload: Vec<u8>,
+}
+
+impl Envelope {
+    pub fn new<T: MessageSchema>(sender: &str, body: &T) -> Result<Self, MessengerError> {
+        Self::new_as(sender, body, Format::default())
+    }
+
+    pub fn new_as<T: MessageSchema>(sender: &str, body: &T, format: Format) -> Result<Self, MessengerError> {
+        let payload = serialization::encode(format, body).map_err(|e| MessengerError::EncodeError(e.to_string()))?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        Ok(Self {
+            sender: sender.to_string(),
+            timestamp,
+            content_type: T::CONTENT_TYPE.to_string(),
+            schema_version: T::SCHEMA_VERSION,
+            correlation_id: Uid::new().to_string(),
+            payload,
+        })
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: impl Into<String>) -> Self {
+        self.correlation_id = correlation_id.into();
+        self
+    }
+
+    // the format the payload was written in, and the envelope will be
+    pub fn format(&self) -> Format {
+        serialization::detect(&self.payload).unwrap_or_default()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MessengerError> {
+        serialization::encode(self.format(), self).map_err(|e| MessengerError::EncodeError(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MessengerError> {
+        serialization::deserialize(bytes)
+            .map_err(|e| MessengerError::DecodeError(format!("not a message envelope: {}", e)))
+    }
+
+    // decode the payload, checking it is a T written with T's schema version
+    pub fn decode<T: MessageSchema>(&self) -> Result<T, MessengerError> {
+        if self.content_type != T::CONTENT_TYPE {
+            return Err(MessengerError::ContentTypeMismatch {
+                expected: T::CONTENT_TYPE.to_string(),
+                found: self.content_type.clone(),
+            });
+        }
+        if self.schema_version != T::SCHEMA_VERSION {
+            return Err(MessengerError::SchemaVersionMismatch {
+                content_type: self.content_type.clone(),
+                expected: T::SCHEMA_VERSION,
+                found: self.schema_version,
+            });
+        }
+        serialization::deserialize(&self.payload).map_err(|e| {
+            MessengerError::DecodeError(format!("{} v{}: {}", self.content_type, self.schema_version, e))
+        })
+    }
+}
+
+// a decoded typed message and the envelope fields that came with it
+#[derive(Debug, Clone)]
+pub struct Received<T> {
+    pub sender: String,
+    pub timestamp: u64,
+    pub correlation_id: String,
+    pub body: T,
+}
+
+impl<T: MessageSchema> Received<T> {
+    fn open(bytes: &[u8]) -> Result<Self, MessengerError> {
+        let envelope = Envelope::from_bytes(bytes)?;
+        let body = envelope.decode()?;
+        Ok(Self {
+            sender: envelope.sender,
+            timestamp: envelope.timestamp,
+            correlation_id: envelope.correlation_id,
+            body,
+        })
+    }
+}
+
+impl Messenger {
+    // wrap a value in an envelope and send it
+    pub async fn send_typed<T: MessageSchema>(&self, topic: &str, sender: &str, body: &T) -> Result<bool, MessengerError> {
+        self.send_typed_with_priority(topic, sender, body, Priority::Data).await
+    }
+
+    pub async fn send_typed_with_priority<T: MessageSchema>(
+        &self,
+        topic: &str,
+        sender: &str,
+        body: &T,
+        priority: Priority,
+    ) -> Result<bool, MessengerError> {
+        let envelope = Envelope::new(sender, body)?;
+        self.send_with_priority(topic, &envelope.to_bytes()?, priority).await
+    }
+
+    // as send_typed, written in the given format
+    pub async fn send_typed_as<T: MessageSchema>(
+        &self,
+        topic: &str,
+        sender: &str,
+        body: &T,
+        format: Format,
+    ) -> Result<bool, MessengerError> {
+        let envelope = Envelope::new_as(sender, body, format)?;
+        self.send(topic, &envelope.to_bytes()?).await
+    }
+
+    pub async fn send_envelope(&self, topic: &str, envelope: &Envelope) -> Result<bool, MessengerError> {
+        self.send(topic, &envelope.to_bytes()?).await
+    }
+
+    // subscribe to typed messages. the callback gets every message on the
+    // topic, decoded, or the reason it couldn't be decoded as a T
+    pub async fn subscribe_typed<T, F>(&self, topic: &str, callback: F) -> Result<SubscriberId, MessengerError>
+    where
+        T: MessageSchema,
+        F: Fn(Result<Received<T>, MessengerError>) + Send + Sync + 'static,
+    {
+        self.subscribe(topic, Arc::new(move |message| callback(Received::open(&message))))
+            .await
+    }
+}
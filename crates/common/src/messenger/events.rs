@@ -0,0 +1,91 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// typed messages the core itself publishes
+
+use serde::{Deserialize, Serialize};
+
+use super::envelope::MessageSchema;
+use super::queue::OverflowPolicy;
+
+// a module or plugin changed state; sent on "module.<name>.<event>" and
+// "plugin.<name>.<event>", and for the core itself on "core.<event>"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LifecycleEvent {
+    // "module", "plugin" or "core"
+    pub component: String,
+    pub name: String,
+    // loaded, unloaded, started, stopped, ...
+    pub event: String,
+}
+
+impl LifecycleEvent {
+    pub fn module(name: &str, event: &str) -> Self {
+        Self {
+            component: "module".to_string(),
+            name: name.to_string(),
+            event: event.to_string(),
+        }
+    }
+
+    pub fn core(event: &str) -> Self {
+        Self {
+            component: "core".to_string(),
+            name: "zark-core".to_string(),
+            event: event.to_string(),
+        }
+    }
+
+    pub fn plugin(name: &str, event: &str) -> Self {
+        Self {
+            component: "plugin".to_string(),
+            name: name.to_string(),
+            event: event.to_string(),
+        }
+    }
+}
+
+impl MessageSchema for LifecycleEvent {
+    const CONTENT_TYPE: &'static str = "zark.lifecycle";
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+// a message a full subscriber queue discarded, sent on "$dead_letter"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    // topic the message was sent to
+    pub topic: String,
+    pub subscriber_id: String,
+    // pattern the subscriber subscribed with
+    pub subscription: String,
+    pub overflow: OverflowPolicy,
+    // messages this subscriber has lost so far, this one included
+    pub dropped: u64,
+    pub message: Vec<u8>,
+}
+
+impl MessageSchema for DeadLetter {
+    const CONTENT_TYPE: &'static str = "zark.dead_letter";
+    const SCHEMA_VERSION: u32 = 1;
+}
@@ -23,14 +23,11 @@
 // Authors: I. Zeqiri, E. Gjergji
 
 use std::ffi::{CStr, CString};
-use std::ptr;
-use crate::utils::uid::Uid;
-use crate::messenger::{Topic, SubscriberId, Message, Callback};
-
-// define a type alias for a raw pointer to a c_void
-type RawPointer = *mut std::ffi::c_void;
-
-// define a type alias for a callback function pointer
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use tokio::sync::Mutex;
+use crate::messenger::{SubscriberId, Callback, Message, MessengerError, Priority, SubscriptionOptions, Topic};
+use crate::messenger::backend::MessengerBackend;
 
 // ffi module: handles low-level FFI interactions with the messenger library
 
@@ -142,3 +139,49 @@ impl Drop for FFIMessenger {
 
 unsafe impl Send for FFIMessenger {}
 unsafe impl Sync for FFIMessenger {}
+
+// backend that hands every call to the external zark_messenger library, one
+// call at a time
+pub struct FFIBackend {
+    messenger: Mutex<FFIMessenger>,
+}
+
+impl FFIBackend {
+    pub fn new<P: AsRef<std::ffi::OsStr>>(library_path: P) -> Result<Self, FFIError> {
+        Ok(Self {
+            messenger: Mutex::new(FFIMessenger::new(library_path)?),
+        })
+    }
+}
+
+#[async_trait]
+impl MessengerBackend for FFIBackend {
+    // the library has a single delivery path
+    async fn send(&self, topic: &str, message: &[u8], _priority: Priority) -> Result<bool, MessengerError> {
+        self.messenger.lock().await.send(topic, message)
+            .map_err(|e| MessengerError::SendError(e.to_string()))
+    }
+
+    async fn subscribe(&self, topic: &str, callback: Callback, options: SubscriptionOptions) -> Result<SubscriberId, MessengerError> {
+        // the library queues messages its own way
+        if options != SubscriptionOptions::default() {
+            return Err(MessengerError::SubscribeError(
+                "the messenger library does not support subscription options".to_string(),
+            ));
+        }
+        self.messenger.lock().await.subscribe(topic, callback)
+            .map_err(|e| MessengerError::SubscribeError(e.to_string()))
+    }
+
+    async fn subscribe_stream(&self, _topic: &str, _options: SubscriptionOptions) -> Result<(SubscriberId, BoxStream<'static, (Topic, Message)>), MessengerError> {
+        Err(MessengerError::SubscribeError(
+            "the messenger library only supports callback subscriptions".to_string(),
+        ))
+    }
+
+    async fn unsubscribe(&self, topic: &str, subscriber_id: &SubscriberId) -> Result<(), MessengerError> {
+        self.messenger.lock().await.unsubscribe(topic, subscriber_id)
+            .map_err(|e| MessengerError::UnsubscribeError(e.to_string()))?;
+        Ok(())
+    }
+}
@@ -1,15 +1,38 @@
 // messenger module: provides a simple interface for messaging functionality
 
+mod backend;
+#[cfg(unix)]
+mod bridge;
 mod ffi;
 mod domain;
+mod durable;
+mod envelope;
+mod events;
+mod native;
+mod queue;
 mod repository;
+mod rpc;
+mod stream;
+mod tap;
+mod topic;
 
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use thiserror::Error;
+use serde::Serialize;
 
+pub use backend::MessengerBackend;
+#[cfg(unix)]
+pub use bridge::{BridgeClient, BridgeServer, BridgeSubscription, ClientFrame, ServerFrame, MAX_FRAME_SIZE};
 pub use domain::{Topic, SubscriberId, Message, Callback};
-use repository::SubscriptionRepository;
+pub use durable::{ConsumerOptions, Delivery, DurableStore, DurableSubscription, FileStore, FileStoreOptions, SyncPolicy};
+pub use envelope::{Envelope, MessageSchema, Received};
+pub use events::{DeadLetter, LifecycleEvent};
+pub use ffi::FFIBackend;
+pub use native::NativeBackend;
+pub use queue::{LaneStats, OverflowPolicy, Priority, SubscriptionOptions, DEFAULT_CAPACITY};
+pub use stream::MessageStream;
+pub use tap::{Pace, RecordedMessage, Recorder, Replayer};
+pub use topic::{topic_segment, DEAD_LETTER_TOPIC};
 
 #[derive(Error, Debug)]
 pub enum MessengerError {
@@ -23,66 +46,118 @@ pub enum MessengerError {
     UnsubscribeError(String),
     #[error("Invalid subscriber ID: {0}")]
     InvalidSubscriberId(String),
+    #[error("Invalid topic: {0}")]
+    InvalidTopic(String),
+    #[error("No responder for {0}")]
+    NoResponder(String),
+    #[error("Request to {0} timed out after {1:?}")]
+    Timeout(String, std::time::Duration),
+    #[error("Responder failed: {0}")]
+    RemoteError(String),
+    #[error("Invalid reply: {0}")]
+    InvalidReply(String),
+    #[error("Failed to encode message: {0}")]
+    EncodeError(String),
+    #[error("Failed to decode message: {0}")]
+    DecodeError(String),
+    #[error("Expected a {expected} message, got {found}")]
+    ContentTypeMismatch { expected: String, found: String },
+    #[error("Expected {content_type} schema version {expected}, got version {found}")]
+    SchemaVersionMismatch { content_type: String, expected: u32, found: u32 },
+    #[error("Queue full for {1} subscriber(s) of {0}")]
+    QueueFull(String, usize),
+    #[error("Bridge error: {0}")]
+    BridgeError(String),
+    #[error("Recording error: {0}")]
+    RecordingError(String),
+    #[error("Durable store error: {0}")]
+    StoreError(String),
+}
+
+// a subscription's queue as seen by its backend
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionStats {
+    pub id: String,
+    pub topic: String,
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+    // one entry per priority, in delivery order
+    pub lanes: Vec<LaneStats>,
 }
 
 pub struct Messenger {
-    inner: Arc<Mutex<ffi::FFIMessenger>>,
-    repository: Arc<SubscriptionRepository>,
+    backend: Arc<dyn MessengerBackend>,
+    durable: durable::DurableTopics,
+    responders: rpc::Responders,
 }
 
 impl Messenger {
     
-    // create a new messenger instance
-    pub async fn new(library_path: &str) -> Result<Self, MessengerError> {
-        let ffi_messenger = ffi::FFIMessenger::new(library_path)
-            .map_err(|e| MessengerError::InitializationError(e.to_string()))?;
-        Ok(Self {
-            inner: Arc::new(Mutex::new(ffi_messenger)),
-            repository: Arc::new(SubscriptionRepository::new()),
-        })
+    // create a messenger on the in-process native backend
+    pub fn new() -> Self {
+        Self::with_backend(Arc::new(NativeBackend::new()))
     }
 
-    // send a message to a specific topic
-    pub async fn send(&self, topic: &str, message: &[u8]) -> Result<bool, MessengerError> {
-        let messenger = self.inner.lock().await;
-        messenger.send(topic, message)
-            .map_err(|e| MessengerError::SendError(e.to_string()))
+    // create a messenger backed by an external zark_messenger library
+    pub fn with_library(library_path: &str) -> Result<Self, MessengerError> {
+        let backend = FFIBackend::new(library_path)
+            .map_err(|e| MessengerError::InitializationError(e.to_string()))?;
+        Ok(Self::with_backend(Arc::new(backend)))
     }
 
-    // subscribe to a topic and receive messages
-    pub async fn subscribe(&self, topic: &str, callback: Callback) -> Result<SubscriberId, MessengerError> {
-        let messenger = self.inner.lock().await;
-        let subscriber_id = messenger.subscribe(topic, callback.clone())
-            .map_err(|e| MessengerError::SubscribeError(e.to_string()))?;
+    pub fn with_backend(backend: Arc<dyn MessengerBackend>) -> Self {
+        Self {
+            backend,
+            durable: durable::DurableTopics::default(),
+            responders: rpc::Responders::default(),
+        }
+    }
 
-        let (tx, mut rx) = tokio::sync::mpsc::channel(100); // Create a channel
+    // send a message to a specific topic. on a durable topic it is stored
+    // first and counts as delivered once it is
+    pub async fn send(&self, topic: &str, message: &[u8]) -> Result<bool, MessengerError> {
+        self.send_with_priority(topic, message, Priority::Data).await
+    }
 
-        let subscription = domain::Subscription {
-            subscriber: domain::Subscriber {
-                id: subscriber_id.clone(),
-                topic: topic.to_string(),
-                sender: tx, // Add the sender
-            },
-            callback,
-        };
+    // control messages overtake data messages still queued for a subscriber
+    pub async fn send_with_priority(&self, topic: &str, message: &[u8], priority: Priority) -> Result<bool, MessengerError> {
+        topic::validate_topic(topic)?;
+        let stored = self.durable.append(topic, message).await?;
+        let delivered = self.backend.send(topic, message, priority).await?;
+        Ok(stored || delivered)
+    }
 
-        self.repository.add(subscription).await;
+    // subscribe to a topic, or to every topic matching a wildcard pattern,
+    // and receive messages
+    pub async fn subscribe(&self, topic: &str, callback: Callback) -> Result<SubscriberId, MessengerError> {
+        self.subscribe_with(topic, callback, SubscriptionOptions::default()).await
+    }
 
-        Ok(subscriber_id)
+    // subscribe with a queue capacity and a policy for when that queue is full
+    pub async fn subscribe_with(&self, topic: &str, callback: Callback, options: SubscriptionOptions) -> Result<SubscriberId, MessengerError> {
+        topic::validate_pattern(topic)?;
+        options.validate()?;
+        self.backend.subscribe(topic, callback, options).await
     }
 
     // unsubscribe from a topic
     pub async fn unsubscribe(&self, topic: &str, subscriber_id: &SubscriberId) -> Result<(), MessengerError> {
-        let messenger = self.inner.lock().await;
-        messenger.unsubscribe(topic, subscriber_id)
-            .map_err(|e| MessengerError::UnsubscribeError(e.to_string()))?;
+        self.responders.remove(subscriber_id);
+        self.backend.unsubscribe(topic, subscriber_id).await
+    }
 
-        self.repository.remove(&topic.to_string(), subscriber_id).await;
-        Ok(())
+    pub async fn stats(&self) -> Vec<SubscriptionStats> {
+        self.backend.stats().await
+    }
+}
+
+impl Default for Messenger {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 // helper function to convert string to subscriber id
 pub fn string_to_subscriber_id(id: &str) -> Option<SubscriberId> {
     SubscriberId::from_string(id)
-}
\ No newline at end of file
+}
@@ -0,0 +1,165 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// native backend: an in-process bus with no external library. every
+// subscription gets a bounded queue read through a stream; callback
+// subscriptions are a task draining that stream into the callback, so a slow
+// callback never runs on the sender's task.
+// what happens when a queue is full is up to the subscription; whatever it
+// discards is counted and republished on "$dead_letter".
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+
+use super::backend::MessengerBackend;
+use super::domain::{Callback, Message, Subscriber, SubscriberId, Subscription, Topic};
+use super::envelope::Envelope;
+use super::events::DeadLetter;
+use super::queue::{OverflowPolicy, Priority, Push, SubscriberQueue, SubscriptionOptions};
+use super::repository::SubscriptionRepository;
+use super::topic::DEAD_LETTER_TOPIC;
+use super::{MessengerError, SubscriptionStats};
+
+pub struct NativeBackend {
+    repository: SubscriptionRepository,
+}
+
+impl NativeBackend {
+    pub fn new() -> Self {
+        Self {
+            repository: SubscriptionRepository::new(),
+        }
+    }
+
+    async fn dead_letter(&self, subscriber: &Subscriber, topic: String, message: Message) {
+        // a full dead-letter subscriber only gets its drop counted, otherwise
+        // one slow consumer there would feed itself
+        if topic == DEAD_LETTER_TOPIC {
+            return;
+        }
+        let letter = DeadLetter {
+            topic,
+            subscriber_id: subscriber.id.to_string(),
+            subscription: subscriber.topic.clone(),
+            overflow: subscriber.queue.options().overflow,
+            dropped: subscriber.queue.dropped(),
+            message,
+        };
+        if let Ok(letter) = Envelope::new("messenger", &letter).and_then(|envelope| envelope.to_bytes()) {
+            let _ = self.send(DEAD_LETTER_TOPIC, &letter, Priority::Data).await;
+        }
+    }
+}
+
+impl Default for NativeBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MessengerBackend for NativeBackend {
+    async fn send(&self, topic: &str, message: &[u8], priority: Priority) -> Result<bool, MessengerError> {
+        let subscriptions = self.repository.get_subscriptions(&topic.to_string()).await;
+        let delivered = !subscriptions.is_empty();
+        let mut rejected = 0;
+        for subscription in subscriptions {
+            let subscriber = &subscription.subscriber;
+            // a closed queue means the subscriber went away mid-send
+            if let Push::Overflow(dropped_topic, dropped) = subscriber.queue.push(topic, message.to_vec(), priority).await {
+                if subscriber.queue.options().overflow == OverflowPolicy::Error {
+                    rejected += 1;
+                }
+                self.dead_letter(subscriber, dropped_topic, dropped).await;
+            }
+        }
+        if rejected > 0 {
+            return Err(MessengerError::QueueFull(topic.to_string(), rejected));
+        }
+        Ok(delivered)
+    }
+
+    async fn subscribe(&self, topic: &str, callback: Callback, options: SubscriptionOptions) -> Result<SubscriberId, MessengerError> {
+        let (id, mut messages) = self.subscribe_stream(topic, options).await?;
+        tokio::spawn(async move {
+            while let Some((_, message)) = messages.next().await {
+                callback(message);
+            }
+        });
+        Ok(id)
+    }
+
+    async fn subscribe_stream(&self, topic: &str, options: SubscriptionOptions) -> Result<(SubscriberId, BoxStream<'static, (Topic, Message)>), MessengerError> {
+        let queue = Arc::new(SubscriberQueue::new(options));
+        let id = SubscriberId::new();
+
+        // ends once unsubscribe closes the queue and it has drained
+        let messages = stream::unfold(queue.clone(), |queue| async move {
+            let next = queue.recv().await?;
+            Some((next, queue))
+        });
+
+        self.repository
+            .add(Subscription {
+                subscriber: Subscriber {
+                    id,
+                    topic: topic.to_string(),
+                    queue,
+                },
+            })
+            .await;
+        Ok((id, messages.boxed()))
+    }
+
+    async fn unsubscribe(&self, topic: &str, subscriber_id: &SubscriberId) -> Result<(), MessengerError> {
+        match self.repository.remove(&topic.to_string(), subscriber_id).await {
+            Some(subscription) => {
+                subscription.subscriber.queue.close();
+                Ok(())
+            }
+            None => Err(MessengerError::InvalidSubscriberId(subscriber_id.to_string())),
+        }
+    }
+
+    async fn stats(&self) -> Vec<SubscriptionStats> {
+        self.repository
+            .all()
+            .await
+            .into_iter()
+            .map(|subscription| {
+                let subscriber = subscription.subscriber;
+                let options = subscriber.queue.options();
+                SubscriptionStats {
+                    id: subscriber.id.to_string(),
+                    topic: subscriber.topic,
+                    capacity: options.capacity,
+                    overflow: options.overflow,
+                    lanes: subscriber.queue.stats(),
+                }
+            })
+            .collect()
+    }
+}
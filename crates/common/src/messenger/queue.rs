@@ -0,0 +1,259 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// bounded per-subscription queues. an mpsc channel can't evict from the
+// sending side, which drop-oldest needs, so this keeps the messages in
+// deques and tracks free slots with semaphores. every priority has its own
+// lane, slots and counters: a full data lane never holds up a control
+// message, and control messages are handed out before queued data.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, Semaphore, TryAcquireError};
+
+use super::domain::{Message, Topic};
+use super::MessengerError;
+
+// messages queued per subscriber unless the subscription asks otherwise
+pub const DEFAULT_CAPACITY: usize = 100;
+
+// what a send does when a subscriber's queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    // wait for the subscriber to make room; nothing is lost
+    #[default]
+    Block,
+    // evict the oldest queued message to make room for the new one
+    DropOldest,
+    // discard the new message
+    DropNewest,
+    // discard the new message and fail the send
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriptionOptions {
+    // per priority lane
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+// which lane a message travels in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Priority {
+    // lifecycle and control messages: start, stop, reload, shutdown
+    Control,
+    // everything else, inspection events included
+    #[default]
+    Data,
+}
+
+impl Priority {
+    // in delivery order
+    pub const ALL: [Priority; 2] = [Priority::Control, Priority::Data];
+
+    fn lane(self) -> usize {
+        self as usize
+    }
+}
+
+impl SubscriptionOptions {
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Self {
+        Self { capacity, overflow }
+    }
+
+    pub fn validate(&self) -> Result<(), MessengerError> {
+        if self.capacity == 0 {
+            return Err(MessengerError::SubscribeError("queue capacity must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Default for SubscriptionOptions {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY, OverflowPolicy::Block)
+    }
+}
+
+pub enum Push {
+    Queued,
+    // the lane was full; carries the message that was discarded, which is
+    // the evicted one under drop-oldest, and the topic it was sent to
+    Overflow(Topic, Message),
+    // the subscriber is gone
+    Closed,
+}
+
+// one lane's counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LaneStats {
+    pub priority: Priority,
+    pub queued: usize,
+    pub delivered: u64,
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+}
+
+pub struct SubscriberQueue {
+    // one deque per priority, in Priority::ALL order
+    lanes: Mutex<[VecDeque<(Topic, Message)>; 2]>,
+    // one permit per free slot, per lane
+    slots: [Semaphore; 2],
+    counters: [Counters; 2],
+    ready: Notify,
+    closed: AtomicBool,
+    options: SubscriptionOptions,
+}
+
+impl SubscriberQueue {
+    pub fn new(options: SubscriptionOptions) -> Self {
+        Self {
+            lanes: Mutex::new(Default::default()),
+            slots: [Semaphore::new(options.capacity), Semaphore::new(options.capacity)],
+            counters: Default::default(),
+            ready: Notify::new(),
+            closed: AtomicBool::new(false),
+            options,
+        }
+    }
+
+    pub fn options(&self) -> SubscriptionOptions {
+        self.options
+    }
+
+    pub fn stats(&self) -> Vec<LaneStats> {
+        let lanes = self.lanes.lock();
+        Priority::ALL
+            .iter()
+            .map(|priority| {
+                let counters = &self.counters[priority.lane()];
+                LaneStats {
+                    priority: *priority,
+                    queued: lanes[priority.lane()].len(),
+                    delivered: counters.delivered.load(Ordering::Relaxed),
+                    dropped: counters.dropped.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+
+    // messages discarded so far, over all lanes
+    pub fn dropped(&self) -> u64 {
+        self.counters
+            .iter()
+            .map(|counters| counters.dropped.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub async fn push(&self, topic: &str, message: Message, priority: Priority) -> Push {
+        let lane = priority.lane();
+        if self.options.overflow == OverflowPolicy::Block {
+            return match self.slots[lane].acquire().await {
+                Ok(slot) => {
+                    slot.forget();
+                    self.enqueue(lane, topic, message)
+                }
+                Err(_) => Push::Closed,
+            };
+        }
+
+        loop {
+            match self.slots[lane].try_acquire() {
+                Ok(slot) => {
+                    slot.forget();
+                    return self.enqueue(lane, topic, message);
+                }
+                Err(TryAcquireError::Closed) => return Push::Closed,
+                Err(TryAcquireError::NoPermits) => {}
+            }
+            if self.options.overflow != OverflowPolicy::DropOldest {
+                return self.overflow(lane, topic.to_string(), message);
+            }
+            {
+                let mut lanes = self.lanes.lock();
+                if let Some((evicted_topic, oldest)) = lanes[lane].pop_front() {
+                    lanes[lane].push_back((topic.to_string(), message));
+                    drop(lanes);
+                    return self.overflow(lane, evicted_topic, oldest);
+                }
+            }
+            // the receiver emptied the lane but hasn't released its slots yet
+            tokio::task::yield_now().await;
+        }
+    }
+
+    // next message, highest priority first, or None once the queue is closed
+    // and drained
+    pub async fn recv(&self) -> Option<(Topic, Message)> {
+        loop {
+            let next = {
+                let mut lanes = self.lanes.lock();
+                Priority::ALL
+                    .iter()
+                    .find_map(|priority| lanes[priority.lane()].pop_front().map(|next| (priority.lane(), next)))
+            };
+            if let Some((lane, next)) = next {
+                self.slots[lane].add_permits(1);
+                self.counters[lane].delivered.fetch_add(1, Ordering::Relaxed);
+                return Some(next);
+            }
+            if self.closed.load(Ordering::Acquire) {
+                return None;
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    // refuse further messages and wake blocked senders; whatever is already
+    // queued is still delivered
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        for slots in &self.slots {
+            slots.close();
+        }
+        self.ready.notify_one();
+    }
+
+    fn enqueue(&self, lane: usize, topic: &str, message: Message) -> Push {
+        self.lanes.lock()[lane].push_back((topic.to_string(), message));
+        self.ready.notify_one();
+        Push::Queued
+    }
+
+    fn overflow(&self, lane: usize, topic: Topic, message: Message) -> Push {
+        self.counters[lane].dropped.fetch_add(1, Ordering::Relaxed);
+        Push::Overflow(topic, message)
+    }
+}
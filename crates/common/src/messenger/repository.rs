@@ -23,38 +23,117 @@
 // Authors: I. Zeqiri, E. Gjergji
 
 use super::domain::{Topic, SubscriberId, Subscription};
+use super::topic::{is_reply_topic, segments, MULTI_WILDCARD, SINGLE_WILDCARD};
 use std::collections::HashMap;
 use tokio::sync::RwLock;
 
+// one level of the topic trie. subscriptions sit on the node their pattern
+// ends at; wildcard segments are stored as ordinary "*" and "#" children
+#[derive(Default)]
+struct TopicNode {
+    children: HashMap<String, TopicNode>,
+    subscriptions: Vec<Subscription>,
+}
+
+impl TopicNode {
+    fn is_empty(&self) -> bool {
+        self.children.is_empty() && self.subscriptions.is_empty()
+    }
+
+    fn collect_all(&self, all: &mut Vec<Subscription>) {
+        all.extend(self.subscriptions.iter().cloned());
+        for child in self.children.values() {
+            child.collect_all(all);
+        }
+    }
+
+    // collect the subscriptions whose pattern matches the remaining segments
+    fn collect(&self, remaining: &[&str], matches: &mut Vec<Subscription>) {
+        if let Some(rest) = self.children.get(MULTI_WILDCARD) {
+            matches.extend(rest.subscriptions.iter().cloned());
+        }
+        let Some((segment, remaining)) = remaining.split_first() else {
+            matches.extend(self.subscriptions.iter().cloned());
+            return;
+        };
+        if let Some(child) = self.children.get(*segment) {
+            child.collect(remaining, matches);
+        }
+        if let Some(child) = self.children.get(SINGLE_WILDCARD) {
+            child.collect(remaining, matches);
+        }
+    }
+
+    // like collect, but only following the topic's own segments
+    fn collect_exact(&self, remaining: &[&str], matches: &mut Vec<Subscription>) {
+        match remaining.split_first() {
+            Some((segment, remaining)) => {
+                if let Some(child) = self.children.get(*segment) {
+                    child.collect_exact(remaining, matches);
+                }
+            }
+            None => matches.extend(self.subscriptions.iter().cloned()),
+        }
+    }
+
+    // remove a subscription from the node at the end of the pattern, pruning
+    // nodes left empty on the way back up
+    fn remove(&mut self, pattern: &[&str], subscriber_id: &SubscriberId) -> Option<Subscription> {
+        let Some((segment, pattern)) = pattern.split_first() else {
+            let index = self.subscriptions.iter().position(|sub| sub.subscriber.id == *subscriber_id)?;
+            return Some(self.subscriptions.remove(index));
+        };
+        let child = self.children.get_mut(*segment)?;
+        let removed = child.remove(pattern, subscriber_id);
+        if child.is_empty() {
+            self.children.remove(*segment);
+        }
+        removed
+    }
+}
+
 pub struct SubscriptionRepository {
-    subscriptions: RwLock<HashMap<Topic, Vec<Subscription>>>,
+    subscriptions: RwLock<TopicNode>,
 }
 
 impl SubscriptionRepository {
     pub fn new() -> Self {
         Self {
-            subscriptions: RwLock::new(HashMap::new()),
+            subscriptions: RwLock::new(TopicNode::default()),
         }
     }
 
     pub async fn add(&self, subscription: Subscription) {
-        let mut subs = self.subscriptions.write().await;
-        subs.entry(subscription.subscriber.topic.clone())
-            .or_insert_with(Vec::new)
-            .push(subscription);
+        let mut root = self.subscriptions.write().await;
+        let node = segments(&subscription.subscriber.topic)
+            .fold(&mut *root, |node, segment| node.children.entry(segment.to_string()).or_default());
+        node.subscriptions.push(subscription);
     }
 
-    pub async fn remove(&self, topic: &Topic, subscriber_id: &SubscriberId) {
-        let mut subs = self.subscriptions.write().await;
-        if let Some(topic_subs) = subs.get_mut(topic) {
-            topic_subs.retain(|sub| sub.subscriber.id != *subscriber_id);
-        }
+    // returns the subscription if it existed
+    pub async fn remove(&self, topic: &Topic, subscriber_id: &SubscriberId) -> Option<Subscription> {
+        let pattern: Vec<&str> = segments(topic).collect();
+        self.subscriptions.write().await.remove(&pattern, subscriber_id)
     }
 
+    // every subscription whose topic or pattern matches the given topic.
+    // reply topics skip wildcard patterns
     pub async fn get_subscriptions(&self, topic: &Topic) -> Vec<Subscription> {
-        let subs = self.subscriptions.read().await;
-        subs.get(topic)
-            .map(|subscriptions| subscriptions.iter().cloned().collect())
-            .unwrap_or_default()
+        let reply = is_reply_topic(topic);
+        let topic: Vec<&str> = segments(topic).collect();
+        let mut matches = Vec::new();
+        let root = self.subscriptions.read().await;
+        if reply {
+            root.collect_exact(&topic, &mut matches);
+        } else {
+            root.collect(&topic, &mut matches);
+        }
+        matches
     }
-}
\ No newline at end of file
+
+    pub async fn all(&self) -> Vec<Subscription> {
+        let mut all = Vec::new();
+        self.subscriptions.read().await.collect_all(&mut all);
+        all
+    }
+}
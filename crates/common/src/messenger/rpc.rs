@@ -0,0 +1,181 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// request/reply over the bus. a request travels to its topic wrapped with a
+// correlation id and the topic to answer on; the requester subscribes to that
+// topic for the duration of the call. responders are ordinary subscribers
+// that run a handler and send its result back, so rpc works on any backend.
+// the messenger also keeps their patterns, so a topic that only has
+// observers (a "#" recorder, a bridge client) still counts as unanswered.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use super::domain::{Message, SubscriberId};
+use super::topic::{self, REPLY_TOPIC_PREFIX};
+use super::{Messenger, MessengerError};
+use crate::utils::serialization;
+use crate::utils::uid::Uid;
+
+// the pattern of every responder, by its subscription id
+#[derive(Default)]
+pub(super) struct Responders {
+    patterns: Mutex<HashMap<SubscriberId, String>>,
+}
+
+impl Responders {
+    fn add(&self, id: SubscriberId, pattern: &str) {
+        self.patterns.lock().unwrap_or_else(|e| e.into_inner()).insert(id, pattern.to_string());
+    }
+
+    pub(super) fn remove(&self, id: &SubscriberId) {
+        self.patterns.lock().unwrap_or_else(|e| e.into_inner()).remove(id);
+    }
+
+    fn answers(&self, topic: &str) -> bool {
+        let patterns = self.patterns.lock().unwrap_or_else(|e| e.into_inner());
+        patterns.values().any(|pattern| topic::matches(pattern, topic))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RpcRequest {
+    correlation_id: String,
+    reply_to: String,
+    payload: Message,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RpcReply {
+    correlation_id: String,
+    // the handler's error message when it failed
+    result: Result<Message, String>,
+}
+
+impl Messenger {
+    // send a request to the responders of a topic and wait for the first
+    // reply. fails with NoResponder when nobody listens on the topic, Timeout
+    // when no reply arrives in time and RemoteError when the responder failed
+    pub async fn request(&self, topic: &str, payload: &[u8], timeout: Duration) -> Result<Message, MessengerError> {
+        let correlation_id = Uid::new().to_string();
+        let reply_to = format!("{}.{}", REPLY_TOPIC_PREFIX, correlation_id);
+
+        // replies for a request with correlation id X are sent to "_reply.X"
+        // only the first reply is taken, later ones (from other responders) are dropped
+        let (reply_tx, reply_rx) = oneshot::channel::<Message>();
+        let reply_tx = Mutex::new(Some(reply_tx));
+        let subscriber_id = self
+            .subscribe(
+                &reply_to,
+                Arc::new(move |message| {
+                    if let Some(reply_tx) = reply_tx.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                        let _ = reply_tx.send(message);
+                    }
+                }),
+            )
+            .await?;
+
+        let result = self.exchange(topic, payload, &correlation_id, &reply_to, reply_rx, timeout).await;
+        let _ = self.unsubscribe(&reply_to, &subscriber_id).await;
+        result
+    }
+
+    async fn exchange(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        correlation_id: &str,
+        reply_to: &str,
+        reply_rx: oneshot::Receiver<Message>,
+        timeout: Duration,
+    ) -> Result<Message, MessengerError> {
+        let request = RpcRequest {
+            correlation_id: correlation_id.to_string(),
+            reply_to: reply_to.to_string(),
+            payload: payload.to_vec(),
+        };
+        let request = serialization::serialize(&request).map_err(|e| MessengerError::SendError(e.to_string()))?;
+        if !self.responders.answers(topic) {
+            return Err(MessengerError::NoResponder(topic.to_string()));
+        }
+        self.send(topic, &request).await?;
+
+        let reply = match tokio::time::timeout(timeout, reply_rx).await {
+            Ok(Ok(reply)) => reply,
+            // the sender only goes away with the subscription, i.e. never before the timeout
+            Ok(Err(_)) | Err(_) => return Err(MessengerError::Timeout(topic.to_string(), timeout)),
+        };
+        let reply: RpcReply =
+            serialization::deserialize(&reply).map_err(|e| MessengerError::InvalidReply(e.to_string()))?;
+        if reply.correlation_id != correlation_id {
+            return Err(MessengerError::InvalidReply(format!(
+                "expected correlation id {}, got {}",
+                correlation_id, reply.correlation_id
+            )));
+        }
+        reply.result.map_err(MessengerError::RemoteError)
+    }
+
+    // answer requests sent to a topic (or pattern) with the handler's result.
+    // each request is handled in its own task; messages on the topic that
+    // aren't requests are ignored. unsubscribe with the returned id to stop
+    pub async fn respond<F, Fut>(self: &Arc<Self>, topic: &str, handler: F) -> Result<SubscriberId, MessengerError>
+    where
+        F: Fn(Message) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Message, String>> + Send + 'static,
+    {
+        // weak, so a responder doesn't keep its own messenger alive
+        let messenger: Weak<Messenger> = Arc::downgrade(self);
+        let handler = Arc::new(handler);
+        let id = self.subscribe(
+            topic,
+            Arc::new(move |message| {
+                let Ok(request) = serialization::deserialize::<RpcRequest>(&message) else {
+                    return;
+                };
+                let messenger = messenger.clone();
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let result = handler(request.payload).await;
+                    let reply = RpcReply {
+                        correlation_id: request.correlation_id,
+                        result,
+                    };
+                    let reply = serialization::serialize(&reply).ok();
+                    if let (Some(messenger), Some(reply)) = (messenger.upgrade(), reply) {
+                        let _ = messenger.send(&request.reply_to, &reply).await;
+                    }
+                });
+            }),
+        )
+        .await?;
+        self.responders.add(id, topic);
+        Ok(id)
+    }
+}
@@ -0,0 +1,113 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// pull-based subscriptions: `while let Some(message) = stream.next().await`
+// instead of a callback. dropping the stream unsubscribes.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::stream::{BoxStream, Stream, StreamExt};
+
+use super::backend::MessengerBackend;
+use super::domain::{Message, SubscriberId, Topic};
+use super::queue::SubscriptionOptions;
+use super::{topic, Messenger, MessengerError};
+
+pub struct MessageStream {
+    id: SubscriberId,
+    topic: String,
+    messages: BoxStream<'static, (Topic, Message)>,
+    // taken once the subscription has been removed
+    backend: Option<Arc<dyn MessengerBackend>>,
+}
+
+impl MessageStream {
+    pub fn id(&self) -> &SubscriberId {
+        &self.id
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    // the next message and the topic it was sent to, which tells apart
+    // messages matched by a wildcard pattern
+    pub async fn next_with_topic(&mut self) -> Option<(Topic, Message)> {
+        self.messages.next().await
+    }
+
+    // unsubscribe now rather than in the background on drop
+    pub async fn unsubscribe(mut self) -> Result<(), MessengerError> {
+        match self.backend.take() {
+            Some(backend) => backend.unsubscribe(&self.topic, &self.id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Stream for MessageStream {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        self.messages
+            .poll_next_unpin(cx)
+            .map(|next| next.map(|(_, message)| message))
+    }
+}
+
+impl Drop for MessageStream {
+    fn drop(&mut self) {
+        let Some(backend) = self.backend.take() else {
+            return;
+        };
+        // without a runtime there is nothing left to deliver to us anyway
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let topic = std::mem::take(&mut self.topic);
+            let id = self.id;
+            runtime.spawn(async move {
+                let _ = backend.unsubscribe(&topic, &id).await;
+            });
+        }
+    }
+}
+
+impl Messenger {
+    pub async fn subscribe_stream(&self, topic: &str) -> Result<MessageStream, MessengerError> {
+        self.subscribe_stream_with(topic, SubscriptionOptions::default()).await
+    }
+
+    pub async fn subscribe_stream_with(&self, topic: &str, options: SubscriptionOptions) -> Result<MessageStream, MessengerError> {
+        topic::validate_pattern(topic)?;
+        options.validate()?;
+        let (id, messages) = self.backend.subscribe_stream(topic, options).await?;
+        Ok(MessageStream {
+            id,
+            topic: topic.to_string(),
+            messages,
+            backend: Some(self.backend.clone()),
+        })
+    }
+}
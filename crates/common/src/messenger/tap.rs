@@ -0,0 +1,307 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// message tap: record what goes over the bus to a file and publish it again
+// later, for debugging module interactions and for repeatable tests.
+//
+// a recording starts with the 8-byte header "ZBUSREC" + format version, then
+// one record per message: a 4-byte big-endian length followed by a
+// RecordedMessage encoded with utils::serialization, in bincode unless the
+// recorder was started with another format.
+
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use super::backend::MessengerBackend;
+use super::domain::{Message, Topic};
+use super::{topic, Messenger, MessengerError, OverflowPolicy, SubscriptionOptions, DEFAULT_CAPACITY};
+use crate::utils::serialization::{self, Format};
+
+const MAGIC: &[u8; 7] = b"ZBUSREC";
+const FORMAT_VERSION: u8 = 2;
+// a record can't be larger than the largest message the bridge accepts
+const MAX_RECORD_SIZE: usize = 16 * 1024 * 1024;
+// records waiting for the writer before new ones are dropped
+const WRITE_QUEUE: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    // microseconds since the unix epoch
+    pub timestamp: u64,
+    pub topic: Topic,
+    pub message: Message,
+}
+
+pub struct Recorder {
+    stop: oneshot::Sender<()>,
+    backend: Arc<dyn MessengerBackend>,
+    subscriptions: Vec<String>,
+    // records the writer had no room for
+    dropped: Arc<AtomicU64>,
+    task: JoinHandle<Result<u64, MessengerError>>,
+}
+
+impl Recorder {
+    // record every message sent to a topic matching one of the patterns until
+    // stop is called. a message matching several patterns is recorded once
+    pub async fn start(messenger: &Messenger, patterns: &[&str], path: impl AsRef<Path>) -> Result<Self, MessengerError> {
+        Self::start_as(messenger, patterns, path, Format::default()).await
+    }
+
+    // as start, writing records in the given format.
+    //
+    // recording never slows down publishers: the subscriptions drop their
+    // oldest messages rather than block, and messages are stamped as they are
+    // received and handed to a separate writer, which drops the newest ones
+    // when it falls more than WRITE_QUEUE records behind. see dropped
+    pub async fn start_as(
+        messenger: &Messenger,
+        patterns: &[&str],
+        path: impl AsRef<Path>,
+        format: Format,
+    ) -> Result<Self, MessengerError> {
+        let path = path.as_ref();
+        let file = File::create(path).await.map_err(|e| file_error(path, e))?;
+        let mut file = BufWriter::new(file);
+        file.write_all(MAGIC).await.map_err(recording_error)?;
+        file.write_u8(FORMAT_VERSION).await.map_err(recording_error)?;
+
+        let patterns: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        let options = SubscriptionOptions::new(DEFAULT_CAPACITY, OverflowPolicy::DropOldest);
+        let mut ids = Vec::with_capacity(patterns.len());
+        let mut subscriptions = Vec::with_capacity(patterns.len());
+        for (index, pattern) in patterns.iter().enumerate() {
+            let subscription = messenger.subscribe_stream_with(pattern, options).await?;
+            ids.push(subscription.id().to_string());
+            subscriptions.push(stream::unfold(subscription, move |mut subscription| async move {
+                let next = subscription.next_with_topic().await?;
+                Some(((index, next), subscription))
+            }).boxed());
+        }
+        let mut messages = stream::select_all(subscriptions);
+
+        let (records, mut queued) = mpsc::channel::<RecordedMessage>(WRITE_QUEUE);
+        let writer = tokio::spawn(async move {
+            let mut recorded = 0;
+            while let Some(record) = queued.recv().await {
+                write_record(&mut file, &record, format).await?;
+                recorded += 1;
+            }
+            file.flush().await.map_err(recording_error)?;
+            Ok(recorded)
+        });
+
+        let dropped = Arc::new(AtomicU64::new(0));
+        let (stop, mut stopped) = oneshot::channel();
+        let task = tokio::spawn({
+            let dropped = dropped.clone();
+            async move {
+                loop {
+                    let (index, (topic, message)) = tokio::select! {
+                        _ = &mut stopped => break,
+                        next = messages.next() => match next {
+                            Some(next) => next,
+                            None => break,
+                        },
+                    };
+                    // the subscription of the first matching pattern records it
+                    if patterns[..index].iter().any(|pattern| topic::matches(pattern, &topic)) {
+                        continue;
+                    }
+                    let record = RecordedMessage { timestamp: now_micros(), topic, message };
+                    match records.try_send(record) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        // the writer failed; its error is returned below
+                        Err(TrySendError::Closed(_)) => break,
+                    }
+                }
+                drop(records);
+                writer
+                    .await
+                    .map_err(|e| MessengerError::RecordingError(e.to_string()))?
+            }
+        });
+
+        Ok(Self {
+            stop,
+            backend: messenger.backend.clone(),
+            subscriptions: ids,
+            dropped,
+            task,
+        })
+    }
+
+    // messages left out of the recording so far, by the subscriptions or by
+    // the writer. a message matching several patterns counts once for every
+    // subscription that dropped it
+    pub async fn dropped(&self) -> u64 {
+        let subscriptions: u64 = self
+            .backend
+            .stats()
+            .await
+            .iter()
+            .filter(|stats| self.subscriptions.contains(&stats.id))
+            .flat_map(|stats| &stats.lanes)
+            .map(|lane| lane.dropped)
+            .sum();
+        subscriptions + self.dropped.load(Ordering::Relaxed)
+    }
+
+    // stop recording, unsubscribe and flush the file; returns how many
+    // messages were recorded
+    pub async fn stop(self) -> Result<u64, MessengerError> {
+        // while the subscriptions still exist
+        let dropped = self.dropped().await;
+        let _ = self.stop.send(());
+        let recorded = self
+            .task
+            .await
+            .map_err(|e| MessengerError::RecordingError(e.to_string()))??;
+        if dropped > 0 {
+            log::warn!("Recorder dropped {} messages it could not keep up with", dropped);
+        }
+        Ok(recorded)
+    }
+}
+
+// how fast a replay goes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pace {
+    // the gaps between messages as recorded
+    Original,
+    // the recorded gaps divided by this factor; 2.0 is twice as fast
+    Speedup(f64),
+    // no gaps at all
+    Unpaced,
+}
+
+pub struct Replayer {
+    file: BufReader<File>,
+}
+
+impl Replayer {
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, MessengerError> {
+        let path = path.as_ref();
+        let mut file = BufReader::new(File::open(path).await.map_err(|e| file_error(path, e))?);
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header).await.map_err(|_| not_a_recording(path))?;
+        if &header[..7] != MAGIC {
+            return Err(not_a_recording(path));
+        }
+        if header[7] != FORMAT_VERSION {
+            return Err(MessengerError::RecordingError(format!(
+                "{} is recording format version {}, expected {}",
+                path.display(),
+                header[7],
+                FORMAT_VERSION
+            )));
+        }
+        Ok(Self { file })
+    }
+
+    // the next recorded message, or None at the end of the recording
+    pub async fn next(&mut self) -> Result<Option<RecordedMessage>, MessengerError> {
+        let mut length = [0u8; 4];
+        match self.file.read_exact(&mut length).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(recording_error(e)),
+        }
+        let length = u32::from_be_bytes(length) as usize;
+        if length > MAX_RECORD_SIZE {
+            return Err(MessengerError::RecordingError(format!("record of {} bytes is too large", length)));
+        }
+        let mut record = vec![0u8; length];
+        self.file.read_exact(&mut record).await.map_err(recording_error)?;
+        serialization::deserialize(&record)
+            .map(Some)
+            .map_err(|e| MessengerError::RecordingError(format!("corrupt record: {}", e)))
+    }
+
+    // publish the rest of the recording to the messenger; returns how many
+    // messages were sent
+    pub async fn replay(mut self, messenger: &Messenger, pace: Pace) -> Result<u64, MessengerError> {
+        let start = Instant::now();
+        let mut first = None;
+        let mut replayed = 0;
+        while let Some(record) = self.next().await? {
+            let first = *first.get_or_insert(record.timestamp);
+            let offset = Duration::from_micros(record.timestamp.saturating_sub(first));
+            let offset = match pace {
+                Pace::Original => Some(offset),
+                Pace::Speedup(factor) if factor > 0.0 => Some(offset.div_f64(factor)),
+                Pace::Speedup(_) | Pace::Unpaced => None,
+            };
+            if let Some(offset) = offset {
+                tokio::time::sleep_until(start + offset).await;
+            }
+            messenger.send(&record.topic, &record.message).await?;
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+}
+
+async fn write_record(file: &mut BufWriter<File>, record: &RecordedMessage, format: Format) -> Result<(), MessengerError> {
+    let record = serialization::encode(format, record).map_err(|e| MessengerError::RecordingError(e.to_string()))?;
+    if record.len() > MAX_RECORD_SIZE {
+        return Err(MessengerError::RecordingError(format!("record of {} bytes is too large", record.len())));
+    }
+    file.write_all(&(record.len() as u32).to_be_bytes()).await.map_err(recording_error)?;
+    file.write_all(&record).await.map_err(recording_error)
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+fn recording_error(e: io::Error) -> MessengerError {
+    MessengerError::RecordingError(e.to_string())
+}
+
+fn file_error(path: &Path, e: io::Error) -> MessengerError {
+    MessengerError::RecordingError(format!("{}: {}", path.display(), e))
+}
+
+fn not_a_recording(path: &Path) -> MessengerError {
+    MessengerError::RecordingError(format!("{} is not a bus recording", path.display()))
+}
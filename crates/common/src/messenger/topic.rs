@@ -0,0 +1,112 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// topics are dotted paths such as "module.dsl.started". subscriptions take a
+// pattern where a "*" segment matches exactly one segment and a trailing "#"
+// matches whatever follows, including nothing: "module.*.started" and
+// "rules.#" are patterns, messages are always sent to concrete topics.
+
+use super::MessengerError;
+
+pub const SEPARATOR: char = '.';
+pub const SINGLE_WILDCARD: &str = "*";
+pub const MULTI_WILDCARD: &str = "#";
+
+// messages dropped by full subscriber queues are republished here as
+// DeadLetter envelopes
+pub const DEAD_LETTER_TOPIC: &str = "$dead_letter";
+
+// replies to requests are sent to "_reply.<correlation id>". they only reach
+// a subscription on that exact topic, never a wildcard pattern
+pub const REPLY_TOPIC_PREFIX: &str = "_reply";
+
+pub fn is_reply_topic(topic: &str) -> bool {
+    segments(topic).next() == Some(REPLY_TOPIC_PREFIX)
+}
+
+pub fn segments(topic: &str) -> impl Iterator<Item = &str> {
+    topic.split(SEPARATOR)
+}
+
+// a topic messages can be sent to: non-empty segments and no wildcards
+pub fn validate_topic(topic: &str) -> Result<(), MessengerError> {
+    for segment in segments(topic) {
+        if segment.is_empty() || segment.contains(['*', '#']) {
+            return Err(MessengerError::InvalidTopic(topic.to_string()));
+        }
+    }
+    Ok(())
+}
+
+// a subscription pattern: like a topic, but segments may be wildcards, and
+// "#" only as the last one
+pub fn validate_pattern(pattern: &str) -> Result<(), MessengerError> {
+    let mut segments = segments(pattern).peekable();
+    while let Some(segment) = segments.next() {
+        let valid = match segment {
+            SINGLE_WILDCARD => true,
+            MULTI_WILDCARD => segments.peek().is_none(),
+            _ => !segment.is_empty() && !segment.contains(['*', '#']),
+        };
+        if !valid {
+            return Err(MessengerError::InvalidTopic(pattern.to_string()));
+        }
+    }
+    Ok(())
+}
+
+// whether a message sent to the topic reaches a subscription on the pattern
+pub fn matches(pattern: &str, topic: &str) -> bool {
+    if is_reply_topic(topic) {
+        return pattern == topic;
+    }
+    let pattern: Vec<&str> = segments(pattern).collect();
+    let topic: Vec<&str> = segments(topic).collect();
+    matches_segments(&pattern, &topic)
+}
+
+fn matches_segments(pattern: &[&str], topic: &[&str]) -> bool {
+    match (pattern.split_first(), topic.split_first()) {
+        (Some((&MULTI_WILDCARD, _)), _) => true,
+        (None, None) => true,
+        (Some((expected, pattern)), Some((segment, topic))) => {
+            (*expected == SINGLE_WILDCARD || expected == segment) && matches_segments(pattern, topic)
+        }
+        _ => false,
+    }
+}
+
+// turn a name (of a module, plugin, ...) into a single topic segment, so it
+// can be embedded in a topic without adding levels or wildcards
+pub fn topic_segment(name: &str) -> String {
+    let segment: String = name
+        .chars()
+        .map(|c| if matches!(c, '.' | '*' | '#') { '_' } else { c })
+        .collect();
+    if segment.is_empty() {
+        "_".to_string()
+    } else {
+        segment
+    }
+}
@@ -22,14 +22,231 @@
 //
 // Authors: I. Zeqiri, E. Gjergji
 
-use serde::{Serialize, Deserialize};
-use bincode;
+// encoding of everything the core puts on the wire or on disk. every
+// encoded value starts with a one-byte tag naming its format, so a reader
+// can decode it without being told the format up front:
+//
+//   0x01 bincode       compact, rust-to-rust
+//   0x02 json          human-readable
+//   0x03 messagepack   compact, structs as maps so other tools can read them
+//   0x04 cbor          compact, self-describing
+//
+// before the tag was introduced, serialize wrote bare bincode, and durable
+// logs and other stored data may still hold values written that way.
+// deserialize reads both: a value that doesn't decode as tagged is read
+// again as untagged bincode. decode only reads tagged values.
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SerializationError {
+    #[error("nothing to decode")]
+    Empty,
+    #[error("unknown format tag {0:#04x}")]
+    UnknownFormat(u8),
+    #[error("{0} encoding failed: {1}")]
+    Encode(Format, String),
+    #[error("{0} decoding failed: {1}")]
+    Decode(Format, String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum Format {
+    #[default]
+    Bincode = 0x01,
+    Json = 0x02,
+    MessagePack = 0x03,
+    Cbor = 0x04,
+}
+
+impl Format {
+    pub fn tag(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_tag(tag: u8) -> Result<Self, SerializationError> {
+        match tag {
+            0x01 => Ok(Format::Bincode),
+            0x02 => Ok(Format::Json),
+            0x03 => Ok(Format::MessagePack),
+            0x04 => Ok(Format::Cbor),
+            _ => Err(SerializationError::UnknownFormat(tag)),
+        }
+    }
 
+    // whether people can read it without tooling
+    pub fn is_human_readable(self) -> bool {
+        self == Format::Json
+    }
+
+    // the value without a tag
+    pub fn encode<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>, SerializationError> {
+        match self {
+            Format::Bincode => Bincode.encode(value),
+            Format::Json => Json.encode(value),
+            Format::MessagePack => MessagePack.encode(value),
+            Format::Cbor => Cbor.encode(value),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, SerializationError> {
+        match self {
+            Format::Bincode => Bincode.decode(bytes),
+            Format::Json => Json.decode(bytes),
+            Format::MessagePack => MessagePack.decode(bytes),
+            Format::Cbor => Cbor.decode(bytes),
+        }
+    }
+}
 
-pub fn serialize<T: Serialize>(value: &T) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    Ok(bincode::serialize(value)?)
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Format::Bincode => "bincode",
+            Format::Json => "json",
+            Format::MessagePack => "messagepack",
+            Format::Cbor => "cbor",
+        })
+    }
 }
 
-pub fn deserialize<T: for<'de> Deserialize<'de>>(bytes: &[u8]) -> Result<T, Box<dyn std::error::Error>> {
-    Ok(bincode::deserialize(bytes)?)
+// one encoding, untagged
+pub trait Codec {
+    const FORMAT: Format;
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, SerializationError>;
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializationError>;
+}
+
+pub struct Bincode;
+pub struct Json;
+pub struct MessagePack;
+pub struct Cbor;
+
+impl Codec for Bincode {
+    const FORMAT: Format = Format::Bincode;
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, SerializationError> {
+        bincode::serialize(value).map_err(|e| SerializationError::Encode(Self::FORMAT, e.to_string()))
+    }
+
+    // strict about trailing bytes, so untagged data that happens to start
+    // with the bincode tag doesn't decode as something else
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializationError> {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .deserialize(bytes)
+            .map_err(|e| SerializationError::Decode(Self::FORMAT, e.to_string()))
+    }
+}
+
+impl Codec for Json {
+    const FORMAT: Format = Format::Json;
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, SerializationError> {
+        serde_json::to_vec(value).map_err(|e| SerializationError::Encode(Self::FORMAT, e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializationError> {
+        serde_json::from_slice(bytes).map_err(|e| SerializationError::Decode(Self::FORMAT, e.to_string()))
+    }
+}
+
+impl Codec for MessagePack {
+    const FORMAT: Format = Format::MessagePack;
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, SerializationError> {
+        rmp_serde::to_vec_named(value).map_err(|e| SerializationError::Encode(Self::FORMAT, e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializationError> {
+        rmp_serde::from_slice(bytes).map_err(|e| SerializationError::Decode(Self::FORMAT, e.to_string()))
+    }
+}
+
+impl Codec for Cbor {
+    const FORMAT: Format = Format::Cbor;
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, SerializationError> {
+        let mut encoded = Vec::new();
+        ciborium::into_writer(value, &mut encoded)
+            .map_err(|e| SerializationError::Encode(Self::FORMAT, e.to_string()))?;
+        Ok(encoded)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializationError> {
+        ciborium::from_reader(bytes).map_err(|e| SerializationError::Decode(Self::FORMAT, e.to_string()))
+    }
+}
+
+// the format an encoded value was written in
+pub fn detect(bytes: &[u8]) -> Result<Format, SerializationError> {
+    Format::from_tag(*bytes.first().ok_or(SerializationError::Empty)?)
+}
+
+// tag and encode
+pub fn encode<T: Serialize + ?Sized>(format: Format, value: &T) -> Result<Vec<u8>, SerializationError> {
+    let mut encoded = vec![format.tag()];
+    encoded.extend(format.encode(value)?);
+    Ok(encoded)
+}
+
+// decode whatever format the tag names
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, SerializationError> {
+    detect(bytes)?.decode(&bytes[1..])
+}
+
+// the default, compact encoding
+pub fn serialize<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, SerializationError> {
+    encode(Format::default(), value)
+}
+
+// decode a tagged value, or an untagged bincode one written before tags.
+// an untagged value's first byte can look like a tag (a string of length 2
+// starts with 0x02), so any tagged failure is retried untagged. the tagged
+// error is the one reported
+pub fn deserialize<T: for<'de> Deserialize<'de>>(bytes: &[u8]) -> Result<T, SerializationError> {
+    match decode(bytes) {
+        Err(SerializationError::Empty) => Err(SerializationError::Empty),
+        Err(e) => bincode::deserialize(bytes).map_err(|_| e),
+        decoded => decoded,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        topic: String,
+        offset: u64,
+    }
+
+    #[test]
+    fn untagged_bincode_still_deserializes() {
+        // untagged, the first bytes are the topic's length: 0x02 looks like the json tag
+        for topic in ["ab", "a", "abcd", "a longer topic"] {
+            let record = Record { topic: topic.to_string(), offset: 7 };
+            let legacy = bincode::serialize(&record).unwrap();
+            assert_eq!(deserialize::<Record>(&legacy).unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn tagged_values_round_trip() {
+        let record = Record { topic: "rules.reload".to_string(), offset: 42 };
+        for format in [Format::Bincode, Format::Json, Format::MessagePack, Format::Cbor] {
+            let encoded = encode(format, &record).unwrap();
+            assert_eq!(detect(&encoded).unwrap(), format);
+            assert_eq!(deserialize::<Record>(&encoded).unwrap(), record);
+        }
+        assert!(matches!(deserialize::<Record>(&[]), Err(SerializationError::Empty)));
+        assert!(matches!(deserialize::<Record>(&[0x7f, 1]), Err(SerializationError::UnknownFormat(0x7f))));
+    }
 }
@@ -22,54 +22,294 @@
 //
 // Authors: I. Zeqiri, E. Gjergji
 
+// sortable unique ids, ULID style: 128 bits written as 26 characters of
+// crockford base32, so the text sorts the same way as the number.
+//
+//   48 bits  milliseconds since the unix epoch
+//   16 bits  node id, set with Uid::set_node_id or random per process
+//   64 bits  sequence, random at the start of each millisecond and counting
+//            up within it, so ids from one process are strictly increasing
+//
+// a clock that steps backwards keeps counting on from the last millisecond
+// seen rather than going back with it.
+
 use chrono::{DateTime, Utc};
 use rand::Rng;
-use std::time::SystemTime;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ENCODED_LENGTH: usize = 26;
+const TIMESTAMP_BITS: u32 = 48;
+const NODE_BITS: u32 = 16;
+const SEQUENCE_BITS: u32 = 64;
+// the sequence starts below this so a millisecond has room to count up
+const SEQUENCE_START_LIMIT: u64 = 1 << 62;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum UidError {
+    #[error("uid must be {ENCODED_LENGTH} characters, got {0}")]
+    InvalidLength(usize),
+    #[error("invalid character {0:?} in uid")]
+    InvalidCharacter(char),
+    #[error("uid is out of range")]
+    Overflow,
+}
+
+struct Generator {
+    node: Option<u16>,
+    last_millis: u64,
+    sequence: u64,
+}
+
+impl Generator {
+    // the id for a clock reading of now
+    fn next(&mut self, now: u64, rng: &mut impl Rng) -> Uid {
+        let node = *self.node.get_or_insert_with(|| rng.gen());
+        if now > self.last_millis {
+            self.last_millis = now;
+            self.sequence = rng.gen_range(0..SEQUENCE_START_LIMIT);
+        } else if self.sequence == u64::MAX {
+            // a whole millisecond's sequence used up: borrow the next one
+            self.last_millis += 1;
+            self.sequence = 0;
+        } else {
+            self.sequence += 1;
+        }
+        Uid::from_parts(self.last_millis, node, self.sequence)
+    }
+}
+
+static GENERATOR: Mutex<Generator> = Mutex::new(Generator {
+    node: None,
+    last_millis: 0,
+    sequence: 0,
+});
 
-pub struct Uid(String);
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uid(u128);
 
 impl Uid {
     pub fn new() -> Self {
-        let now = SystemTime::now();
-        let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
 
-        let random_part: String = rand::thread_rng()
-            .sample_iter(&rand::distributions::Alphanumeric)
-            .take(12)
-            .map(char::from)
-            .collect();
-
-        let uid = format!("{:X}{}", timestamp, random_part)
-            .chars()
-            .collect::<Vec<char>>()
-            .chunks(4)
-            .map(|c| c.iter().collect::<String>())
-            .collect::<Vec<String>>()
-            .join("-");
+        GENERATOR
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .next(now, &mut rand::thread_rng())
+    }
 
-        Uid(uid)
+    // identify this process in the ids it generates, instead of the random
+    // node id it picks otherwise. set it at startup: ids generated in the same
+    // millisecond under a smaller node id would sort before earlier ones
+    pub fn set_node_id(node: u16) {
+        GENERATOR.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).node = Some(node);
     }
 
-    pub fn to_string(&self) -> String {
-        self.0.clone()
+    pub fn from_parts(timestamp_millis: u64, node: u16, sequence: u64) -> Self {
+        let timestamp = (timestamp_millis as u128) & ((1 << TIMESTAMP_BITS) - 1);
+        Uid(timestamp << (NODE_BITS + SEQUENCE_BITS) | (node as u128) << SEQUENCE_BITS | sequence as u128)
     }
 
     pub fn from_string(s: &str) -> Option<Self> {
-        if s.len() == 23 && s.chars().filter(|&c| c == '-').count() == 3 {
-            Some(Uid(s.to_string()))
-        } else {
-            None
-        }
+        s.parse().ok()
+    }
+
+    pub fn timestamp_millis(&self) -> u64 {
+        (self.0 >> (NODE_BITS + SEQUENCE_BITS)) as u64
+    }
+
+    pub fn node_id(&self) -> u16 {
+        (self.0 >> SEQUENCE_BITS) as u16
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.0 as u64
     }
 
     pub fn get_timestamp(&self) -> Option<DateTime<Utc>> {
-        let timestamp_hex = self.0.split('-').next()?;
-        let timestamp = u64::from_str_radix(timestamp_hex, 16).ok()?;
-        let datetime = DateTime::<Utc>::from(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(timestamp));
-        Some(datetime)
+        let time = SystemTime::UNIX_EPOCH.checked_add(Duration::from_millis(self.timestamp_millis()))?;
+        Some(DateTime::<Utc>::from(time))
+    }
+
+    pub fn as_u128(&self) -> u128 {
+        self.0
+    }
+}
+
+impl Default for Uid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<u128> for Uid {
+    fn from(value: u128) -> Self {
+        Uid(value)
+    }
+}
+
+impl fmt::Display for Uid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut encoded = [0u8; ENCODED_LENGTH];
+        for (index, character) in encoded.iter_mut().rev().enumerate() {
+            *character = ALPHABET[((self.0 >> (5 * index)) & 0x1f) as usize];
+        }
+        // the alphabet is ascii
+        f.write_str(std::str::from_utf8(&encoded).map_err(|_| fmt::Error)?)
     }
 }
 
+impl fmt::Debug for Uid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Uid({})", self)
+    }
+}
+
+// exactly 26 characters of the crockford alphabet, in either case. 26
+// characters hold 130 bits, so the first one can't be above '7'
+impl FromStr for Uid {
+    type Err = UidError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != ENCODED_LENGTH {
+            return Err(UidError::InvalidLength(s.chars().count()));
+        }
+        let mut value: u128 = 0;
+        for (index, character) in s.chars().enumerate() {
+            let digit = ALPHABET
+                .iter()
+                .position(|symbol| *symbol as char == character.to_ascii_uppercase())
+                .ok_or(UidError::InvalidCharacter(character))? as u128;
+            if index == 0 && digit > 7 {
+                return Err(UidError::Overflow);
+            }
+            value = value << 5 | digit;
+        }
+        Ok(Uid(value))
+    }
+}
+
+impl Serialize for Uid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Uid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        encoded.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn generator() -> Generator {
+        Generator { node: Some(7), last_millis: 0, sequence: 0 }
+    }
 
+    #[test]
+    fn a_millisecond_counts_up_from_a_random_start() {
+        let mut generator = generator();
+        let mut rng = rand::thread_rng();
+        let first = generator.next(NOW, &mut rng);
+        assert_eq!(first.timestamp_millis(), NOW);
+        assert_eq!(first.node_id(), 7);
+        assert!(first.sequence() < SEQUENCE_START_LIMIT);
+        for step in 1..=3 {
+            let next = generator.next(NOW, &mut rng);
+            assert_eq!(next.timestamp_millis(), NOW);
+            assert_eq!(next.sequence(), first.sequence() + step);
+        }
+        let later = generator.next(NOW + 1, &mut rng);
+        assert_eq!(later.timestamp_millis(), NOW + 1);
+        assert!(later.sequence() < SEQUENCE_START_LIMIT);
+    }
+
+    #[test]
+    fn a_clock_stepping_back_keeps_counting() {
+        let mut generator = generator();
+        let mut rng = rand::thread_rng();
+        let before = generator.next(NOW, &mut rng);
+        let after = generator.next(NOW - 5_000, &mut rng);
+        assert_eq!(after.timestamp_millis(), NOW);
+        assert_eq!(after.sequence(), before.sequence() + 1);
+        assert!(after > before);
+    }
+
+    #[test]
+    fn a_used_up_sequence_borrows_the_next_millisecond() {
+        let mut generator = Generator { node: Some(7), last_millis: NOW, sequence: u64::MAX - 1 };
+        let mut rng = rand::thread_rng();
+        let last = generator.next(NOW, &mut rng);
+        assert_eq!((last.timestamp_millis(), last.sequence()), (NOW, u64::MAX));
+        let borrowed = generator.next(NOW, &mut rng);
+        assert_eq!((borrowed.timestamp_millis(), borrowed.sequence()), (NOW + 1, 0));
+        assert!(borrowed > last);
+        // the clock catching up with the borrowed millisecond counts on from it
+        let caught_up = generator.next(NOW + 1, &mut rng);
+        assert_eq!((caught_up.timestamp_millis(), caught_up.sequence()), (NOW + 1, 1));
+    }
+
+    #[test]
+    fn ids_are_strictly_increasing() {
+        let mut last = Uid::new();
+        for _ in 0..100_000 {
+            let next = Uid::new();
+            assert!(next > last, "{} after {}", next, last);
+            assert!(next.to_string() > last.to_string());
+            last = next;
+        }
+    }
+
+    #[test]
+    fn text_round_trips() {
+        for uid in [Uid::new(), Uid::from(0), Uid::from(u128::MAX), Uid::from_parts(NOW, 0xbeef, 42)] {
+            let text = uid.to_string();
+            assert_eq!(text.len(), ENCODED_LENGTH);
+            assert_eq!(text.parse::<Uid>(), Ok(uid));
+            assert_eq!(text.to_lowercase().parse::<Uid>(), Ok(uid));
+        }
+        assert_eq!(Uid::from(0).to_string(), "0".repeat(ENCODED_LENGTH));
+        assert_eq!(Uid::from(u128::MAX).to_string(), format!("7{}", "Z".repeat(ENCODED_LENGTH - 1)));
+        let uid = Uid::from_parts(NOW, 0xbeef, 42);
+        assert_eq!((uid.timestamp_millis(), uid.node_id(), uid.sequence()), (NOW, 0xbeef, 42));
+    }
+
+    #[test]
+    fn parsing_is_strict() {
+        let valid = Uid::from_parts(NOW, 1, 2).to_string();
+        assert_eq!("".parse::<Uid>(), Err(UidError::InvalidLength(0)));
+        assert_eq!(valid[1..].parse::<Uid>(), Err(UidError::InvalidLength(25)));
+        assert_eq!(format!("{}0", valid).parse::<Uid>(), Err(UidError::InvalidLength(27)));
+        // ulid's ambiguous letters aren't read as digits
+        for letter in ['I', 'L', 'O', 'U', 'i', '-'] {
+            let text = format!("{}{}", &valid[..25], letter);
+            assert_eq!(text.parse::<Uid>(), Err(UidError::InvalidCharacter(letter)));
+        }
+        let text = format!("8{}", &valid[1..]);
+        assert_eq!(text.parse::<Uid>(), Err(UidError::Overflow));
+        assert!(format!("7{}", &valid[1..]).parse::<Uid>().is_ok());
+    }
+
+    #[test]
+    fn serde_uses_the_text() {
+        let uid = Uid::from_parts(NOW, 3, 4);
+        let json = serde_json::to_string(&uid).unwrap();
+        assert_eq!(json, format!("\"{}\"", uid));
+        assert_eq!(serde_json::from_str::<Uid>(&json).unwrap(), uid);
+        assert!(serde_json::from_str::<Uid>("\"not a uid\"").is_err());
+    }
+}
@@ -23,7 +23,7 @@
 // Authors: I. Zeqiri, E. Gjergji 
 
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use serde::{Deserialize, Serialize};
 use tokio::fs::File;
@@ -33,6 +33,57 @@ use crate::error::ConfigError;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CoreConfig {
+    pub thread_pool_size: usize,
+    pub max_connections: usize,
+    // xml rule set evaluated before any module or plugin
+    #[serde(default)]
+    pub rules_path: Option<String>,
+    // largest request body buffered for inspection, in bytes
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    // external zark_messenger library to use for the message bus; the
+    // in-process bus is used when unset
+    #[serde(default)]
+    pub messenger_library: Option<String>,
+    // unix socket exposing the message bus to out-of-process modules; no
+    // bridge is started when unset
+    #[serde(default)]
+    pub messenger_socket: Option<String>,
+    // topics whose messages are persisted for durable consumers, and the
+    // directory they are persisted in
+    #[serde(default)]
+    pub durable_topics: Vec<String>,
+    #[serde(default)]
+    pub durable_store: Option<String>,
+    // sync every durable message to disk before it counts as sent; turning
+    // it off trades the latest messages on a power loss for throughput
+    #[serde(default = "default_durable_sync")]
+    pub durable_sync: bool,
+    // written into every generated id to tell instances apart; random per
+    // process when unset
+    #[serde(default)]
+    pub node_id: Option<u16>,
+    // seconds to wait for open connections to finish on shutdown
+    #[serde(default = "default_drain_timeout")]
+    pub drain_timeout: u64,
+}
+
+fn default_max_body_size() -> usize {
+    1024 * 1024
+}
+
+fn default_drain_timeout() -> u64 {
+    30
+}
+
+fn default_durable_sync() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct LoggerConfig {
     pub log_type: Vec<String>,
     pub log_path: String,
@@ -43,17 +94,134 @@ pub struct LoggerConfig {
     pub log_compress: bool,
 }
 
+// how zark sits next to a web server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConnectorMode {
+    // inline reverse proxy in front of the upstream
+    #[default]
+    Proxy,
+    // verdict endpoint for nginx's auth_request module
+    AuthRequest,
+    // stream processing offload agent for haproxy's spoe filter
+    Spoa,
+    // envoy ext_authz service, grpc flavour
+    ExtAuthzGrpc,
+    // envoy ext_authz service, http flavour
+    ExtAuthzHttp,
+    // icap service (rfc 3507) for squid and other forward proxies
+    Icap,
+    // detect only: tail log-path and inspect the logged requests
+    Passive,
+}
+
+// access log format read in passive mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    NginxCombined,
+    ApacheCombined,
+    HaproxyHttp,
+    IisW3c,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WebServerConfig {
+    pub enabled: bool,
+    #[serde(default)]
+    pub mode: ConnectorMode,
+    pub config_path: String,
+    pub pid_path: String,
+    pub log_path: String,
+    // format of log-path, required in passive mode
+    #[serde(default)]
+    pub log_format: Option<LogFormat>,
+    pub error_log_path: String,
+    pub port: u16,
+    pub host: String,
+    pub ssl_enabled: bool,
+    pub ssl_cert_path: String,
+    pub ssl_key_path: String,
+    pub ssl_port: u16,
+    pub ssl_host: String,
+    pub ssl_protocols: Vec<String>,
+    // address allowed traffic is forwarded to in proxy mode, e.g. "http://127.0.0.1:8080"
+    #[serde(default)]
+    pub upstream: Option<String>,
+}
+
+// name and path of a dynamically loaded library (module or plugin)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub name: String,
+    pub path: String,
+    #[serde(default, rename = "on-failure")]
+    pub on_failure: FailureMode,
+}
+
+// what the inspection chain does with a request a library failed to inspect
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureMode {
+    // skip the library, so a broken one can't take traffic down with it
+    #[default]
+    Open,
+    // block the request, so a broken one can't wave attacks through
+    Closed,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct ModulesConfig {
-    pub logger_path: String,
-    pub paths: HashMap<String, String>,
+    #[serde(default)]
+    pub logger_path: Option<String>,
+    // modules in load order, which is also the order they inspect requests in
+    #[serde(default)]
+    pub paths: Vec<LibraryEntry>,
+    // the only place modules are loaded from at runtime; runtime loads are
+    // refused when unset
+    #[serde(default)]
+    pub directory: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PluginsConfig {
+    // plugins in load order; they inspect requests after all modules
+    #[serde(default)]
+    pub paths: Vec<LibraryEntry>,
+    // the only place plugins are loaded from at runtime, like modules.directory
+    #[serde(default)]
+    pub directory: Option<String>,
+}
+
+// runtime control api; it can load native code, so it only binds beyond
+// loopback when a token is set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AdminConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    // bearer token required on every request, if set
+    #[serde(default)]
+    pub token: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(rename = "zark-core")]
+    pub core: CoreConfig,
+    #[serde(rename = "zark-logger")]
     pub logger: LoggerConfig,
+    #[serde(rename = "zark-admin", default)]
+    pub admin: Option<AdminConfig>,
+    #[serde(rename = "web-servers", default)]
+    pub web_servers: BTreeMap<String, WebServerConfig>,
+    #[serde(default)]
     pub modules: ModulesConfig,
-
+    #[serde(default)]
+    pub plugins: PluginsConfig,
 }
 
 
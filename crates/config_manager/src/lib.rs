@@ -37,7 +37,6 @@ use crate::error::ConfigError;
 pub struct ConfigManager {
     config: Arc<RwLock<Config>>,
     watcher: ConfigWatcher,
-    // TODO: Implement usage of updater
     updater: ConfigUpdater,
 }
 
@@ -65,6 +64,10 @@ impl ConfigManager {
         Ok(())
     }
 
+    pub async fn apply_changes(&self) -> Result<(), ConfigError> {
+        self.updater.apply_changes().await
+    }
+
     pub async fn get_config(&self) -> Config {
         self.config.read().await.clone()
     }
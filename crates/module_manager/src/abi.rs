@@ -0,0 +1,415 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// the boundary between the core and a dynamically loaded module. only
+// #[repr(C)] types and extern "C" functions cross it, so a module doesn't
+// depend on how a particular compiler lays out trait objects.
+//
+// a module library exports three symbols, all generated by export_module!:
+//
+//   zark_module_abi_version  the ABI version it was built against
+//   zark_module_metadata     a static ModuleMetadata
+//   zark_create_module       constructs the module, returning its vtable
+//
+// the loader checks the first two before calling the third. execute input
+// and output cross as json, inspections as InspectionCall json dispatched to
+// the module's typed methods; buffers are freed by the side that allocated them.
+
+use std::ffi::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::OnceLock;
+
+use tokio::runtime::Runtime;
+use tokio::sync::RwLock;
+use zark_waf_common::inspection::InspectionCall;
+
+use crate::module::Module;
+
+// export_module! refers to these through this module
+pub use zark_waf_common::abi::{AbiStr, TOOLCHAIN};
+
+// bump on any change to the types or functions in this file, or to the json
+// that crosses them. 2: request and response bodies are base64 strings
+pub const MODULE_ABI_VERSION: u32 = 2;
+
+pub const ABI_VERSION_SYMBOL: &[u8] = b"zark_module_abi_version";
+pub const METADATA_SYMBOL: &[u8] = b"zark_module_metadata";
+pub const CREATE_SYMBOL: &[u8] = b"zark_create_module";
+
+#[repr(C)]
+pub struct ModuleMetadata {
+    pub abi_version: u32,
+    pub toolchain: AbiStr,
+    pub name: AbiStr,
+    pub version: AbiStr,
+    pub description: AbiStr,
+}
+
+// bytes allocated by the module, returned to it through free_buffer
+#[repr(C)]
+pub struct AbiBuffer {
+    ptr: *mut u8,
+    len: usize,
+    capacity: usize,
+}
+
+impl AbiBuffer {
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let mut bytes = std::mem::ManuallyDrop::new(bytes);
+        Self { ptr: bytes.as_mut_ptr(), len: bytes.len(), capacity: bytes.capacity() }
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+// on success the buffer holds the output (json for execute, empty
+// otherwise), on failure the error message
+#[repr(C)]
+pub struct AbiResult {
+    pub ok: bool,
+    pub buffer: AbiBuffer,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ModuleVTable {
+    pub(crate) instance: *mut c_void,
+    pub(crate) init: unsafe extern "C" fn(instance: *mut c_void, messenger: *mut c_void) -> AbiResult,
+    pub(crate) execute: unsafe extern "C" fn(instance: *mut c_void, input: *const u8, len: usize) -> AbiResult,
+    pub(crate) shutdown: unsafe extern "C" fn(instance: *mut c_void) -> AbiResult,
+    pub(crate) free_buffer: unsafe extern "C" fn(buffer: AbiBuffer),
+    pub(crate) destroy: unsafe extern "C" fn(instance: *mut c_void),
+}
+
+// the module side. a module's async methods run on a runtime of its own:
+// the library has its own copy of tokio, which can't see the core's
+type Instance<T> = RwLock<T>;
+
+impl ModuleVTable {
+    pub fn new<T: Module + 'static>(module: T) -> Self {
+        let instance: Box<Instance<T>> = Box::new(RwLock::new(module));
+        Self {
+            instance: Box::into_raw(instance) as *mut c_void,
+            init: init_module::<T>,
+            execute: execute_module::<T>,
+            shutdown: shutdown_module::<T>,
+            free_buffer,
+            destroy: destroy_module::<T>,
+        }
+    }
+}
+
+fn runtime() -> Result<&'static Runtime, String> {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+    let runtime = Runtime::new().map_err(|e| format!("Failed to start the module runtime: {}", e))?;
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+// run a call into the module, turning its result and any panic into an
+// AbiResult; a panic must not unwind into the core
+fn call(f: impl FnOnce() -> Result<Vec<u8>, String>) -> AbiResult {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(format!("Module panicked: {}", message))
+    });
+    match result {
+        Ok(output) => AbiResult { ok: true, buffer: AbiBuffer::from_vec(output) },
+        Err(error) => AbiResult { ok: false, buffer: AbiBuffer::from_vec(error.into_bytes()) },
+    }
+}
+
+unsafe extern "C" fn init_module<T: Module>(instance: *mut c_void, messenger: *mut c_void) -> AbiResult {
+    let module = &*(instance as *const Instance<T>);
+    call(|| {
+        runtime()?.block_on(async {
+            module.write().await.init(messenger).await.map_err(|e| e.to_string())?;
+            Ok(Vec::new())
+        })
+    })
+}
+
+unsafe extern "C" fn execute_module<T: Module>(instance: *mut c_void, input: *const u8, len: usize) -> AbiResult {
+    let module = &*(instance as *const Instance<T>);
+    let input = std::slice::from_raw_parts(input, len);
+    call(|| {
+        let input: serde_json::Value = serde_json::from_slice(input).map_err(|e| format!("Invalid module input: {}", e))?;
+        let inspection = InspectionCall::from_input(&input)
+            .transpose()
+            .map_err(|e| format!("Invalid inspection input: {}", e))?;
+        runtime()?.block_on(async {
+            let module = module.read().await;
+            // inspections reach the module's typed methods
+            let output = match inspection {
+                Some(InspectionCall::Inspect { request }) => module
+                    .inspect_request(&request)
+                    .await
+                    .and_then(|inspection| Ok(serde_json::to_value(inspection)?)),
+                Some(InspectionCall::InspectResponse { request, response }) => module
+                    .inspect_response(&request, &response)
+                    .await
+                    .and_then(|inspection| Ok(serde_json::to_value(inspection)?)),
+                None => module.execute(input).await,
+            };
+            let output = output.map_err(|e| e.to_string())?;
+            serde_json::to_vec(&output).map_err(|e| format!("Invalid module output: {}", e))
+        })
+    })
+}
+
+unsafe extern "C" fn shutdown_module<T: Module>(instance: *mut c_void) -> AbiResult {
+    let module = &*(instance as *const Instance<T>);
+    call(|| {
+        runtime()?.block_on(async {
+            module.write().await.shutdown().await.map_err(|e| e.to_string())?;
+            Ok(Vec::new())
+        })
+    })
+}
+
+unsafe extern "C" fn free_buffer(buffer: AbiBuffer) {
+    if !buffer.ptr.is_null() {
+        drop(Vec::from_raw_parts(buffer.ptr, buffer.len, buffer.capacity));
+    }
+}
+
+unsafe extern "C" fn destroy_module<T: Module>(instance: *mut c_void) {
+    drop(Box::from_raw(instance as *mut Instance<T>));
+}
+
+// export a module from a cdylib:
+//
+//     export_module! {
+//         name: "zark_logger",
+//         version: env!("CARGO_PKG_VERSION"),
+//         description: "Logs to stdout, files and syslog",
+//         create: || ZarkLogger::new(LoggerConfig::default()),
+//     }
+//
+// name, version and description must match what the module reports
+#[macro_export]
+macro_rules! export_module {
+    (
+        name: $name:expr,
+        version: $version:expr,
+        description: $description:expr,
+        create: $create:expr $(,)?
+    ) => {
+        #[no_mangle]
+        pub extern "C" fn zark_module_abi_version() -> u32 {
+            $crate::abi::MODULE_ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn zark_module_metadata() -> *const $crate::abi::ModuleMetadata {
+            static METADATA: $crate::abi::ModuleMetadata = $crate::abi::ModuleMetadata {
+                abi_version: $crate::abi::MODULE_ABI_VERSION,
+                toolchain: $crate::abi::AbiStr::new($crate::abi::TOOLCHAIN),
+                name: $crate::abi::AbiStr::new($name),
+                version: $crate::abi::AbiStr::new($version),
+                description: $crate::abi::AbiStr::new($description),
+            };
+            &METADATA
+        }
+
+        #[no_mangle]
+        pub extern "C" fn zark_create_module() -> $crate::abi::ModuleVTable {
+            $crate::abi::ModuleVTable::new(($create)())
+        }
+    };
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use zark_waf_common::inspection::{HttpRequest, Inspection, Verdict};
+
+    // echoes execute input; {"panic": ..} panics, {"fail": ..} fails
+    pub(crate) struct Echo {
+        pub(crate) dropped: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl Module for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        fn description(&self) -> &str {
+            "echoes its input"
+        }
+
+        fn init<'life0, 'async_trait>(
+            &'life0 mut self,
+            _messenger: *mut c_void,
+        ) -> Pin<Box<dyn Future<Output = Result<(), Box<dyn std::error::Error>>> + Send + 'async_trait>>
+        where
+            'life0: 'async_trait,
+            Self: 'async_trait,
+        {
+            Box::pin(async { Ok(()) })
+        }
+
+        async fn execute(&self, input: serde_json::Value) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+            if let Some(message) = input.get("panic").and_then(|message| message.as_str()) {
+                panic!("{}", message);
+            }
+            if let Some(message) = input.get("fail").and_then(|message| message.as_str()) {
+                return Err(message.into());
+            }
+            Ok(input)
+        }
+
+        async fn shutdown(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+            panic!("static panic message");
+        }
+
+        async fn inspect_request(&self, request: &HttpRequest) -> Result<Inspection, Box<dyn std::error::Error>> {
+            if request.body.windows(4).any(|window| window == b"DROP") {
+                return Ok(Verdict::block("echo", "drop").into());
+            }
+            Ok(Inspection::default())
+        }
+    }
+
+    impl Drop for Echo {
+        fn drop(&mut self) {
+            self.dropped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn echo() -> (ModuleVTable, Arc<AtomicBool>) {
+        let dropped = Arc::new(AtomicBool::new(false));
+        (ModuleVTable::new(Echo { dropped: dropped.clone() }), dropped)
+    }
+
+    // what the core does with a result: copy it out, then free it module side
+    fn take(vtable: &ModuleVTable, result: AbiResult) -> (bool, String) {
+        let text = String::from_utf8(result.buffer.as_slice().to_vec()).unwrap();
+        unsafe { (vtable.free_buffer)(result.buffer) };
+        (result.ok, text)
+    }
+
+    fn execute(vtable: &ModuleVTable, input: serde_json::Value) -> (bool, String) {
+        let input = serde_json::to_vec(&input).unwrap();
+        let result = unsafe { (vtable.execute)(vtable.instance, input.as_ptr(), input.len()) };
+        take(vtable, result)
+    }
+
+    #[test]
+    fn execute_round_trips_through_buffers() {
+        let (vtable, dropped) = echo();
+        let input = serde_json::json!({"a": [1, 2, 3], "b": "text"});
+        let (ok, output) = execute(&vtable, input.clone());
+        assert!(ok);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&output).unwrap(), input);
+
+        let (ok, output) = execute(&vtable, serde_json::json!({"fail": "no thanks"}));
+        assert_eq!((ok, output.as_str()), (false, "no thanks"));
+
+        let (ok, output) = unsafe { take(&vtable, (vtable.execute)(vtable.instance, b"{".as_ptr(), 1)) };
+        assert!(!ok);
+        assert!(output.starts_with("Invalid module input"), "{}", output);
+
+        let (ok, output) = take(&vtable, unsafe { (vtable.init)(vtable.instance, std::ptr::null_mut()) });
+        assert_eq!((ok, output.as_str()), (true, ""));
+
+        assert!(!dropped.load(Ordering::SeqCst));
+        unsafe { (vtable.destroy)(vtable.instance) };
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        let (vtable, _) = echo();
+        let (ok, output) = execute(&vtable, serde_json::json!({"panic": "formatted"}));
+        assert_eq!((ok, output.as_str()), (false, "Module panicked: formatted"));
+        let (ok, output) = take(&vtable, unsafe { (vtable.shutdown)(vtable.instance) });
+        assert_eq!((ok, output.as_str()), (false, "Module panicked: static panic message"));
+
+        // the module is still usable after a panic
+        let (ok, output) = execute(&vtable, serde_json::json!(1));
+        assert_eq!((ok, output.as_str()), (true, "1"));
+        unsafe { (vtable.destroy)(vtable.instance) };
+    }
+
+    #[test]
+    fn panics_without_a_message_become_errors() {
+        let result = call(|| std::panic::panic_any(7u8));
+        let (ok, message) = (result.ok, String::from_utf8(result.buffer.as_slice().to_vec()).unwrap());
+        unsafe { free_buffer(result.buffer) };
+        assert_eq!((ok, message.as_str()), (false, "Module panicked: unknown panic"));
+    }
+
+    #[test]
+    fn inspections_reach_the_typed_methods() {
+        let (vtable, _) = echo();
+        let request = HttpRequest::new("POST", "/").with_body("DROP TABLE users");
+        let (ok, output) = execute(&vtable, zark_waf_common::inspection::InspectionCall::request(&request).unwrap());
+        assert!(ok, "{}", output);
+        let inspection = Inspection::from_output(serde_json::from_str(&output).unwrap()).unwrap();
+        assert_eq!(inspection, Verdict::block("echo", "drop").into());
+
+        let request = HttpRequest::new("GET", "/");
+        let (ok, output) = execute(&vtable, zark_waf_common::inspection::InspectionCall::request(&request).unwrap());
+        assert!(ok);
+        assert_eq!(Inspection::from_output(serde_json::from_str(&output).unwrap()).unwrap(), Inspection::default());
+
+        let (ok, output) = execute(&vtable, serde_json::json!({"action": "inspect"}));
+        assert!(!ok);
+        assert!(output.starts_with("Invalid inspection input"), "{}", output);
+        unsafe { (vtable.destroy)(vtable.instance) };
+    }
+
+    #[test]
+    fn empty_buffers_free_cleanly() {
+        let buffer = AbiBuffer::from_vec(Vec::new());
+        assert_eq!(buffer.as_slice(), &[] as &[u8]);
+        unsafe { free_buffer(buffer) };
+        let null = AbiBuffer { ptr: std::ptr::null_mut(), len: 0, capacity: 0 };
+        assert_eq!(null.as_slice(), &[] as &[u8]);
+        unsafe { free_buffer(null) };
+    }
+}
@@ -48,6 +48,9 @@ pub enum ModuleManagerError {
     #[error("Invalid module: {0}")]
     InvalidModule(String),
 
+    #[error("Incompatible module: {0}")]
+    IncompatibleModule(String),
+
     #[error("I/O error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -22,8 +22,10 @@
 //
 // Authors: I. Zeqiri, E. Gjergji 
 
-use std::{collections::HashMap, sync::Arc};
-use zark_waf_common::messenger::Messenger;
+use std::{collections::HashMap, ffi::c_void, sync::Arc};
+use zark_waf_common::inspection::{HttpRequest, HttpResponse, Inspection};
+use zark_waf_common::messenger::{topic_segment, LifecycleEvent, Messenger, Priority};
+pub mod abi;
 mod error;
 mod supervisor;
 mod loader;
@@ -34,41 +36,61 @@ pub use supervisor::ModuleSupervisor;
 pub use loader::ModuleLoader;
 pub use module::{Module, ModuleInfo};
 
+// topic module lifecycle events are published on, e.g. "module.dsl.started"
+pub fn module_topic(name: &str, event: &str) -> String {
+    format!("module.{}.{}", topic_segment(name), event)
+}
+
 pub struct ModuleManager {
+    // declared before the loader so modules are dropped before their libraries
     modules: HashMap<String, Box<dyn Module>>,
+    // module names in the order they were loaded
+    load_order: Vec<String>,
     loader: ModuleLoader,
     messenger: Arc<Messenger>,
 }
 
 impl ModuleManager {
-    pub fn new() -> Self {
+    pub fn new(messenger: Arc<Messenger>) -> Self {
         Self {
             modules: HashMap::new(),
+            load_order: Vec::new(),
             loader: ModuleLoader::new(),
-            messenger: Arc::new(Messenger::new("module_manager"))
+            messenger,
         }
     }
 
-    pub async fn load_module(&mut self, path: &str) -> Result<(), ModuleManagerError> {
-        let module = self.loader.load(path)?;
+    // load and initialize a module, returning the name it registered under
+    pub async fn load_module(&mut self, path: &str) -> Result<String, ModuleManagerError> {
+        let mut module = self.loader.load(path)?;
         let name = module.name().to_string();
 
+        if self.modules.contains_key(&name) {
+            return Err(ModuleManagerError::LoadError(format!("Module '{}' is already loaded", name)));
+        }
+
+        module.init(Arc::as_ptr(&self.messenger) as *mut c_void).await
+            .map_err(|e| ModuleManagerError::InitializationError(e.to_string()))?;
+
         self.modules.insert(name.clone(), module);
+        self.load_order.push(name.clone());
         
         // Notify about module loading
-        self.messenger.send("module_manager", format!("Module '{}' loaded", name).as_bytes()).await
+        self.messenger.send_typed_with_priority(&module_topic(&name, "loaded"), "module_manager", &LifecycleEvent::module(&name, "loaded"), Priority::Control).await
             .map_err(|e| ModuleManagerError::ExecutionError(e.to_string()))?;
 
-        Ok(())
+        Ok(name)
     }
 
     pub async fn unload_module(&mut self, name: &str) -> Result<(), ModuleManagerError> {
         if let Some(mut module) = self.modules.remove(name) {
+            self.load_order.retain(|n| n != name);
+
             module.shutdown().await
                 .map_err(|e| ModuleManagerError::ShutdownError(e.to_string()))?;
 
             // Notify about module unloading
-            self.messenger.send("module_manager", format!("Module '{}' unloaded", name).as_bytes()).await
+            self.messenger.send_typed_with_priority(&module_topic(name, "unloaded"), "module_manager", &LifecycleEvent::module(name, "unloaded"), Priority::Control).await
                 .map_err(|e| ModuleManagerError::ExecutionError(e.to_string()))?;
 
             Ok(())
@@ -83,7 +105,7 @@ impl ModuleManager {
                 .map_err(|e| ModuleManagerError::ExecutionError(e.to_string()))?;
 
             // Notify about module starting
-            self.messenger.send("module_manager", format!("Module '{}' started", name).as_bytes()).await
+            self.messenger.send_typed_with_priority(&module_topic(name, "started"), "module_manager", &LifecycleEvent::module(name, "started"), Priority::Control).await
                 .map_err(|e| ModuleManagerError::ExecutionError(e.to_string()))?;
         }
         Ok(())
@@ -95,12 +117,39 @@ impl ModuleManager {
                 .map_err(|e| ModuleManagerError::ShutdownError(e.to_string()))?;
 
             // Notify about module stopping
-            self.messenger.send("module_manager", format!("Module '{}' stopped", name).as_bytes()).await
+            self.messenger.send_typed_with_priority(&module_topic(name, "stopped"), "module_manager", &LifecycleEvent::module(name, "stopped"), Priority::Control).await
                 .map_err(|e| ModuleManagerError::ExecutionError(e.to_string()))?;
         }
         Ok(())
     }
 
+    // run a single module against the given input
+    pub async fn execute_module(&self, name: &str, input: serde_json::Value) -> Result<serde_json::Value, ModuleManagerError> {
+        let module = self.modules.get(name)
+            .ok_or_else(|| ModuleManagerError::ModuleNotFound(name.to_string()))?;
+        module.execute(input).await
+            .map_err(|e| ModuleManagerError::ExecutionError(e.to_string()))
+    }
+
+    pub async fn inspect_request(&self, name: &str, request: &HttpRequest) -> Result<Inspection, ModuleManagerError> {
+        let module = self.modules.get(name)
+            .ok_or_else(|| ModuleManagerError::ModuleNotFound(name.to_string()))?;
+        module.inspect_request(request).await
+            .map_err(|e| ModuleManagerError::ExecutionError(e.to_string()))
+    }
+
+    pub async fn inspect_response(&self, name: &str, request: &HttpRequest, response: &HttpResponse) -> Result<Inspection, ModuleManagerError> {
+        let module = self.modules.get(name)
+            .ok_or_else(|| ModuleManagerError::ModuleNotFound(name.to_string()))?;
+        module.inspect_response(request, response).await
+            .map_err(|e| ModuleManagerError::ExecutionError(e.to_string()))
+    }
+
+    // names of the loaded modules, in load order
+    pub fn module_names(&self) -> Vec<String> {
+        self.load_order.clone()
+    }
+
     pub async fn get_module_info(&self, name: &str) -> Result<ModuleInfo, ModuleManagerError> {
         self.modules.get(name)
             .ok_or(ModuleManagerError::ModuleNotFound(name.to_string()))
@@ -113,7 +162,7 @@ impl ModuleManager {
     }
 
     pub async fn list_modules(&self) -> Vec<ModuleInfo> {
-        self.modules.iter().map(|(_, module)| ModuleInfo {
+        self.load_order.iter().filter_map(|name| self.modules.get(name)).map(|module| ModuleInfo {
             name: module.name().to_string(),
             version: module.version().to_string(),
             description: module.description().to_string(),
@@ -22,39 +22,307 @@
 //
 // Authors: I. Zeqiri, E. Gjergji 
 
+use std::ffi::c_void;
+use std::future::Future;
 use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
 use libloading::{Library, Symbol};
+use zark_waf_common::inspection::{HttpRequest, HttpResponse, Inspection, InspectionCall};
+use crate::abi::{self, AbiResult, ModuleMetadata, ModuleVTable};
 use crate::error::ModuleManagerError;
 use crate::module::Module;
 
-pub struct ModuleLoader;
+// the code and vtable of every module live in its library, so libraries stay
+// open for as long as the loader does, unloaded modules' included: a module's
+// runtime threads keep running code from its library after the module is
+// gone, and closing it under them would crash the process. drop modules
+// before their loader.
+//
+// because the library stays open, loading a path again gets the code that was
+// loaded from it first, even if the file has been replaced since; a rebuilt
+// module needs a new file name or a restart.
+#[derive(Default)]
+pub struct ModuleLoader {
+    libraries: Mutex<Vec<Library>>,
+}
 
-type ModuleCreateFn = unsafe fn() -> *mut dyn Module;
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type MetadataFn = unsafe extern "C" fn() -> *const ModuleMetadata;
+type ModuleCreateFn = unsafe extern "C" fn() -> ModuleVTable;
 
 impl ModuleLoader {
     pub fn new() -> Self {
-        ModuleLoader
+        Self::default()
     }
 
+    // load a module library, refusing it unless it was built against this
+    // ABI version with this toolchain
     pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<Box<dyn Module>, ModuleManagerError> {
-        let path = path.as_ref().to_path_buf();
-        
+        let path = path.as_ref();
+        let incompatible = |reason: String| ModuleManagerError::IncompatibleModule(format!("{}: {}", path.display(), reason));
+
         // Load the dynamic library
         let lib = unsafe {
-            Library::new(path.clone()).map_err(|e| ModuleManagerError::LoadError(e.to_string()))?
+            Library::new(path).map_err(|e| ModuleManagerError::LoadError(e.to_string()))?
+        };
+
+        let abi_version: Symbol<AbiVersionFn> = unsafe {
+            lib.get(abi::ABI_VERSION_SYMBOL)
+                .map_err(|_| incompatible("no zark_module_abi_version symbol, not built with export_module!".to_string()))?
         };
+        let found = unsafe { abi_version() };
+        if found != abi::MODULE_ABI_VERSION {
+            return Err(incompatible(format!(
+                "built against module ABI version {}, expected {}",
+                found,
+                abi::MODULE_ABI_VERSION
+            )));
+        }
+
+        let metadata: Symbol<MetadataFn> = unsafe {
+            lib.get(abi::METADATA_SYMBOL)
+                .map_err(|e| incompatible(format!("Failed to find 'zark_module_metadata' symbol: {}", e)))?
+        };
+        let metadata = unsafe { metadata().as_ref() }
+            .ok_or_else(|| ModuleManagerError::InvalidModule(format!("{}: no module metadata", path.display())))?;
+        let (name, version, description) = unsafe { validate(path, metadata)? };
 
-        // Look up the `create_module` symbol
         let constructor: Symbol<ModuleCreateFn> = unsafe {
-            lib.get(b"create_module")
-                .map_err(|e| ModuleManagerError::LoadError(format!("Failed to find 'create_module' symbol: {}", e)))?
+            lib.get(abi::CREATE_SYMBOL)
+                .map_err(|e| ModuleManagerError::LoadError(format!("Failed to find 'zark_create_module' symbol: {}", e)))?
         };
+        let vtable = unsafe { constructor() };
+        if vtable.instance.is_null() {
+            return Err(ModuleManagerError::InitializationError(format!("{}: module constructor returned null", name)));
+        }
 
-        // Call the constructor
-        let module = unsafe {
-            Box::from_raw(constructor())
+        let module = AbiModule {
+            name,
+            version,
+            description,
+            instance: Arc::new(Instance(vtable)),
         };
+        self.libraries.lock().unwrap_or_else(|e| e.into_inner()).push(lib);
+        Ok(Box::new(module))
+    }
+}
+
+// the name, version and description of a module whose metadata says it was
+// built against this ABI version with this toolchain.
+//
+// safety: the metadata must come from a library that is still loaded
+unsafe fn validate(path: &Path, metadata: &ModuleMetadata) -> Result<(String, String, String), ModuleManagerError> {
+    let incompatible = |reason: String| ModuleManagerError::IncompatibleModule(format!("{}: {}", path.display(), reason));
+    if metadata.abi_version != abi::MODULE_ABI_VERSION {
+        return Err(incompatible(format!(
+            "metadata is for module ABI version {}, expected {}",
+            metadata.abi_version,
+            abi::MODULE_ABI_VERSION
+        )));
+    }
+    let text = |field: &str, value: abi::AbiStr| {
+        value.read().ok_or_else(|| {
+            ModuleManagerError::InvalidModule(format!("{}: module {} is not valid utf-8", path.display(), field))
+        })
+    };
+    let toolchain = text("toolchain", metadata.toolchain)?;
+    if toolchain != abi::TOOLCHAIN {
+        return Err(incompatible(format!("built with {}, the core with {}", toolchain, abi::TOOLCHAIN)));
+    }
+    Ok((text("name", metadata.name)?, text("version", metadata.version)?, text("description", metadata.description)?))
+}
+
+// a constructed module, destroyed by its library once no call is using it
+struct Instance(ModuleVTable);
+
+// modules are Send + Sync, and the module side serializes &mut calls
+unsafe impl Send for Instance {}
+unsafe impl Sync for Instance {}
+
+impl Instance {
+    // copy a result out of module memory and hand the buffer back
+    fn take(&self, result: AbiResult) -> Result<Vec<u8>, String> {
+        let bytes = result.buffer.as_slice().to_vec();
+        unsafe { (self.0.free_buffer)(result.buffer) };
+        if result.ok {
+            Ok(bytes)
+        } else {
+            Err(String::from_utf8_lossy(&bytes).into_owned())
+        }
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe { (self.0.destroy)(self.0.instance) };
+    }
+}
+
+// a loaded module seen through its vtable. calls block on the module's own
+// runtime, so they run on tokio's blocking pool
+struct AbiModule {
+    name: String,
+    version: String,
+    description: String,
+    instance: Arc<Instance>,
+}
+
+impl AbiModule {
+    async fn call(
+        &self,
+        f: impl FnOnce(&ModuleVTable) -> AbiResult + Send + 'static,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let instance = self.instance.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let result = f(&instance.0);
+            instance.take(result)
+        })
+        .await?;
+        Ok(result?)
+    }
+}
+
+#[async_trait]
+impl Module for AbiModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    // written out by hand: an async fn would hold the raw pointer, which
+    // isn't Send, across awaits
+    fn init<'life0, 'async_trait>(
+        &'life0 mut self,
+        messenger: *mut c_void,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Box<dyn std::error::Error>>> + Send + 'async_trait>>
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        // the messenger outlives every module
+        let messenger = messenger as usize;
+        Box::pin(async move {
+            self.call(move |vtable| unsafe { (vtable.init)(vtable.instance, messenger as *mut c_void) }).await?;
+            Ok(())
+        })
+    }
+
+    async fn execute(&self, input: serde_json::Value) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        let input = serde_json::to_vec(&input)?;
+        let output = self
+            .call(move |vtable| unsafe { (vtable.execute)(vtable.instance, input.as_ptr(), input.len()) })
+            .await?;
+        Ok(serde_json::from_slice(&output)?)
+    }
+
+    async fn shutdown(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.call(|vtable| unsafe { (vtable.shutdown)(vtable.instance) }).await?;
+        Ok(())
+    }
+
+    // inspect_request's default already goes through execute; responses
+    // have to be asked for too, the module decides whether it looks at them
+    async fn inspect_response(&self, request: &HttpRequest, response: &HttpResponse) -> Result<Inspection, Box<dyn std::error::Error>> {
+        let output = self.execute(InspectionCall::response(request, response)?).await?;
+        Ok(Inspection::from_output(output)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use zark_waf_common::inspection::Verdict;
+    use crate::abi::tests::Echo;
+    use crate::abi::AbiStr;
+
+    fn metadata(abi_version: u32, toolchain: &'static str) -> ModuleMetadata {
+        ModuleMetadata {
+            abi_version,
+            toolchain: AbiStr::new(toolchain),
+            name: AbiStr::new("echo"),
+            version: AbiStr::new("1.0.0"),
+            description: AbiStr::new("echoes its input"),
+        }
+    }
+
+    fn refusal(metadata: ModuleMetadata) -> String {
+        match unsafe { validate(Path::new("echo.so"), &metadata) } {
+            Err(ModuleManagerError::IncompatibleModule(reason)) => reason,
+            other => panic!("expected IncompatibleModule, got {:?}", other.map_err(|e| e.to_string())),
+        }
+    }
+
+    #[test]
+    fn compatible_metadata_is_read() {
+        let read = unsafe { validate(Path::new("echo.so"), &metadata(abi::MODULE_ABI_VERSION, abi::TOOLCHAIN)) }.unwrap();
+        assert_eq!(read, ("echo".to_string(), "1.0.0".to_string(), "echoes its input".to_string()));
+    }
+
+    #[test]
+    fn other_abi_versions_are_refused() {
+        let reason = refusal(metadata(abi::MODULE_ABI_VERSION + 1, abi::TOOLCHAIN));
+        assert!(reason.starts_with("echo.so: metadata is for module ABI version"), "{}", reason);
+    }
+
+    #[test]
+    fn other_toolchains_are_refused() {
+        let reason = refusal(metadata(abi::MODULE_ABI_VERSION, "rustc 1.0.0 (a59807616 2015-05-13)"));
+        assert!(reason.contains("built with rustc 1.0.0"), "{}", reason);
+    }
+
+    #[test]
+    fn libraries_without_a_module_are_refused() {
+        match ModuleLoader::new().load("libc.so.6") {
+            Err(ModuleManagerError::IncompatibleModule(reason)) => {
+                assert!(reason.contains("not built with export_module!"), "{}", reason)
+            }
+            Err(e) => panic!("expected IncompatibleModule, got {}", e),
+            Ok(_) => panic!("libc loaded as a module"),
+        }
+    }
+
+    fn echo() -> (AbiModule, Arc<AtomicBool>) {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let vtable = ModuleVTable::new(Echo { dropped: dropped.clone() });
+        let module = AbiModule {
+            name: "echo".to_string(),
+            version: "1.0.0".to_string(),
+            description: "echoes its input".to_string(),
+            instance: Arc::new(Instance(vtable)),
+        };
+        (module, dropped)
+    }
+
+    #[tokio::test]
+    async fn calls_cross_the_vtable() {
+        let (mut module, dropped) = echo();
+        module.init(std::ptr::null_mut()).await.unwrap();
+        let input = serde_json::json!({"nested": {"list": [1, "two", null]}});
+        assert_eq!(module.execute(input.clone()).await.unwrap(), input);
+        let error = module.execute(serde_json::json!({"fail": "no thanks"})).await.unwrap_err();
+        assert_eq!(error.to_string(), "no thanks");
+
+        let request = HttpRequest::new("POST", "/").with_body("DROP TABLE users");
+        let inspection = module.inspect_request(&request).await.unwrap();
+        assert_eq!(inspection.verdict, Verdict::block("echo", "drop"));
+
+        let error = module.execute(serde_json::json!({"panic": "boom"})).await.unwrap_err();
+        assert_eq!(error.to_string(), "Module panicked: boom");
+        let error = module.shutdown().await.unwrap_err();
+        assert_eq!(error.to_string(), "Module panicked: static panic message");
 
-        Ok(module)
+        assert!(!dropped.load(Ordering::SeqCst));
+        drop(module);
+        assert!(dropped.load(Ordering::SeqCst));
     }
-}
\ No newline at end of file
+}
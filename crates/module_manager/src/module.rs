@@ -26,6 +26,7 @@ use std::ffi::c_void;
 
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
+use zark_waf_common::inspection::{HttpRequest, HttpResponse, Inspection, InspectionCall};
 
 #[async_trait]
 pub trait Module: Send + Sync {
@@ -35,6 +36,18 @@ pub trait Module: Send + Sync {
     async fn init(&mut self, messenger: *mut c_void) -> Result<(), Box<dyn std::error::Error>>;
     async fn execute(&self, input: serde_json::Value) -> Result<serde_json::Value, Box<dyn std::error::Error>>;
     async fn shutdown(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+
+    // typed inspection. by default the request goes through execute as an
+    // InspectionCall, for modules that only implement execute
+    async fn inspect_request(&self, request: &HttpRequest) -> Result<Inspection, Box<dyn std::error::Error>> {
+        let output = self.execute(InspectionCall::request(request)?).await?;
+        Ok(Inspection::from_output(output)?)
+    }
+
+    // modules that don't look at responses let every one through
+    async fn inspect_response(&self, _request: &HttpRequest, _response: &HttpResponse) -> Result<Inspection, Box<dyn std::error::Error>> {
+        Ok(Inspection::default())
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -27,7 +27,8 @@ use dashmap::DashMap;
 use std::sync::Arc;
 
 use futures::future::join_all;
-use zark_waf_common::messenger::Messenger;
+use zark_waf_common::messenger::{LifecycleEvent, Messenger, Priority};
+use crate::module_topic;
 use crate::error::ModuleManagerError;
 use crate::module::{Module, ModuleInfo, ModuleStatus};
 
@@ -49,7 +50,7 @@ impl ModuleSupervisor {
     pub async fn add_module(&self, name: String, module: Arc<RwLock<Box<dyn Module>>>) -> Result<(), ModuleManagerError> {
         self.modules.insert(name.clone(), module);
         // notify about module addition
-        self.messenger.send( "module_", b"module added").await
+        self.messenger.send_typed_with_priority(&module_topic(&name, "added"), "module_supervisor", &LifecycleEvent::module(&name, "added"), Priority::Control).await
             .map_err(|e| ModuleManagerError::InitializationError(e.to_string()))?;
         Ok(())
     }
@@ -60,7 +61,7 @@ impl ModuleSupervisor {
             module.shutdown().await
                 .map_err(|e| ModuleManagerError::ShutdownError(e.to_string()))?;
             // notify about module removal
-            self.messenger.send("module_", b"module removed").await
+            self.messenger.send_typed_with_priority(&module_topic(name, "removed"), "module_supervisor", &LifecycleEvent::module(name, "removed"), Priority::Control).await
                 .map_err(|e| ModuleManagerError::InvalidModule(e.to_string()))?;
             Ok(())
         } else {
@@ -70,13 +71,14 @@ impl ModuleSupervisor {
 
     pub async fn start_all(&self) -> Result<(), ModuleManagerError> {
         let futures: Vec<_> = self.modules.iter().map(|entry| {
+            let name = entry.key().clone();
             let module = entry.value().clone();
             async move {
                 let module = module.read().await;
                 module.execute(serde_json::json!({"action": "start"})).await
                     .map_err(|e| ModuleManagerError::ExecutionError(e.to_string()))?;
                 // notify about module start
-                self.messenger.send("module_", b"module started").await
+                self.messenger.send_typed_with_priority(&module_topic(&name, "started"), "module_supervisor", &LifecycleEvent::module(&name, "started"), Priority::Control).await
                     .map_err(|e| ModuleManagerError::LoadError(e.to_string()))?;
                 Ok(())
             }
@@ -88,12 +90,12 @@ impl ModuleSupervisor {
     }
 
     pub async fn stop_all(&self) -> Result<(), ModuleManagerError> {
-        for module in self.modules.iter() {
-            let mut module = module.value().write().await;
+        for entry in self.modules.iter() {
+            let mut module = entry.value().write().await;
             module.shutdown().await
                 .map_err(|e| ModuleManagerError::ShutdownError(e.to_string()))?;
             // notify about module stop
-            self.messenger.send("module_", b"module stopped").await
+            self.messenger.send_typed_with_priority(&module_topic(entry.key(), "stopped"), "module_supervisor", &LifecycleEvent::module(entry.key(), "stopped"), Priority::Control).await
                 .map_err(|e| ModuleManagerError::ShutdownError(e.to_string()))?;
         }
         Ok(())
@@ -26,17 +26,28 @@ mod error;
 mod plugin;
 mod manager;
 mod loader;
+pub mod manifest;
+mod version;
 
 pub use error::PluginError;
 pub use plugin::{Plugin, PluginMetadata};
 pub use manager::PluginManager;
 pub use loader::PluginLoader;
+pub use manifest::{PluginManifest, ValidManifest};
+pub use version::{Version, VersionReq};
 
 use std::sync::Arc;
-use zark_waf_common::messenger::Messenger;
+use zark_waf_common::inspection::{HttpRequest, HttpResponse, Inspection};
+use zark_waf_common::messenger::{topic_segment, LifecycleEvent, Messenger, Priority};
+
+/// Topic plugin lifecycle events are published on, e.g. `plugin.geoip.loaded`.
+pub fn plugin_topic(name: &str, event: &str) -> String {
+    format!("plugin.{}.{}", topic_segment(name), event)
+}
 
 /// A system for managing plugins.
 pub struct PluginSystem {
+    // declared before the loader so plugins are dropped before their libraries
     manager: PluginManager,
     loader: PluginLoader,
     messenger: Arc<Messenger>,
@@ -52,16 +63,16 @@ impl PluginSystem {
         }
     }
 
-    /// Loads a plugin from the given path.
+    /// Loads a plugin from the given path and returns the name it registered under.
     ///
     /// # Errors
     ///
     /// Returns a `PluginError` if the plugin cannot be loaded.
-    pub async fn load_plugin(&self, path: &str) -> Result<(), PluginError> {
+    pub async fn load_plugin(&self, path: &str) -> Result<String, PluginError> {
         let plugin = self.loader.load(path).await?;
-        self.manager.add_plugin(plugin).await?;
-        match self.messenger.send("plugins", b"plugin loaded").await {
-            Ok(_) => Ok(()),
+        let name = self.manager.add_plugin(plugin).await?;
+        match self.messenger.send_typed_with_priority(&plugin_topic(&name, "loaded"), "plugin_system", &LifecycleEvent::plugin(&name, "loaded"), Priority::Control).await {
+            Ok(_) => Ok(name),
             Err(e) => Err(PluginError::InitializationError(e.to_string())),
         }
     }
@@ -72,7 +83,7 @@ impl PluginSystem {
     /// Returns a `PluginError` if the plugin cannot be unloaded.
     pub async fn unload_plugin(&self, name: &str) -> Result<(), PluginError> {
         self.manager.remove_plugin(name).await?;
-        match self.messenger.send("plugins", b"plugin unloaded").await {
+        match self.messenger.send_typed_with_priority(&plugin_topic(name, "unloaded"), "plugin_system", &LifecycleEvent::plugin(name, "unloaded"), Priority::Control).await {
             Ok(_) => Ok(()),
             Err(e) => Err(PluginError::InitializationError(e.to_string())),
         }
@@ -85,12 +96,40 @@ impl PluginSystem {
     /// Returns a `PluginError` if the plugin cannot be executed.
     pub async fn execute_plugin(&self, name: &str, input: serde_json::Value) -> Result<serde_json::Value, PluginError> {
         let result = self.manager.execute_plugin(name, input).await?;
-        match self.messenger.send("plugins", b"plugin executed").await {
+        match self.messenger.send_typed_with_priority(&plugin_topic(name, "executed"), "plugin_system", &LifecycleEvent::plugin(name, "executed"), Priority::Control).await {
             Ok(_) => Ok(result),
             Err(e) => Err(PluginError::InitializationError(e.to_string())),
         }
     }
 
+    /// Inspects a request with a plugin's typed `inspect_request`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `PluginError` if the plugin cannot inspect the request.
+    pub async fn inspect_request(&self, name: &str, request: &HttpRequest) -> Result<Inspection, PluginError> {
+        let inspection = self.manager.inspect_request(name, request).await?;
+        self.executed(name).await?;
+        Ok(inspection)
+    }
+
+    /// Inspects a response, together with the request it answers, with a
+    /// plugin's typed `inspect_response`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `PluginError` if the plugin cannot inspect the response.
+    pub async fn inspect_response(&self, name: &str, request: &HttpRequest, response: &HttpResponse) -> Result<Inspection, PluginError> {
+        let inspection = self.manager.inspect_response(name, request, response).await?;
+        self.executed(name).await?;
+        Ok(inspection)
+    }
+
+    async fn executed(&self, name: &str) -> Result<(), PluginError> {
+        self.messenger.send_typed_with_priority(&plugin_topic(name, "executed"), "plugin_system", &LifecycleEvent::plugin(name, "executed"), Priority::Control).await
+            .map(|_| ())
+            .map_err(|e| PluginError::InitializationError(e.to_string()))
+    }
 
     /// Gets metadata for a specific plugin.
     ///
@@ -101,8 +140,13 @@ impl PluginSystem {
         self.manager.get_plugin_metadata(name).await
     }
 
-    /// Lists all loaded plugins.
+    /// Returns the names of the loaded plugins, in load order.
+    pub async fn plugin_names(&self) -> Vec<String> {
+        self.manager.plugin_names().await
+    }
+
+    /// Lists all loaded plugins, in load order.
     pub async fn list_plugins(&self) -> Vec<PluginMetadata> {
-        self.manager.list_plugins()
+        self.manager.list_plugins().await
     }
 }
\ No newline at end of file
@@ -23,24 +23,45 @@
 // Authors: I. Zeqiri, E. Gjergji 
 
 use std::path::Path;
+use std::sync::Mutex;
 use libloading::{Library, Symbol};
 use crate::error::PluginError;
+use crate::manifest::{self, PluginManifest};
 use crate::plugin::{Plugin, PluginCreate};
 
-pub struct PluginLoader;
+type ManifestFn = unsafe extern "C" fn() -> *const PluginManifest;
+
+// the code and vtable of every plugin live in its library, so libraries stay
+// open for as long as the loader does. drop plugins before their loader.
+#[derive(Default)]
+pub struct PluginLoader {
+    libraries: Mutex<Vec<Library>>,
+}
 
 impl PluginLoader {
     pub fn new() -> Self {
-        PluginLoader
+        Self::default()
     }
 
+    // load a plugin library, refusing it unless its manifest says it was
+    // built for this host
     pub async fn load<P: AsRef<Path>>(&self, path: P) -> Result<Box<dyn Plugin>, PluginError> {
-        let path = path.as_ref().to_path_buf();
-        
+        let path = path.as_ref();
+        let invalid = |reason: String| PluginError::InvalidPlugin(format!("{}: {}", path.display(), reason));
+
         // Load the dynamic library
         let lib = unsafe {
-            Library::new(path.clone()).map_err(|e| PluginError::LoadError(e.to_string()))?
+            Library::new(path).map_err(|e| PluginError::LoadError(e.to_string()))?
+        };
+
+        // Read and check the manifest before running any plugin code
+        let manifest: Symbol<ManifestFn> = unsafe {
+            lib.get(manifest::MANIFEST_SYMBOL)
+                .map_err(|_| invalid("no zark_plugin_manifest symbol, not built with export_plugin!".to_string()))?
         };
+        let manifest = unsafe { manifest().as_ref() }
+            .ok_or_else(|| invalid("empty manifest".to_string()))?;
+        let manifest = unsafe { manifest.validate() }.map_err(invalid)?;
 
         // Look up the `create_plugin` symbol
         let constructor: Symbol<PluginCreate> = unsafe {
@@ -52,7 +73,15 @@ impl PluginLoader {
         let plugin = unsafe {
             Box::from_raw(constructor())
         };
+        if plugin.name() != manifest.name {
+            return Err(invalid(format!("plugin calls itself '{}', its manifest '{}'", plugin.name(), manifest.name)));
+        }
 
+        log::debug!(
+            "Plugin {} {} for host {} loaded from {} with capabilities [{}]",
+            manifest.name, manifest.version, manifest.host_version, path.display(), manifest.capabilities.join(", ")
+        );
+        self.libraries.lock().unwrap_or_else(|e| e.into_inner()).push(lib);
         Ok(plugin)
     }
-}
\ No newline at end of file
+}
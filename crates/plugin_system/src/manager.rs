@@ -25,12 +25,15 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use dashmap::DashMap;
+use zark_waf_common::inspection::{HttpRequest, HttpResponse, Inspection};
 use zark_waf_common::messenger::Messenger;
 use crate::error::PluginError;
 use crate::plugin::{Plugin, PluginMetadata, PluginStatus};
 
 pub struct PluginManager {
     plugins: DashMap<String, Arc<RwLock<Box<dyn Plugin>>>>,
+    // plugin names in the order they were added
+    load_order: RwLock<Vec<String>>,
     messenger: Arc<Messenger>,
 }
 
@@ -39,22 +42,28 @@ impl PluginManager {
     pub fn new(messenger: Arc<Messenger>) -> Self {
         Self {
             plugins: DashMap::new(),
+            load_order: RwLock::new(Vec::new()),
             messenger,
         }
     }
 
-    // add a plugin to the manager
-    pub async fn add_plugin(&self, mut plugin: Box<dyn Plugin>) -> Result<(), PluginError> {
+    // add a plugin to the manager, returning the name it registered under
+    pub async fn add_plugin(&self, mut plugin: Box<dyn Plugin>) -> Result<String, PluginError> {
         let name = plugin.name().to_string();
+        if self.plugins.contains_key(&name) {
+            return Err(PluginError::LoadError(format!("Plugin '{}' is already loaded", name)));
+        }
         plugin.init(&self.messenger).await
             .map_err(|e| PluginError::InitializationError(e.to_string()))?;
         self.plugins.insert(name.clone(), Arc::new(RwLock::new(plugin)));
-        Ok(())
+        self.load_order.write().await.push(name.clone());
+        Ok(name)
     }
 
     // remove a plugin from the manager
     pub async fn remove_plugin(&self, name: &str) -> Result<(), PluginError> {
         if let Some((_, plugin)) = self.plugins.remove(name) {
+            self.load_order.write().await.retain(|n| n != name);
             let mut plugin = plugin.write().await;
             plugin.shutdown().await
                 .map_err(|e| PluginError::ShutdownError(e.to_string()))?;
@@ -75,6 +84,31 @@ impl PluginManager {
         }
     }
 
+    pub async fn inspect_request(&self, name: &str, request: &HttpRequest) -> Result<Inspection, PluginError> {
+        if let Some(plugin) = self.plugins.get(name) {
+            let plugin = plugin.read().await;
+            plugin.inspect_request(request).await
+                .map_err(|e| PluginError::ExecutionError(e.to_string()))
+        } else {
+            Err(PluginError::PluginNotFound(name.to_string()))
+        }
+    }
+
+    pub async fn inspect_response(&self, name: &str, request: &HttpRequest, response: &HttpResponse) -> Result<Inspection, PluginError> {
+        if let Some(plugin) = self.plugins.get(name) {
+            let plugin = plugin.read().await;
+            plugin.inspect_response(request, response).await
+                .map_err(|e| PluginError::ExecutionError(e.to_string()))
+        } else {
+            Err(PluginError::PluginNotFound(name.to_string()))
+        }
+    }
+
+    // names of the loaded plugins, in the order they were added
+    pub async fn plugin_names(&self) -> Vec<String> {
+        self.load_order.read().await.clone()
+    }
+
     // get metadata for a specific plugin
     pub async fn get_plugin_metadata(&self, name: &str) -> Result<PluginMetadata, PluginError> {
         if let Some(plugin) = self.plugins.get(name) {
@@ -90,16 +124,14 @@ impl PluginManager {
         }
     }
 
-    // list all plugins
-    pub fn list_plugins(&self) -> Vec<PluginMetadata> {
-        self.plugins.iter().map(|entry| {
-            let plugin = entry.value().blocking_read();
-            PluginMetadata {
-                name: plugin.name().to_string(),
-                version: plugin.version().to_string(),
-                description: plugin.description().to_string(),
-                status: PluginStatus::Running, 
+    // list all plugins, in the order they were added
+    pub async fn list_plugins(&self) -> Vec<PluginMetadata> {
+        let mut plugins = Vec::new();
+        for name in self.plugin_names().await {
+            if let Ok(metadata) = self.get_plugin_metadata(&name).await {
+                plugins.push(metadata);
             }
-        }).collect()
+        }
+        plugins
     }
 }
\ No newline at end of file
@@ -0,0 +1,163 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// what a plugin library says about itself, read before the plugin is created.
+// a library exports it as zark_plugin_manifest, generated by export_plugin!
+// together with create_plugin. plugins are passed as trait objects, so a
+// plugin must be built against this ABI version by the core's toolchain.
+
+// export_plugin! refers to these through this module
+pub use zark_waf_common::abi::{AbiStr, TOOLCHAIN};
+
+use crate::version::{Version, VersionReq};
+
+// bump on any change to the manifest or to the Plugin trait
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+pub const MANIFEST_SYMBOL: &[u8] = b"zark_plugin_manifest";
+
+// version of the plugin host, matched against a plugin's host_version. this
+// crate can't see the core's version, so the two are released in lockstep;
+// the core has a test that fails when they drift apart
+pub const HOST_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// what the host offers plugins; a plugin declaring anything else needs a
+// newer host
+pub const CAPABILITIES: &[&str] = &["execute", "inspect_request", "inspect_response", "messenger"];
+
+#[repr(C)]
+pub struct PluginManifest {
+    pub abi_version: u32,
+    pub toolchain: AbiStr,
+    pub name: AbiStr,
+    pub version: AbiStr,
+    // requirement on HOST_VERSION, e.g. "^0.1"
+    pub host_version: AbiStr,
+    pub capabilities: *const AbiStr,
+    pub capabilities_len: usize,
+}
+
+// built by export_plugin! from string literals
+unsafe impl Sync for PluginManifest {}
+
+// a manifest that passed validation
+#[derive(Debug, Clone)]
+pub struct ValidManifest {
+    pub name: String,
+    pub version: String,
+    pub host_version: String,
+    pub capabilities: Vec<String>,
+}
+
+impl PluginManifest {
+    /// Checks the manifest against this host, returning why the plugin
+    /// can't be loaded if it can't.
+    ///
+    /// # Safety
+    ///
+    /// The manifest must come from a library that is still loaded.
+    pub unsafe fn validate(&self) -> Result<ValidManifest, String> {
+        if self.abi_version != PLUGIN_ABI_VERSION {
+            return Err(format!(
+                "built against plugin ABI version {}, expected {}",
+                self.abi_version, PLUGIN_ABI_VERSION
+            ));
+        }
+        let text = |field: &str, value: AbiStr| value.read().ok_or_else(|| format!("manifest {} is not valid utf-8", field));
+        let toolchain = text("toolchain", self.toolchain)?;
+        if toolchain != TOOLCHAIN {
+            return Err(format!("built with {}, the core with {}", toolchain, TOOLCHAIN));
+        }
+
+        let name = text("name", self.name)?;
+        if name.is_empty() {
+            return Err("manifest has no name".to_string());
+        }
+        let version = text("version", self.version)?;
+
+        let host_version = text("host version", self.host_version)?;
+        let requirement = VersionReq::parse(&host_version).map_err(|e| format!("invalid host version requirement: {}", e))?;
+        let host = Version::parse(HOST_VERSION)?;
+        if !requirement.matches(&host) {
+            return Err(format!("requires host version {}, this host is {}", host_version, host));
+        }
+
+        let capabilities = if self.capabilities.is_null() {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(self.capabilities, self.capabilities_len)
+                .iter()
+                .map(|capability| text("capability", *capability))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        if let Some(unknown) = capabilities.iter().find(|capability| !CAPABILITIES.contains(&capability.as_str())) {
+            return Err(format!("requires capability '{}', which this host doesn't provide", unknown));
+        }
+
+        Ok(ValidManifest { name, version, host_version, capabilities })
+    }
+}
+
+// export a plugin from a cdylib:
+//
+//     export_plugin! {
+//         name: "geoip",
+//         version: env!("CARGO_PKG_VERSION"),
+//         host_version: "^0.1",
+//         capabilities: ["inspect_request", "messenger"],
+//         create: || GeoIp::new(),
+//     }
+//
+// name must match what the plugin reports
+#[macro_export]
+macro_rules! export_plugin {
+    (
+        name: $name:expr,
+        version: $version:expr,
+        host_version: $host_version:expr,
+        capabilities: [$($capability:expr),* $(,)?],
+        create: $create:expr $(,)?
+    ) => {
+        #[no_mangle]
+        pub extern "C" fn zark_plugin_manifest() -> *const $crate::manifest::PluginManifest {
+            static CAPABILITIES: &[$crate::manifest::AbiStr] = &[$($crate::manifest::AbiStr::new($capability)),*];
+            static MANIFEST: $crate::manifest::PluginManifest = $crate::manifest::PluginManifest {
+                abi_version: $crate::manifest::PLUGIN_ABI_VERSION,
+                toolchain: $crate::manifest::AbiStr::new($crate::manifest::TOOLCHAIN),
+                name: $crate::manifest::AbiStr::new($name),
+                version: $crate::manifest::AbiStr::new($version),
+                host_version: $crate::manifest::AbiStr::new($host_version),
+                capabilities: CAPABILITIES.as_ptr(),
+                capabilities_len: CAPABILITIES.len(),
+            };
+            &MANIFEST
+        }
+
+        #[no_mangle]
+        pub fn create_plugin() -> *mut dyn $crate::Plugin {
+            let plugin: Box<dyn $crate::Plugin> = Box::new(($create)());
+            Box::into_raw(plugin)
+        }
+    };
+}
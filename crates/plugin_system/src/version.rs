@@ -0,0 +1,251 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// just enough semver for plugins to say which hosts they run on: versions are
+// major.minor.patch, requirements are comma-separated comparators that must
+// all hold, in cargo's syntax ("^0.1", ">=0.1.2, <0.3", "~1.2", "*")
+
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self { major, minor, patch }
+    }
+
+    pub fn parse(version: &str) -> Result<Self, String> {
+        let partial = Partial::parse(version)?;
+        match (partial.minor, partial.patch) {
+            (Some(minor), Some(patch)) => Ok(Self::new(partial.major, minor, patch)),
+            _ => Err(format!("'{}' is not a major.minor.patch version", version)),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+// a version with trailing parts left out, as written in a comparator
+#[derive(Debug, Clone, Copy)]
+struct Partial {
+    major: u64,
+    minor: Option<u64>,
+    patch: Option<u64>,
+}
+
+impl Partial {
+    fn parse(version: &str) -> Result<Self, String> {
+        let invalid = || format!("'{}' is not a valid version", version);
+        let mut parts = version.trim().split('.');
+        let mut next = || -> Result<Option<u64>, String> {
+            parts.next().map(|part| part.parse().map_err(|_| invalid())).transpose()
+        };
+        let major = next()?.ok_or_else(invalid)?;
+        let minor = next()?;
+        let patch = if minor.is_some() { next()? } else { None };
+        if next()?.is_some() {
+            return Err(invalid());
+        }
+        Ok(Self { major, minor, patch })
+    }
+
+    // the lowest version this one stands for
+    fn floor(&self) -> Version {
+        Version::new(self.major, self.minor.unwrap_or(0), self.patch.unwrap_or(0))
+    }
+
+    // the lowest version above every version this one stands for
+    fn ceiling(&self) -> Version {
+        match (self.minor, self.patch) {
+            (Some(minor), Some(patch)) => Version::new(self.major, minor, patch + 1),
+            (Some(minor), None) => Version::new(self.major, minor + 1, 0),
+            _ => Version::new(self.major + 1, 0, 0),
+        }
+    }
+
+    // the lowest version a caret requirement on this one excludes
+    fn caret_ceiling(&self) -> Version {
+        match (self.major, self.minor, self.patch) {
+            (0, Some(0), Some(patch)) => Version::new(0, 0, patch + 1),
+            (0, Some(minor), _) => Version::new(0, minor + 1, 0),
+            (major, _, _) => Version::new(major + 1, 0, 0),
+        }
+    }
+
+    fn tilde_ceiling(&self) -> Version {
+        match self.minor {
+            Some(minor) => Version::new(self.major, minor + 1, 0),
+            None => Version::new(self.major + 1, 0, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Caret,
+    Tilde,
+}
+
+#[derive(Debug, Clone)]
+pub struct VersionReq {
+    comparators: Vec<(Op, Partial)>,
+}
+
+impl VersionReq {
+    pub fn parse(requirement: &str) -> Result<Self, String> {
+        let requirement = requirement.trim();
+        if requirement.is_empty() {
+            return Err("empty version requirement".to_string());
+        }
+        if requirement == "*" {
+            return Ok(Self { comparators: Vec::new() });
+        }
+        let comparators = requirement
+            .split(',')
+            .map(|comparator| {
+                let comparator = comparator.trim();
+                let (op, version) = [
+                    (">=", Op::GreaterEq),
+                    ("<=", Op::LessEq),
+                    (">", Op::Greater),
+                    ("<", Op::Less),
+                    ("=", Op::Exact),
+                    ("^", Op::Caret),
+                    ("~", Op::Tilde),
+                ]
+                .into_iter()
+                .find_map(|(prefix, op)| comparator.strip_prefix(prefix).map(|version| (op, version)))
+                // a bare version is a caret requirement, as in cargo
+                .unwrap_or((Op::Caret, comparator));
+                Ok((op, Partial::parse(version)?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { comparators })
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        self.comparators.iter().all(|(op, partial)| {
+            let at_least = |floor: Version| version.cmp(&floor) != Ordering::Less;
+            let below = |ceiling: Version| version.cmp(&ceiling) == Ordering::Less;
+            match op {
+                Op::Exact => at_least(partial.floor()) && below(partial.ceiling()),
+                Op::Greater => at_least(partial.ceiling()),
+                Op::GreaterEq => at_least(partial.floor()),
+                Op::Less => below(partial.floor()),
+                Op::LessEq => below(partial.ceiling()),
+                Op::Caret => at_least(partial.floor()) && below(partial.caret_ceiling()),
+                Op::Tilde => at_least(partial.floor()) && below(partial.tilde_ceiling()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(requirement: &str, version: &str) -> bool {
+        VersionReq::parse(requirement)
+            .unwrap()
+            .matches(&Version::parse(version).unwrap())
+    }
+
+    fn check(requirement: &str, matching: &[&str], not_matching: &[&str]) {
+        for version in matching {
+            assert!(matches(requirement, version), "{} should match {}", requirement, version);
+        }
+        for version in not_matching {
+            assert!(!matches(requirement, version), "{} should not match {}", requirement, version);
+        }
+    }
+
+    #[test]
+    fn caret() {
+        check("^1.2.3", &["1.2.3", "1.9.0"], &["1.2.2", "2.0.0"]);
+        check("^1", &["1.0.0", "1.99.99"], &["0.9.9", "2.0.0"]);
+        check("^0.2.3", &["0.2.3", "0.2.9"], &["0.2.2", "0.3.0"]);
+        check("^0.2", &["0.2.0", "0.2.9"], &["0.1.9", "0.3.0"]);
+        check("^0.0.3", &["0.0.3"], &["0.0.2", "0.0.4", "0.1.0"]);
+        check("^0.0", &["0.0.0", "0.0.9"], &["0.1.0"]);
+        check("^0", &["0.0.0", "0.9.9"], &["1.0.0"]);
+        // a bare version means the same
+        check("0.2", &["0.2.5"], &["0.3.0"]);
+    }
+
+    #[test]
+    fn tilde() {
+        check("~1.2.3", &["1.2.3", "1.2.9"], &["1.2.2", "1.3.0"]);
+        check("~1.2", &["1.2.0", "1.2.9"], &["1.1.9", "1.3.0"]);
+        check("~1", &["1.0.0", "1.9.9"], &["0.9.9", "2.0.0"]);
+        check("~0.1", &["0.1.0", "0.1.5"], &["0.2.0"]);
+    }
+
+    #[test]
+    fn comparisons() {
+        check(">1.2", &["1.3.0", "2.0.0"], &["1.2.0", "1.2.9"]);
+        check(">1.2.3", &["1.2.4"], &["1.2.3"]);
+        check(">=1.2", &["1.2.0"], &["1.1.9"]);
+        check("<1.2", &["1.1.9"], &["1.2.0"]);
+        check("<=1", &["0.1.0", "1.0.0", "1.9.9"], &["2.0.0"]);
+        check("<=1.2.3", &["1.2.3"], &["1.2.4"]);
+        check("=1.2", &["1.2.0", "1.2.7"], &["1.1.0", "1.3.0"]);
+        check("=1.2.3", &["1.2.3"], &["1.2.4"]);
+    }
+
+    #[test]
+    fn wildcards_and_lists() {
+        check("*", &["0.0.0", "99.1.2"], &[]);
+        check(">=0.1.2, <0.3", &["0.1.2", "0.2.9"], &["0.1.1", "0.3.0"]);
+        check(" >1 ,<=3 , ~2 ", &["2.0.0", "2.9.9"], &["1.9.9", "3.0.0"]);
+        // no version is both
+        check(">2, <1", &[], &["0.5.0", "1.5.0", "2.5.0"]);
+    }
+
+    #[test]
+    fn invalid() {
+        for requirement in ["", " ", "^", ">=a.b", "1.2.3.4", "^1,", "1.-2", "*, ^1"] {
+            assert!(VersionReq::parse(requirement).is_err(), "{:?} should not parse", requirement);
+        }
+        for version in ["1", "1.2", "1.2.3.4", "v1.2.3", "1.2.x", ""] {
+            assert!(Version::parse(version).is_err(), "{:?} should not parse", version);
+        }
+        assert_eq!(Version::parse(" 1.2.3 ").unwrap(), Version::new(1, 2, 3));
+        assert_eq!(Version::new(0, 10, 2).to_string(), "0.10.2");
+    }
+}
@@ -1,17 +1,17 @@
 // MIT License
-// 
+//
 // Copyright (c) 2024 ZARK-WAF
-// 
+//
 // Permission is hereby granted, free of charge, to any person obtaining a copy
 // of this software and associated documentation files (the "Software"), to deal
 // in the Software without restriction, including without limitation the rights
 // to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
 // copies of the Software, and to permit persons to whom the Software is
 // furnished to do so, subject to the following conditions:
-// 
+//
 // The above copyright notice and this permission notice shall be included in all
 // copies or substantial portions of the Software.
-// 
+//
 // THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
 // IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
 // FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
@@ -26,7 +26,7 @@ use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Rules {
-    #[serde(rename = "rule")]
+    #[serde(rename = "rule", default)]
     pub rules: Vec<Rule>,
 }
 
@@ -34,8 +34,19 @@ pub struct Rules {
 pub struct Rule {
     #[serde(rename = "@name")]
     pub name: String,
-    pub condition: Condition,
+    // all conditions must match for the rule to fire
+    #[serde(rename = "condition")]
+    pub conditions: Vec<Condition>,
     pub action: Action,
+    // parameters used by specific actions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<Limit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mask_pattern: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,9 +57,17 @@ pub struct Condition {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub struct Limit {
+    pub requests: u64,
+    pub per_second: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Operator {
     #[serde(rename = "contains")]
     Contains,
+    #[serde(rename = "not_contains")]
+    NotContains,
     #[serde(rename = "equals")]
     Equals,
     #[serde(rename = "starts_with")]
@@ -57,9 +76,15 @@ pub enum Operator {
     EndsWith,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Action {
-    BLOCK,
-    ALLOW,
-    LOG,
-}
\ No newline at end of file
+    Block,
+    Allow,
+    Log,
+    Sanitize,
+    Mask,
+    RateLimit,
+    ValidateCsrf,
+    EnforceContentType,
+}
@@ -0,0 +1,366 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+use std::path::Path;
+
+use regex::Regex;
+use zark_waf_common::inspection::{Modification, RequestContext, ResponseContext, Verdict};
+
+use crate::ast::{Action, Condition, Operator, Rule, Rules};
+use crate::error::DslError;
+use crate::parser::parse_file;
+
+// status returned when a request doesn't carry the content type a rule enforces
+const UNSUPPORTED_MEDIA_TYPE: u16 = 415;
+
+// every field a condition can look at, see field_value
+const FIELDS: [&str; 12] = [
+    "REQUEST_URI",
+    "REQUEST_PATH",
+    "REQUEST_METHOD",
+    "REQUEST_PARAMS",
+    "REQUEST_BODY",
+    "REQUEST_HEADERS",
+    "REMOTE_ADDR",
+    "USER_AGENT",
+    "UPLOAD_FILENAME",
+    "RESPONSE_STATUS",
+    "RESPONSE_BODY",
+    "RESPONSE_HEADERS",
+];
+
+// evaluates a parsed rule set against requests and responses
+pub struct RuleEngine {
+    rules: Vec<Rule>,
+}
+
+impl RuleEngine {
+    // build an engine from parsed rules, validating fields and action
+    // parameters up front. a misspelled field would never match anything
+    pub fn new(rules: Rules) -> Result<Self, DslError> {
+        for rule in &rules.rules {
+            if let Some(condition) = rule.conditions.iter().find(|c| !FIELDS.contains(&c.field.as_str())) {
+                return Err(DslError::CompilationError(format!(
+                    "rule '{}': unknown field '{}'",
+                    rule.name, condition.field
+                )));
+            }
+            match rule.action {
+                Action::Mask => {
+                    let pattern = rule.mask_pattern.as_deref().ok_or_else(|| {
+                        DslError::CompilationError(format!("rule '{}': MASK requires a mask_pattern", rule.name))
+                    })?;
+                    Regex::new(pattern).map_err(|e| {
+                        DslError::CompilationError(format!("rule '{}': invalid mask_pattern: {}", rule.name, e))
+                    })?;
+                }
+                Action::EnforceContentType if rule.content_type.is_none() => {
+                    return Err(DslError::CompilationError(format!(
+                        "rule '{}': ENFORCE_CONTENT_TYPE requires a content_type",
+                        rule.name
+                    )));
+                }
+                Action::RateLimit if rule.limit.is_none() => {
+                    return Err(DslError::CompilationError(format!(
+                        "rule '{}': RATE_LIMIT requires a limit",
+                        rule.name
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self { rules: rules.rules })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, DslError> {
+        Self::new(parse_file(path)?)
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    // evaluate the request rules in order and merge the verdicts of the ones
+    // that match. an ALLOW rule stops evaluation and keeps whatever was decided
+    // before it.
+    pub fn evaluate(&self, request: &RequestContext) -> Verdict {
+        self.evaluate_phase(request, None)
+    }
+
+    // evaluate the response rules (those with a RESPONSE_* condition) against a
+    // response and the request it answers
+    pub fn evaluate_response(&self, request: &RequestContext, response: &ResponseContext) -> Verdict {
+        self.evaluate_phase(request, Some(response))
+    }
+
+    fn evaluate_phase(&self, request: &RequestContext, response: Option<&ResponseContext>) -> Verdict {
+        let mut verdict = Verdict::Allow;
+
+        for rule in &self.rules {
+            if is_response_rule(rule) != response.is_some() {
+                continue;
+            }
+            if !rule.conditions.iter().all(|c| condition_matches(c, request, response)) {
+                continue;
+            }
+
+            let rule_verdict = match rule.action {
+                Action::Allow => break,
+                Action::Block => Verdict::block(&rule.name, format!("Request blocked by rule '{}'", rule.name)),
+                Action::Log => Verdict::log(&rule.name),
+                Action::Sanitize => sanitize(rule, request, response),
+                Action::Mask => Verdict::modify(
+                    &rule.name,
+                    Modification::Mask { pattern: rule.mask_pattern.clone().unwrap_or_default() },
+                ),
+                Action::EnforceContentType => {
+                    let expected = rule.content_type.as_deref().unwrap_or_default();
+                    let matches = request
+                        .header("content-type")
+                        .map(|ct| ct.to_ascii_lowercase().starts_with(&expected.to_ascii_lowercase()))
+                        .unwrap_or(false);
+                    if matches {
+                        continue;
+                    }
+                    Verdict::Block {
+                        rules: vec![rule.name.clone()],
+                        status: UNSUPPORTED_MEDIA_TYPE,
+                        reason: format!("Content-Type must be {}", expected),
+                    }
+                }
+                // stateful actions are enforced by the modules that own the state
+                // (rate limiter, session handling); the rule engine only reports them
+                Action::RateLimit | Action::ValidateCsrf => Verdict::log(&rule.name),
+            };
+
+            verdict = verdict.merge(rule_verdict);
+        }
+
+        verdict
+    }
+}
+
+// strip the values a SANITIZE rule looks for. rules match the decoded,
+// case-folded uri while stripping works on the raw text, so "UNION%20SELECT"
+// matches "union select" but has nothing to strip. the sanitized copy is
+// checked again, and a rule whose values survive blocks instead
+fn sanitize(rule: &Rule, request: &RequestContext, response: Option<&ResponseContext>) -> Verdict {
+    let present: Vec<&Condition> = rule
+        .conditions
+        .iter()
+        .filter(|c| c.operator != Operator::NotContains)
+        .collect();
+    let modifications: Vec<Modification> = present
+        .iter()
+        .map(|c| Modification::Sanitize { value: c.value.clone() })
+        .collect();
+
+    let mut request = request.clone();
+    let mut response = response.cloned();
+    match response.as_mut() {
+        Some(response) => response.apply(&modifications),
+        None => request.apply(&modifications),
+    }
+    if present.iter().any(|c| condition_matches(c, &request, response.as_ref())) {
+        return Verdict::block(
+            &rule.name,
+            format!("Request blocked by rule '{}': the matched value could not be sanitized", rule.name),
+        );
+    }
+    Verdict::Modify { rules: vec![rule.name.clone()], modifications }
+}
+
+// a rule belongs to the response phase as soon as one condition looks at the response
+fn is_response_rule(rule: &Rule) -> bool {
+    rule.conditions.iter().any(|c| c.field.starts_with("RESPONSE_"))
+}
+
+// resolve a rule field to the value it refers to in the request or response,
+// if present. fields are checked against FIELDS when the engine is built
+fn field_value(field: &str, request: &RequestContext, response: Option<&ResponseContext>) -> Option<String> {
+    match field {
+        "REQUEST_URI" => Some(url_decode(&request.uri)),
+        "REQUEST_PATH" => Some(url_decode(request.path())),
+        "REQUEST_METHOD" => Some(request.method.clone()),
+        "REQUEST_PARAMS" => request.query().map(url_decode),
+        "REQUEST_BODY" => Some(String::from_utf8_lossy(&request.body).into_owned()),
+        "REQUEST_HEADERS" => Some(format_headers(&request.headers)),
+        "REMOTE_ADDR" => request.remote_addr.map(|addr| addr.ip().to_string()),
+        "USER_AGENT" => request.header("user-agent").map(str::to_string),
+        "UPLOAD_FILENAME" => upload_filenames(request),
+        "RESPONSE_STATUS" => response.map(|response| response.status.to_string()),
+        "RESPONSE_BODY" => response.map(|response| String::from_utf8_lossy(&response.body).into_owned()),
+        "RESPONSE_HEADERS" => response.map(|response| format_headers(&response.headers)),
+        _ => None,
+    }
+}
+
+// rules see the uri the way the application will: percent escapes decoded
+// and "+" read as a space, so "union%20select" and "union+select" both read
+// "union select". a "%" not followed by two hex digits stays as it is
+fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = |at: usize| bytes.get(at).and_then(|b| (*b as char).to_digit(16));
+                if let (Some(high), Some(low)) = (hex(i + 1), hex(i + 2)) {
+                    decoded.push((high * 16 + low) as u8);
+                    i += 3;
+                    continue;
+                }
+                decoded.push(b'%');
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// one "name: value" line per header
+fn format_headers(headers: &[(String, String)]) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// collect the filenames of multipart uploads, one per line
+fn upload_filenames(request: &RequestContext) -> Option<String> {
+    let body = String::from_utf8_lossy(&request.body);
+    let names: Vec<&str> = body
+        .split("filename=\"")
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .collect();
+    if names.is_empty() {
+        None
+    } else {
+        Some(names.join("\n"))
+    }
+}
+
+// comparisons are ascii case-insensitive. multi-line values (headers, upload
+// filenames) match equals/starts_with/ends_with against any single line.
+fn condition_matches(condition: &Condition, request: &RequestContext, response: Option<&ResponseContext>) -> bool {
+    let value = match field_value(&condition.field, request, response) {
+        Some(value) => value.to_ascii_lowercase(),
+        None => return false,
+    };
+    let expected = condition.value.to_ascii_lowercase();
+
+    match condition.operator {
+        Operator::Contains => value.contains(&expected),
+        Operator::NotContains => !value.contains(&expected),
+        Operator::Equals => value.lines().any(|line| line == expected),
+        Operator::StartsWith => value.lines().any(|line| line.starts_with(&expected)),
+        Operator::EndsWith => value.lines().any(|line| line.ends_with(&expected)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> RuleEngine {
+        let rules = crate::parser::parse(
+            "<rules><rule name=\"sqli\"><condition><field>REQUEST_URI</field>\
+             <operator>contains</operator><value>union select</value></condition>\
+             <action>BLOCK</action></rule></rules>",
+        )
+        .unwrap();
+        RuleEngine::new(rules).unwrap()
+    }
+
+    #[test]
+    fn encoded_uris_are_matched_decoded() {
+        let engine = engine();
+        for uri in ["/?q=union select", "/?q=union%20select", "/?q=union+select", "/?q=UNION%20%53ELECT"] {
+            let verdict = engine.evaluate(&RequestContext::new("GET", uri));
+            assert!(verdict.is_blocked(), "{} was let through", uri);
+        }
+        assert!(!engine.evaluate(&RequestContext::new("GET", "/?q=union%2")).is_blocked());
+    }
+
+    fn sanitizer(field: &str) -> RuleEngine {
+        let rules = crate::parser::parse(&format!(
+            "<rules><rule name=\"strip\"><condition><field>{}</field>\
+             <operator>contains</operator><value>union select</value></condition>\
+             <action>SANITIZE</action></rule></rules>",
+            field
+        ))
+        .unwrap();
+        RuleEngine::new(rules).unwrap()
+    }
+
+    #[test]
+    fn sanitize_strips_what_it_can() {
+        let request = RequestContext::new("GET", "/?q=1 union select 2");
+        let verdict = sanitizer("REQUEST_URI").evaluate(&request);
+        let Verdict::Modify { modifications, .. } = &verdict else {
+            panic!("expected a modification, got {:?}", verdict);
+        };
+        let mut sanitized = request.clone();
+        sanitized.apply(modifications);
+        assert_eq!(sanitized.uri, "/?q=1  2");
+    }
+
+    #[test]
+    fn sanitize_blocks_what_it_cannot_strip() {
+        let engine = sanitizer("REQUEST_URI");
+        for uri in ["/?q=UNION%20SELECT", "/?q=union+select", "/?q=Union Select", "/?q=union%20SELECT"] {
+            let verdict = engine.evaluate(&RequestContext::new("GET", uri));
+            assert!(verdict.is_blocked(), "{} got {:?}", uri, verdict);
+        }
+        let body = RequestContext::new("POST", "/").with_body("x=1 UNION SELECT 2");
+        assert!(sanitizer("REQUEST_BODY").evaluate(&body).is_blocked());
+        let body = RequestContext::new("POST", "/").with_body("x=1 union select 2");
+        assert!(matches!(sanitizer("REQUEST_BODY").evaluate(&body), Verdict::Modify { .. }));
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let rules = crate::parser::parse(
+            "<rules><rule name=\"typo\"><condition><field>REQEUST_URI</field>\
+             <operator>contains</operator><value>x</value></condition>\
+             <action>BLOCK</action></rule></rules>",
+        )
+        .unwrap();
+        let error = RuleEngine::new(rules).err().expect("a misspelled field must not load");
+        assert!(error.to_string().contains("unknown field 'REQEUST_URI'"), "{}", error);
+    }
+
+    #[test]
+    fn broken_escapes_are_kept() {
+        assert_eq!(url_decode("100%"), "100%");
+        assert_eq!(url_decode("%zz%4"), "%zz%4");
+        assert_eq!(url_decode("a%2Bb+c"), "a+b c");
+    }
+}
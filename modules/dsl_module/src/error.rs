@@ -29,9 +29,9 @@ pub enum DslError {
     #[error("XML parsing error: {0}")]
     XmlParsingError(#[from] quick_xml::de::DeError),
 
-    #[error("XML serialization error: {0}")]
-    XmlSerializationError(#[from] quick_xml::se::Error),
-
     #[error("Compilation error: {0}")]
     CompilationError(String),
+
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
 }
\ No newline at end of file
@@ -22,13 +22,16 @@
 //
 // Authors: I. Zeqiri, E. Gjergji
 mod parser;
-mod ast;
+pub mod ast;
 mod compiler;
+mod engine;
 mod error;
 
 use crate::parser::parse;
 use crate::compiler::compile;
+pub use crate::engine::RuleEngine;
 pub use crate::error::DslError;
+pub use crate::parser::parse_file;
 
 pub fn process_dsl(input: &str) -> Result<String, DslError> {
     let ast = parse(input)?;
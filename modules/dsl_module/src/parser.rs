@@ -1,17 +1,17 @@
 // MIT License
-// 
+//
 // Copyright (c) 2024 ZARK-WAF
-// 
+//
 // Permission is hereby granted, free of charge, to any person obtaining a copy
 // of this software and associated documentation files (the "Software"), to deal
 // in the Software without restriction, including without limitation the rights
 // to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
 // copies of the Software, and to permit persons to whom the Software is
 // furnished to do so, subject to the following conditions:
-// 
+//
 // The above copyright notice and this permission notice shall be included in all
 // copies or substantial portions of the Software.
-// 
+//
 // THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
 // IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
 // FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
@@ -22,6 +22,8 @@
 //
 // Authors: I. Zeqiri, E. Gjergji
 
+use std::path::Path;
+
 use quick_xml::de::from_str;
 use crate::ast::*;
 use crate::error::DslError;
@@ -31,26 +33,7 @@ pub fn parse(input: &str) -> Result<Rules, DslError> {
     Ok(rules)
 }
 
-fn parse_condition(pair: pest::iterators::Pair<Rule>) -> Condition {
-    let mut inner = pair.into_inner();
-    let field = inner.next().unwrap().as_str().to_string();
-    let operator = match inner.next().unwrap().as_str() {
-        "contains" => Operator::Contains,
-        "equals" => Operator::Equals,
-        "starts_with" => Operator::StartsWith,
-        "ends_with" => Operator::EndsWith,
-        _ => unreachable!(),
-    };
-    let value = inner.next().unwrap().as_str().to_string();
-    Condition { field, operator, value }
-}
-
-fn parse_action(pair: pest::iterators::Pair<Rule>) -> Action {
-    match pair.as_str() {
-        "BLOCK" => Action::Block,
-        "ALLOW" => Action::Allow,
-        "LOG" => Action::Log,
-        _ => unreachable!(),
-    }
+pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<Rules, DslError> {
+    let input = std::fs::read_to_string(path)?;
+    parse(&input)
 }
-
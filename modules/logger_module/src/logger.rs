@@ -26,17 +26,16 @@ use async_trait::async_trait;
 use chrono::Local;
 use fern::colors::{Color, ColoredLevelConfig};
 use log::LevelFilter;
-use std::sync::Arc;
-use zark_waf_common::messaging::messenger::ZarkMessenger;
-use zark_waf_common::Module; 
+use std::ffi::c_void;
+use std::future::Future;
+use std::pin::Pin;
+use zark_waf_module_manager::{export_module, Module};
 use crate::error::ZarkLoggerError;
 use serde::{Serialize, Deserialize};
 
 
 pub struct ZarkLogger {
     config: LoggerConfig,
-    messenger: *mut c_void,
-
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -124,7 +123,7 @@ impl ZarkLogger {
                 pid: 0,
             };
 
-            let syslog = syslog::unix(formatter).map_err(LoggerError::SyslogError)?;
+            let syslog = syslog::unix(formatter).map_err(ZarkLoggerError::SyslogError)?;
             let syslog_config = fern::Dispatch::new()
                 .format(|out, message, record| {
                     out.finish(format_args!(
@@ -152,11 +151,29 @@ impl Module for ZarkLogger {
         "zark_logger"
     }
 
-    async fn init(&mut self, messenger: *mut c_void) -> Result<(), Box<dyn std::error::Error>> {
-        self.messenger = messenger;
-        self.setup_logger()?;
-        log::info!("ZarkLogger initialized");
-        Ok(())
+    fn version(&self) -> &str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn description(&self) -> &str {
+        "Logs to stdout, files and syslog"
+    }
+
+    // written out by hand: an async fn would hold the raw pointer, which
+    // isn't Send, across awaits. the logger doesn't talk on the bus
+    fn init<'life0, 'async_trait>(
+        &'life0 mut self,
+        _messenger: *mut c_void,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Box<dyn std::error::Error>>> + Send + 'async_trait>>
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        Box::pin(async move {
+            self.setup_logger()?;
+            log::info!("ZarkLogger initialized");
+            Ok(())
+        })
     }
 
     async fn execute(&self, input: serde_json::Value) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
@@ -170,20 +187,21 @@ impl Module for ZarkLogger {
     }
 }
 
-//this is the entry point for the module 
-//it is used by the module manager to create the module
-// it returns a boxed pointer to the module
-#[no_mangle]
-pub extern "C" fn _create_module() -> *mut ZarkLogger {
-    let config = LoggerConfig {
-        log_type: vec!["stdout".to_string(), "file".to_string()],
-        log_path: "/var/log/zark_waf.log".to_string(),
-        log_level: "info".to_string(),
-        log_max_size: 1000000,
-        log_max_backups: 5,
-        log_max_age: 30,
-        log_compress: true,
-    };
-
-    Box::into_raw(Box::new(ZarkLogger::new(config)))
-}
\ No newline at end of file
+// entry points the module manager loads the module through
+export_module! {
+    name: "zark_logger",
+    version: env!("CARGO_PKG_VERSION"),
+    description: "Logs to stdout, files and syslog",
+    create: || {
+        let config = LoggerConfig {
+            log_type: vec!["stdout".to_string(), "file".to_string()],
+            log_path: "/var/log/zark_waf.log".to_string(),
+            log_level: "info".to_string(),
+            log_max_size: 1000000,
+            log_max_backups: 5,
+            log_max_age: 30,
+            log_compress: true,
+        };
+        ZarkLogger::new(config)
+    },
+}
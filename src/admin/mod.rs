@@ -0,0 +1,323 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// admin api: runtime control of a running instance over http/json
+//
+//   GET    /state                  connections, verdict counters, stage failures, module
+//                                  and plugin state
+//   GET    /messenger              bus subscriptions with queue depth and drops per priority
+//   GET    /modules                loaded modules (ModuleInfo), in load order
+//   POST   /modules  {"path": ..}  load a module library; "on-failure": "closed" blocks
+//                                  the requests it fails to inspect
+//   DELETE /modules/{name}         unload a module
+//   GET    /plugins                loaded plugins (PluginMetadata), in load order
+//   POST   /plugins  {"path": ..}  load a plugin library, with the same "on-failure"
+//   DELETE /plugins/{name}         unload a plugin
+//   POST   /rules/reload           re-read the rule set
+//   POST   /config/reload          re-read the config file
+//
+// when a token is configured every request needs "Authorization: Bearer <token>";
+// without one the api only binds to loopback. libraries are only loaded from
+// modules.directory and plugins.directory.
+//
+// unloading takes a module or plugin out of the inspection chain and shuts it
+// down, but its library stays mapped until the process exits. loading the
+// same path again brings back the code first loaded from it, not a rebuilt
+// file; deploy a new build under a new file name, or restart.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+
+use http_body_util::{BodyExt, Limited};
+use hyper::body::Incoming;
+use hyper::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use zark_waf_config_manager::config::{AdminConfig, FailureMode};
+use zark_waf_module_manager::ModuleManagerError;
+use zark_waf_plugin_system::PluginError;
+
+use crate::connectors::http::{full, ResponseBody};
+use crate::core::{CoreError, ZarkWafCore};
+
+// admin requests only carry small json documents
+const MAX_REQUEST_BODY: usize = 64 * 1024;
+
+pub struct AdminServer {
+    listener: TcpListener,
+    core: Arc<ZarkWafCore>,
+    token: Option<Arc<str>>,
+}
+
+impl AdminServer {
+    pub async fn bind(config: &AdminConfig, core: Arc<ZarkWafCore>) -> Result<Self, CoreError> {
+        if config.token.is_none() && !is_loopback(&config.host) {
+            return Err(CoreError::InitError(format!(
+                "zark-admin on {} needs a token; without one it only binds to loopback",
+                config.host
+            )));
+        }
+        let listener = TcpListener::bind((config.host.as_str(), config.port)).await?;
+        log::info!("Admin API listening on {}:{}", config.host, config.port);
+        if config.token.is_none() {
+            log::warn!("Admin API has no token configured, any local user can load libraries");
+        }
+
+        Ok(Self {
+            listener,
+            core,
+            token: config.token.as_deref().map(Arc::from),
+        })
+    }
+
+    pub async fn serve(self) {
+        loop {
+            let (stream, remote_addr) = match self.listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::error!("Admin API failed to accept a connection: {}", e);
+                    continue;
+                }
+            };
+
+            let core = self.core.clone();
+            let token = self.token.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let core = core.clone();
+                    let token = token.clone();
+                    async move { Ok::<_, Infallible>(handle(request, &core, token.as_deref()).await) }
+                });
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    log::debug!("Admin connection from {} closed with error: {}", remote_addr, e);
+                }
+            });
+        }
+    }
+}
+
+// whether a configured host only accepts connections from this machine
+pub fn is_loopback(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+#[derive(Deserialize)]
+struct LoadRequest {
+    path: String,
+    #[serde(default, rename = "on-failure")]
+    on_failure: FailureMode,
+}
+
+#[derive(Serialize)]
+struct StateSnapshot {
+    running: bool,
+    active_connections: usize,
+    inspections: InspectionSnapshot,
+    modules: BTreeMap<String, LibraryState>,
+    plugins: BTreeMap<String, LibraryState>,
+    // failed inspections by stage
+    stage_failures: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize)]
+struct InspectionSnapshot {
+    allowed: u64,
+    logged: u64,
+    modified: u64,
+    blocked: u64,
+}
+
+#[derive(Serialize)]
+struct LibraryState {
+    active: bool,
+    // milliseconds since the library last inspected a request
+    last_execution_ms_ago: Option<u128>,
+}
+
+async fn handle(request: Request<Incoming>, core: &ZarkWafCore, token: Option<&str>) -> Response<ResponseBody> {
+    if let Some(token) = token {
+        if !authorized(&request, token) {
+            return error_response(StatusCode::UNAUTHORIZED, "missing or invalid token");
+        }
+    }
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+
+    match (&method, segments.as_slice()) {
+        (&Method::GET, ["state"]) => json_response(StatusCode::OK, &state_snapshot(core)),
+        (&Method::GET, ["messenger"]) => json_response(StatusCode::OK, &core.messenger_stats().await),
+        (&Method::GET, ["modules"]) => json_response(StatusCode::OK, &core.list_modules().await),
+        (&Method::POST, ["modules"]) => match read_load_request(request).await {
+            Ok(load) => result_response(StatusCode::CREATED, core.load_module(&load.path, load.on_failure).await),
+            Err(response) => response,
+        },
+        (&Method::DELETE, ["modules", name]) => result_response(
+            StatusCode::OK,
+            core.unload_module(name).await.map(|()| serde_json::json!({ "unloaded": name })),
+        ),
+        (&Method::GET, ["plugins"]) => json_response(StatusCode::OK, &core.list_plugins().await),
+        (&Method::POST, ["plugins"]) => match read_load_request(request).await {
+            Ok(load) => result_response(StatusCode::CREATED, core.load_plugin(&load.path, load.on_failure).await),
+            Err(response) => response,
+        },
+        (&Method::DELETE, ["plugins", name]) => result_response(
+            StatusCode::OK,
+            core.unload_plugin(name).await.map(|()| serde_json::json!({ "unloaded": name })),
+        ),
+        (&Method::POST, ["rules", "reload"]) => result_response(
+            StatusCode::OK,
+            core.reload_pipeline().await.map(|stages| serde_json::json!({ "stages": stages })),
+        ),
+        (&Method::POST, ["config", "reload"]) => result_response(
+            StatusCode::OK,
+            core.reload_config().await.map(|sections| serde_json::json!({ "restart_required": sections })),
+        ),
+        _ => error_response(StatusCode::NOT_FOUND, "no such endpoint"),
+    }
+}
+
+fn authorized(request: &Request<Incoming>, token: &str) -> bool {
+    let Some(provided) = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    // compare every byte so the token can't be guessed from response times
+    provided.len() == token.len() && provided.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+async fn read_load_request(request: Request<Incoming>) -> Result<LoadRequest, Response<ResponseBody>> {
+    let body = Limited::new(request.into_body(), MAX_REQUEST_BODY)
+        .collect()
+        .await
+        .map_err(|_| error_response(StatusCode::BAD_REQUEST, "unreadable request body"))?
+        .to_bytes();
+    serde_json::from_slice(&body)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, &format!("expected {{\"path\": ...}}: {}", e)))
+}
+
+fn state_snapshot(core: &ZarkWafCore) -> StateSnapshot {
+    let state = core.state();
+    let now = Instant::now();
+    let ms_ago = |at: Option<Instant>| at.map(|at| now.duration_since(at).as_millis());
+
+    StateSnapshot {
+        running: state.is_running(),
+        active_connections: state.active_connections(),
+        inspections: InspectionSnapshot {
+            allowed: state.inspections.allowed.load(Ordering::Relaxed),
+            logged: state.inspections.logged.load(Ordering::Relaxed),
+            modified: state.inspections.modified.load(Ordering::Relaxed),
+            blocked: state.inspections.blocked.load(Ordering::Relaxed),
+        },
+        modules: state
+            .module_states
+            .iter()
+            .map(|entry| {
+                let library = LibraryState {
+                    active: entry.is_active,
+                    last_execution_ms_ago: ms_ago(entry.last_execution),
+                };
+                (entry.key().clone(), library)
+            })
+            .collect(),
+        plugins: state
+            .plugin_states
+            .iter()
+            .map(|entry| {
+                let library = LibraryState {
+                    active: entry.is_loaded,
+                    last_execution_ms_ago: ms_ago(entry.last_execution),
+                };
+                (entry.key().clone(), library)
+            })
+            .collect(),
+        stage_failures: state
+            .stage_failures
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect(),
+    }
+}
+
+fn json_response<T: Serialize>(status: StatusCode, value: &T) -> Response<ResponseBody> {
+    match serde_json::to_vec_pretty(value) {
+        Ok(body) => {
+            let mut response = Response::new(full(body));
+            *response.status_mut() = status;
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            response
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<ResponseBody> {
+    json_response(status, &serde_json::json!({ "error": message }))
+}
+
+fn result_response<T: Serialize>(status: StatusCode, result: Result<T, CoreError>) -> Response<ResponseBody> {
+    match result {
+        Ok(value) => json_response(status, &value),
+        Err(e) => {
+            log::warn!("Admin request failed: {}", e);
+            error_response(error_status(&e), &e.to_string())
+        }
+    }
+}
+
+fn error_status(error: &CoreError) -> StatusCode {
+    match error {
+        CoreError::ModuleError(ModuleManagerError::ModuleNotFound(_))
+        | CoreError::PluginError(PluginError::PluginNotFound(_)) => StatusCode::NOT_FOUND,
+        CoreError::PathNotAllowed(_) => StatusCode::FORBIDDEN,
+        CoreError::ModuleError(_) | CoreError::PluginError(_) | CoreError::RuleError(_) | CoreError::ConfigError(_) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
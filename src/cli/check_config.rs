@@ -0,0 +1,182 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// check-config: load a config file and check everything startup would trip
+// over, without binding ports or loading libraries. warnings don't fail the
+// check, errors do.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::ExitCode;
+
+use zark_waf_config_manager::config::{Config, ConnectorMode, LibraryEntry};
+use zark_waf_dsl::RuleEngine;
+
+use crate::admin;
+use crate::connectors;
+use crate::core::CoreError;
+
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    errors: usize,
+}
+
+impl Report {
+    fn ok(&self, message: impl AsRef<str>) {
+        println!("ok      {}", message.as_ref());
+    }
+
+    fn warn(&mut self, message: impl AsRef<str>) {
+        println!("WARNING {}", message.as_ref());
+        self.warnings += 1;
+    }
+
+    fn error(&mut self, message: impl AsRef<str>) {
+        println!("ERROR   {}", message.as_ref());
+        self.errors += 1;
+    }
+
+    // libraries must exist and be registered under distinct names
+    fn check_libraries(&mut self, kind: &str, entries: &[LibraryEntry]) {
+        let mut names: HashMap<&str, usize> = HashMap::new();
+        for entry in entries {
+            *names.entry(entry.name.as_str()).or_default() += 1;
+            if Path::new(&entry.path).is_file() {
+                self.ok(format!("{} '{}' found at {}", kind, entry.name, entry.path));
+            } else {
+                self.error(format!("{} '{}': {} does not exist", kind, entry.name, entry.path));
+            }
+        }
+        for (name, count) in names {
+            if count > 1 {
+                self.error(format!("{} '{}' is listed {} times", kind, name, count));
+            }
+        }
+    }
+}
+
+pub async fn execute(config_path: &str) -> Result<ExitCode, CoreError> {
+    let mut report = Report::default();
+
+    let config = match Config::load(config_path).await {
+        Ok(config) => {
+            report.ok(format!("{} parsed", config_path));
+            config
+        }
+        Err(e) => {
+            report.error(format!("{}: {}", config_path, e));
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
+    if config.core.max_connections == 0 {
+        report.error("zark-core.max-connections must be greater than zero");
+    }
+
+    if let Some(library) = &config.core.messenger_library {
+        if Path::new(library).is_file() {
+            report.ok(format!("messenger library found at {}", library));
+        } else {
+            report.error(format!("zark-core.messenger-library: {} does not exist", library));
+        }
+    }
+
+    if let Some(socket) = &config.core.messenger_socket {
+        match Path::new(socket).parent() {
+            Some(directory) if !directory.as_os_str().is_empty() && !directory.is_dir() => {
+                report.error(format!("zark-core.messenger-socket: directory {} does not exist", directory.display()))
+            }
+            _ if config.core.messenger_library.is_some() => {
+                report.warn("zark-core.messenger-socket: the bridge needs stream subscriptions, which the messenger library does not support")
+            }
+            _ => report.ok(format!("message bus bridge on {}", socket)),
+        }
+    }
+
+    if !config.core.durable_topics.is_empty() {
+        match &config.core.durable_store {
+            Some(store) => report.ok(format!(
+                "durable topics {} stored in {}",
+                config.core.durable_topics.join(", "),
+                store
+            )),
+            None => report.error("zark-core.durable-topics is set but zark-core.durable-store is not"),
+        }
+    }
+
+    match config.core.rules_path.as_deref() {
+        Some(path) => match RuleEngine::from_file(path) {
+            Ok(engine) => report.ok(format!("{} rules loaded from {}", engine.rules().len(), path)),
+            Err(e) => report.error(format!("rules {}: {}", path, e)),
+        },
+        None => report.warn("no zark-core.rules-path set, only modules and plugins will inspect traffic"),
+    }
+
+    // every enabled listener needs its own address
+    let mut listeners: HashMap<(String, u16), String> = HashMap::new();
+    let mut enabled = 0;
+    for (name, server) in config.web_servers.iter().filter(|(_, server)| server.enabled) {
+        enabled += 1;
+        match connectors::validate(name, server) {
+            Ok(()) => report.ok(format!("web server '{}' ({:?})", name, server.mode)),
+            Err(e) => report.error(e.to_string()),
+        }
+        if server.mode == ConnectorMode::Passive {
+            continue;
+        }
+        if let Some(other) = listeners.insert((server.host.clone(), server.port), name.clone()) {
+            report.error(format!("web servers '{}' and '{}' both listen on {}:{}", other, name, server.host, server.port));
+        }
+    }
+    if enabled == 0 {
+        report.warn("no web server is enabled, zark will not see any traffic");
+    }
+
+    if let Some(admin) = config.admin.as_ref().filter(|admin| admin.enabled) {
+        if let Some(other) = listeners.get(&(admin.host.clone(), admin.port)) {
+            report.error(format!("zark-admin and web server '{}' both listen on {}:{}", other, admin.host, admin.port));
+        }
+        if admin.token.is_none() && !admin::is_loopback(&admin.host) {
+            report.error(format!("zark-admin binds to {} without a token, set one or bind to loopback", admin.host));
+        } else if admin.token.is_none() {
+            report.warn("zark-admin is enabled without a token, any local user can load code");
+        }
+        for (section, directory) in [("modules", &config.modules.directory), ("plugins", &config.plugins.directory)] {
+            match directory {
+                Some(directory) if !Path::new(directory).is_dir() => {
+                    report.error(format!("{}.directory: {} is not a directory", section, directory))
+                }
+                Some(directory) => report.ok(format!("{} loaded at runtime from {}", section, directory)),
+                None => report.warn(format!("{}.directory is not set, zark-admin can't load {}", section, section)),
+            }
+        }
+    }
+
+    report.check_libraries("module", &config.modules.paths);
+    report.check_libraries("plugin", &config.plugins.paths);
+
+    println!("{} errors, {} warnings", report.errors, report.warnings);
+    Ok(if report.errors == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
@@ -0,0 +1,72 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// list-modules: open every shared library in a directory and print what it
+// reports about itself. modules are created but never initialized or started.
+
+use std::collections::HashMap;
+use std::env::consts::DLL_EXTENSION;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use zark_waf_module_manager::ModuleLoader;
+
+use crate::core::CoreError;
+
+pub fn execute(dir: &str) -> Result<ExitCode, CoreError> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == DLL_EXTENSION))
+        .collect();
+    paths.sort();
+
+    // the loader keeps the libraries open, so it has to outlive the modules
+    let loader = ModuleLoader::new();
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
+    let mut errors = 0;
+    for path in &paths {
+        let module = match loader.load(path) {
+            Ok(module) => module,
+            Err(e) => {
+                println!("ERROR {}: {}", path.display(), e);
+                errors += 1;
+                continue;
+            }
+        };
+        println!(
+            "{} {} ({})\n    {}",
+            module.name(),
+            module.version(),
+            path.display(),
+            module.description()
+        );
+        if let Some(first) = seen.insert(module.name().to_string(), path.clone()) {
+            println!("ERROR {}: module name '{}' is also used by {}", path.display(), module.name(), first.display());
+            errors += 1;
+        }
+    }
+
+    println!("{} libraries in {}, {} modules, {} errors", paths.len(), dir, seen.len(), errors);
+    Ok(if errors == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
@@ -0,0 +1,96 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// command line subcommands. everything but run works offline, without binding
+// a port, so config and rule changes can be checked in a deploy pipeline; each
+// of those exits non-zero when it finds a problem.
+
+mod check_config;
+mod list_modules;
+mod record;
+mod replay;
+mod test_rules;
+
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use clap::Subcommand;
+use zark_waf_config_manager::config::Config;
+
+use crate::core::{CoreError, ZarkWafCore};
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[clap(about = "Start the WAF (the default)")]
+    Run,
+    #[clap(about = "Validate the config file, its rules and library paths")]
+    CheckConfig,
+    #[clap(about = "Print the verdict of a rule file for each request in a JSON lines file")]
+    TestRules {
+        #[clap(value_name = "RULES_XML")]
+        rules: String,
+        #[clap(value_name = "REQUESTS_JSONL")]
+        requests: String,
+    },
+    #[clap(about = "Show the modules found in a directory of shared libraries")]
+    ListModules {
+        #[clap(value_name = "DIR")]
+        dir: String,
+    },
+    #[clap(about = "Replay a recorded event log and report verdicts that would change")]
+    Replay {
+        #[clap(value_name = "EVENTS_JSONL")]
+        events: String,
+        #[clap(long, value_name = "RULES_XML", help = "Rule file to replay against, instead of the configured rules-path")]
+        rules: Option<String>,
+        #[clap(long, help = "Exit non-zero if any verdict changed")]
+        fail_on_change: bool,
+    },
+}
+
+pub async fn execute(command: Command, config_path: &str) -> Result<ExitCode, CoreError> {
+    match command {
+        Command::Run => run(config_path).await,
+        Command::CheckConfig => check_config::execute(config_path).await,
+        Command::TestRules { rules, requests } => test_rules::execute(&rules, &requests).await,
+        Command::ListModules { dir } => list_modules::execute(&dir),
+        Command::Replay { events, rules, fail_on_change } => {
+            let rules = match rules {
+                Some(rules) => rules,
+                None => Config::load(config_path).await?.core.rules_path.ok_or_else(|| {
+                    CoreError::InitError(format!("{} has no rules-path, pass --rules", config_path))
+                })?,
+            };
+            replay::execute(&events, &rules, fail_on_change).await
+        }
+    }
+}
+
+async fn run(config_path: &str) -> Result<ExitCode, CoreError> {
+    log::info!("Starting ZARK-WAF...");
+    let core = Arc::new(ZarkWafCore::new(config_path).await?);
+    core.run().await?;
+    log::info!("ZARK-WAF shutting down...");
+    Ok(ExitCode::SUCCESS)
+}
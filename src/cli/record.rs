@@ -0,0 +1,168 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// the json lines format read by test-rules and replay: one request per line,
+// with optional response, expected verdict and recorded verdict. detection
+// events published on "zark.events" are valid records as they are.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+
+use serde::Deserialize;
+use tokio::fs;
+use zark_waf_common::inspection::{RequestContext, ResponseContext, Verdict};
+use zark_waf_dsl::RuleEngine;
+
+use crate::core::CoreError;
+
+// headers as an object, or as a list of [name, value] pairs when order or
+// repeated names matter
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Headers {
+    Map(BTreeMap<String, String>),
+    Pairs(Vec<(String, String)>),
+}
+
+impl Default for Headers {
+    fn default() -> Self {
+        Headers::Pairs(Vec::new())
+    }
+}
+
+impl Headers {
+    fn pairs(&self) -> Vec<(String, String)> {
+        match self {
+            Headers::Map(map) => map.iter().map(|(n, v)| (n.clone(), v.clone())).collect(),
+            Headers::Pairs(pairs) => pairs.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResponseRecord {
+    pub status: u16,
+    #[serde(default)]
+    headers: Headers,
+    #[serde(default)]
+    body: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequestRecord {
+    #[serde(default, alias = "request_id")]
+    pub id: Option<String>,
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub uri: String,
+    #[serde(default)]
+    headers: Headers,
+    #[serde(default)]
+    body: String,
+    // "ip" or "ip:port"
+    #[serde(default)]
+    remote_addr: Option<String>,
+    // response status alone, as logged by passive mode
+    #[serde(default)]
+    status: Option<u16>,
+    #[serde(default)]
+    response: Option<ResponseRecord>,
+    // verdict name the request should get (allow, log, modify or block)
+    #[serde(default)]
+    pub expect: Option<String>,
+    // verdict the request got when it was recorded
+    #[serde(default)]
+    pub verdict: Option<Verdict>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+impl RequestRecord {
+    // label used when printing results: the record id, or its line number
+    pub fn label(&self, line: usize) -> String {
+        self.id.clone().unwrap_or_else(|| format!("line {}", line))
+    }
+
+    pub fn request(&self) -> Result<RequestContext, String> {
+        let mut request = RequestContext::new(self.method.as_str(), self.uri.as_str())
+            .with_body(self.body.clone());
+        request.headers = self.headers.pairs();
+        if let Some(id) = &self.id {
+            request.id = id.clone();
+        }
+        if let Some(addr) = &self.remote_addr {
+            let addr = addr
+                .parse::<SocketAddr>()
+                .or_else(|_| addr.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 0)))
+                .map_err(|_| format!("invalid remote_addr '{}'", addr))?;
+            request = request.with_remote_addr(addr);
+        }
+        Ok(request)
+    }
+
+    pub fn response(&self) -> Option<ResponseContext> {
+        match (&self.response, self.status) {
+            (Some(record), _) => {
+                let mut response = ResponseContext::new(record.status).with_body(record.body.clone());
+                response.headers = record.headers.pairs();
+                Some(response)
+            }
+            (None, Some(status)) => Some(ResponseContext::new(status)),
+            (None, None) => None,
+        }
+    }
+
+    // run the record through the rules the way the connectors do: request
+    // rules first, then response rules unless the request was already blocked
+    pub fn evaluate(&self, engine: &RuleEngine) -> Result<Verdict, String> {
+        let request = self.request()?;
+        let mut verdict = engine.evaluate(&request);
+        if let (Some(response), false) = (self.response(), verdict.is_blocked()) {
+            verdict = verdict.merge(engine.evaluate_response(&request, &response));
+        }
+        Ok(verdict)
+    }
+}
+
+// read a json lines file; each non-blank line is returned with its line
+// number and either the record or the reason it could not be parsed
+pub async fn read_records(path: &str) -> Result<Vec<(usize, Result<RequestRecord, String>)>, CoreError> {
+    let contents = fs::read_to_string(path).await?;
+    Ok(contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| (i + 1, serde_json::from_str(line).map_err(|e| e.to_string())))
+        .collect())
+}
+
+// verdict and rules in the form printed by test-rules and replay
+pub fn describe(verdict: &Verdict) -> String {
+    match verdict.rules() {
+        [] => verdict.name().to_string(),
+        rules => format!("{} [{}]", verdict.name(), rules.join(", ")),
+    }
+}
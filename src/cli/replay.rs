@@ -0,0 +1,105 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// replay: run a recorded event log back through a rule set and report every
+// request whose verdict would change. the log is json lines in the record
+// format, e.g. the detection events published on "zark.events"; a record
+// without a verdict was allowed when it was recorded.
+
+use std::collections::BTreeSet;
+use std::process::ExitCode;
+
+use zark_waf_common::inspection::Verdict;
+use zark_waf_dsl::RuleEngine;
+
+use super::record::{describe, read_records};
+use crate::core::CoreError;
+
+fn same_outcome(recorded: &Verdict, replayed: &Verdict) -> bool {
+    let rules = |verdict: &Verdict| verdict.rules().iter().cloned().collect::<BTreeSet<_>>();
+    recorded.name() == replayed.name() && rules(recorded) == rules(replayed)
+}
+
+pub async fn execute(events_path: &str, rules_path: &str, fail_on_change: bool) -> Result<ExitCode, CoreError> {
+    let engine = RuleEngine::from_file(rules_path)?;
+    let records = read_records(events_path).await?;
+
+    let (mut unchanged, mut changed, mut errors) = (0, 0, 0);
+    let (mut newly_blocked, mut unblocked) = (0, 0);
+    for (line, record) in records {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                println!("ERROR   {}: {}", line, e);
+                errors += 1;
+                continue;
+            }
+        };
+        let label = record.label(line);
+        let replayed = match record.evaluate(&engine) {
+            Ok(verdict) => verdict,
+            Err(e) => {
+                println!("ERROR   {}: {}", label, e);
+                errors += 1;
+                continue;
+            }
+        };
+
+        let recorded = record.verdict.clone().unwrap_or_default();
+        if same_outcome(&recorded, &replayed) {
+            unchanged += 1;
+            continue;
+        }
+        changed += 1;
+        match (recorded.is_blocked(), replayed.is_blocked()) {
+            (false, true) => newly_blocked += 1,
+            (true, false) => unblocked += 1,
+            _ => {}
+        }
+        println!(
+            "CHANGED {} {} {}: {} -> {}",
+            label,
+            record.method,
+            record.uri,
+            describe(&recorded),
+            describe(&replayed)
+        );
+    }
+
+    println!(
+        "{} events replayed against {} rules: {} unchanged, {} changed ({} newly blocked, {} no longer blocked), {} errors",
+        unchanged + changed,
+        engine.rules().len(),
+        unchanged,
+        changed,
+        newly_blocked,
+        unblocked,
+        errors
+    );
+    Ok(if errors > 0 || (fail_on_change && changed > 0) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
@@ -0,0 +1,91 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// test-rules: evaluate a rule file against a json lines file of requests and
+// print one verdict per request. fails if a line can't be read or a request
+// doesn't get the verdict named in its "expect" field.
+
+use std::process::ExitCode;
+
+use zark_waf_dsl::RuleEngine;
+
+use super::record::{describe, read_records};
+use crate::core::CoreError;
+
+const VERDICTS: [&str; 4] = ["allow", "log", "modify", "block"];
+
+pub async fn execute(rules_path: &str, requests_path: &str) -> Result<ExitCode, CoreError> {
+    let engine = RuleEngine::from_file(rules_path)?;
+    let records = read_records(requests_path).await?;
+
+    let (mut passed, mut failed, mut unchecked) = (0, 0, 0);
+    for (line, record) in records {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                println!("ERROR {}: {}", line, e);
+                failed += 1;
+                continue;
+            }
+        };
+        let label = record.label(line);
+        let verdict = match record.evaluate(&engine) {
+            Ok(verdict) => verdict,
+            Err(e) => {
+                println!("ERROR {}: {}", label, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let summary = format!("{} {} {} -> {}", label, record.method, record.uri, describe(&verdict));
+        match record.expect.as_deref().map(str::to_ascii_lowercase) {
+            None => {
+                println!("      {}", summary);
+                unchecked += 1;
+            }
+            Some(expected) if !VERDICTS.contains(&expected.as_str()) => {
+                println!("ERROR {}: unknown expected verdict '{}'", label, expected);
+                failed += 1;
+            }
+            Some(expected) if expected == verdict.name() => {
+                println!("PASS  {}", summary);
+                passed += 1;
+            }
+            Some(expected) => {
+                println!("FAIL  {} (expected {})", summary, expected);
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "{} rules, {} passed, {} failed, {} without expectation",
+        engine.rules().len(),
+        passed,
+        failed,
+        unchecked
+    );
+    Ok(if failed == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
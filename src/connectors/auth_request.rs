@@ -0,0 +1,150 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// nginx auth_request connector: answers nginx's verdict subrequests.
+//
+// nginx sends a subrequest for every client request and only looks at the
+// status: 2xx lets the request through, 401/403 denies it. the original
+// request line is passed in X-Original-Method / X-Original-URI, e.g.
+//
+//   location = /_zark {
+//       internal;
+//       proxy_pass http://127.0.0.1:8000;
+//       proxy_pass_request_body off;
+//       proxy_set_header Content-Length "";
+//       proxy_set_header X-Original-URI $request_uri;
+//       proxy_set_header X-Original-Method $request_method;
+//       proxy_set_header X-Real-IP $remote_addr;
+//   }
+//
+// the verdict and matched rules are returned in X-Zark-* headers, which nginx
+// can pick up with auth_request_set. the X-Original-* and X-Real-IP headers
+// are trusted as-is, so this endpoint must only be reachable by nginx.
+
+use std::net::{IpAddr, SocketAddr};
+
+use hyper::body::Incoming;
+use hyper::header::HeaderValue;
+use hyper::{Request, Response, StatusCode};
+use tokio::net::TcpListener;
+use zark_waf_common::inspection::{RequestContext, Verdict};
+use zark_waf_config_manager::config::WebServerConfig;
+
+use super::http::{full, is_hop_by_hop, read_request, serve_http, text_response, ResponseBody};
+use super::ConnectorContext;
+use crate::core::CoreError;
+
+const ORIGINAL_METHOD: &str = "x-original-method";
+const ORIGINAL_URI: &str = "x-original-uri";
+const REAL_IP: &str = "x-real-ip";
+
+pub struct AuthRequestServer {
+    name: String,
+    listener: TcpListener,
+    context: ConnectorContext,
+}
+
+impl AuthRequestServer {
+    pub async fn bind(name: &str, config: &WebServerConfig, context: ConnectorContext) -> Result<Self, CoreError> {
+        let listener = TcpListener::bind((config.host.as_str(), config.port)).await?;
+        log::info!("auth_request endpoint '{}' listening on {}:{}", name, config.host, config.port);
+
+        Ok(Self {
+            name: name.to_string(),
+            listener,
+            context,
+        })
+    }
+
+    pub async fn serve(self) {
+        let context = self.context.clone();
+        serve_http(self.name, self.listener, self.context, move |request, remote_addr| {
+            check_request(request, remote_addr, context.clone())
+        })
+        .await
+    }
+}
+
+async fn check_request(request: Request<Incoming>, remote_addr: SocketAddr, context: ConnectorContext) -> Response<ResponseBody> {
+    let subrequest = match read_request(request, remote_addr, context.max_body_size).await {
+        Ok(subrequest) => subrequest,
+        Err(status) => return text_response(status, status.canonical_reason().unwrap_or_default()),
+    };
+    let original = original_request(subrequest);
+    let verdict = context.inspect(&original).await;
+    verdict_response(&original, &verdict)
+}
+
+// reconstruct the client request nginx is asking about from the subrequest
+fn original_request(subrequest: RequestContext) -> RequestContext {
+    let method = subrequest.header(ORIGINAL_METHOD).unwrap_or(&subrequest.method).to_string();
+    let uri = subrequest.header(ORIGINAL_URI).unwrap_or(&subrequest.uri).to_string();
+    let remote_addr = subrequest
+        .header(REAL_IP)
+        .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, 0))
+        .or(subrequest.remote_addr);
+
+    let mut original = RequestContext::new(method, uri).with_body(subrequest.body);
+    original.id = subrequest.id;
+    original.remote_addr = remote_addr;
+    original.headers = subrequest
+        .headers
+        .into_iter()
+        .filter(|(name, _)| {
+            !is_hop_by_hop(name)
+                && !name.eq_ignore_ascii_case(ORIGINAL_METHOD)
+                && !name.eq_ignore_ascii_case(ORIGINAL_URI)
+                && !name.eq_ignore_ascii_case(REAL_IP)
+        })
+        .collect();
+    original
+}
+
+fn verdict_response(request: &RequestContext, verdict: &Verdict) -> Response<ResponseBody> {
+    // nginx treats any status other than 2xx/401/403 as an error, so every
+    // block is reported as 403 and the intended status goes in a header
+    let status = if verdict.is_blocked() { StatusCode::FORBIDDEN } else { StatusCode::OK };
+    let mut response = Response::new(full(""));
+    *response.status_mut() = status;
+
+    let headers = response.headers_mut();
+    headers.insert("x-zark-verdict", HeaderValue::from_static(verdict.name()));
+    if let Ok(id) = HeaderValue::from_str(&request.id) {
+        headers.insert("x-zark-request-id", id);
+    }
+    if !verdict.rules().is_empty() {
+        if let Ok(rules) = HeaderValue::from_str(&verdict.rules().join(", ")) {
+            headers.insert("x-zark-rules", rules);
+        }
+    }
+    if let Verdict::Block { status, reason, .. } = verdict {
+        headers.insert("x-zark-status", HeaderValue::from(*status));
+        if let Ok(reason) = HeaderValue::from_str(reason) {
+            headers.insert("x-zark-reason", reason);
+        }
+    }
+
+    response
+}
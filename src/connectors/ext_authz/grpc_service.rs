@@ -0,0 +1,251 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// ext_authz grpc flavour: serves envoy.service.auth.v3.Authorization/Check
+// over http/2, mapping the CheckRequest attributes onto a RequestContext.
+
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+use tonic::codegen::{http, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService, UnaryService};
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::Status;
+use tonic_prost::ProstCodec;
+use tokio::net::TcpListener;
+use zark_waf_common::inspection::{RequestContext, TlsInfo, Verdict};
+use zark_waf_config_manager::config::WebServerConfig;
+
+use super::proto::{
+    address, check_response, socket_address, CheckRequest, CheckResponse, DeniedHttpResponse, HeaderValue,
+    HeaderValueOption, HttpStatus, OkHttpResponse, Peer, RpcStatus, CHECK_PATH, CODE_OK, CODE_PERMISSION_DENIED,
+    OVERWRITE_IF_EXISTS_OR_ADD, SERVICE_NAME,
+};
+use super::{header_changes, verdict_headers};
+use crate::connectors::http::BLOCK_MESSAGE;
+use crate::connectors::ConnectorContext;
+use crate::core::CoreError;
+
+// room for the request attributes on top of the body in a CheckRequest
+const ATTRIBUTES_SIZE: usize = 64 * 1024;
+
+pub struct ExtAuthzGrpcServer {
+    name: String,
+    listener: TcpListener,
+    context: ConnectorContext,
+}
+
+impl ExtAuthzGrpcServer {
+    pub async fn bind(name: &str, config: &WebServerConfig, context: ConnectorContext) -> Result<Self, CoreError> {
+        let listener = TcpListener::bind((config.host.as_str(), config.port)).await?;
+        log::info!("ext_authz grpc service '{}' listening on {}:{}", name, config.host, config.port);
+
+        Ok(Self {
+            name: name.to_string(),
+            listener,
+            context,
+        })
+    }
+
+    // on shutdown tonic stops accepting, sends GOAWAY and lets in-flight
+    // checks finish
+    pub async fn serve(self) {
+        let state = self.context.state.clone();
+        let service = AuthorizationService { context: self.context };
+        if let Err(e) = Server::builder()
+            .add_service(service)
+            .serve_with_incoming_shutdown(TcpIncoming::from(self.listener), async move { state.stopped().await })
+            .await
+        {
+            log::error!("ext_authz grpc service '{}' stopped: {}", self.name, e);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AuthorizationService {
+    context: ConnectorContext,
+}
+
+impl NamedService for AuthorizationService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl<B> Service<http::Request<B>> for AuthorizationService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if request.uri().path() != CHECK_PATH {
+            return Box::pin(async { Ok(Status::unimplemented("unknown method").into_http()) });
+        }
+
+        let check = Check {
+            context: self.context.clone(),
+        };
+        let max_message_size = self.context.max_body_size.saturating_add(ATTRIBUTES_SIZE);
+        Box::pin(async move {
+            let mut grpc = Grpc::new(ProstCodec::default()).apply_max_message_size_config(Some(max_message_size), None);
+            Ok(grpc.unary(check, request).await)
+        })
+    }
+}
+
+struct Check {
+    context: ConnectorContext,
+}
+
+impl UnaryService<CheckRequest> for Check {
+    type Response = CheckResponse;
+    type Future = BoxFuture<tonic::Response<CheckResponse>, Status>;
+
+    fn call(&mut self, request: tonic::Request<CheckRequest>) -> Self::Future {
+        let context = self.context.clone();
+        Box::pin(async move {
+            // grpc multiplexes checks over a few long lived connections, so
+            // each check in flight counts as a connection
+            let _guard = context
+                .track_connection()
+                .ok_or_else(|| Status::resource_exhausted("connection limit reached"))?;
+
+            let original = original_request(request.into_inner())?;
+            let verdict = context.inspect(&original).await;
+            Ok(tonic::Response::new(check_response(&original, &verdict)))
+        })
+    }
+}
+
+fn original_request(request: CheckRequest) -> Result<RequestContext, Status> {
+    let attributes = request.attributes.unwrap_or_default();
+    let http = attributes
+        .request
+        .and_then(|request| request.http)
+        .ok_or_else(|| Status::invalid_argument("missing http request attributes"))?;
+
+    let mut headers: Vec<(String, String)> = match http.header_map {
+        Some(map) => map
+            .headers
+            .into_iter()
+            .map(|header| {
+                let value = if header.raw_value.is_empty() {
+                    header.value
+                } else {
+                    String::from_utf8_lossy(&header.raw_value).into_owned()
+                };
+                (header.key, value)
+            })
+            .collect(),
+        None => http.headers.into_iter().collect(),
+    };
+    // pseudo-headers (:authority, :path, ...) repeat the other attributes
+    headers.retain(|(name, _)| !name.starts_with(':'));
+    headers.sort();
+    if !http.host.is_empty() && !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("host")) {
+        headers.push(("host".to_string(), http.host));
+    }
+
+    let mut original = RequestContext::new(http.method, http.path);
+    original.headers = headers;
+    original.body = if http.raw_body.is_empty() {
+        http.body.into_bytes().into()
+    } else {
+        http.raw_body.into()
+    };
+    original.remote_addr = attributes.source.and_then(peer_addr);
+    if attributes.tls_session.is_some() || http.scheme == "https" {
+        original.tls = Some(TlsInfo {
+            server_name: attributes.tls_session.map(|tls| tls.sni).filter(|sni| !sni.is_empty()),
+            ..TlsInfo::default()
+        });
+    }
+
+    Ok(original)
+}
+
+fn peer_addr(peer: Peer) -> Option<SocketAddr> {
+    let Some(address::Address::SocketAddress(socket)) = peer.address?.address else {
+        return None;
+    };
+    let ip: IpAddr = socket.address.parse().ok()?;
+    let port = match socket.port_specifier {
+        Some(socket_address::PortSpecifier::PortValue(port)) => u16::try_from(port).unwrap_or(0),
+        None => 0,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+fn check_response(request: &RequestContext, verdict: &Verdict) -> CheckResponse {
+    let mut headers = verdict_headers(request, verdict);
+
+    if let Verdict::Block { status, .. } = verdict {
+        headers.push(("content-type".to_string(), "text/plain; charset=utf-8".to_string()));
+        return CheckResponse {
+            status: Some(RpcStatus {
+                code: CODE_PERMISSION_DENIED,
+                message: "request blocked".to_string(),
+            }),
+            http_response: Some(check_response::HttpResponse::DeniedResponse(DeniedHttpResponse {
+                status: Some(HttpStatus {
+                    code: i32::from(*status),
+                }),
+                headers: headers.into_iter().map(header_option).collect(),
+                body: BLOCK_MESSAGE.to_string(),
+            })),
+        };
+    }
+
+    let (set, headers_to_remove) = header_changes(verdict);
+    headers.extend(set);
+    CheckResponse {
+        status: Some(RpcStatus {
+            code: CODE_OK,
+            message: String::new(),
+        }),
+        http_response: Some(check_response::HttpResponse::OkResponse(OkHttpResponse {
+            headers: headers.into_iter().map(header_option).collect(),
+            headers_to_remove,
+        })),
+    }
+}
+
+fn header_option((key, value): (String, String)) -> HeaderValueOption {
+    HeaderValueOption {
+        header: Some(HeaderValue {
+            key,
+            value,
+            raw_value: Vec::new(),
+        }),
+        append_action: OVERWRITE_IF_EXISTS_OR_ADD,
+    }
+}
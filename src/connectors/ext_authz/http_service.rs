@@ -0,0 +1,117 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// ext_authz http flavour: envoy replays the client request (method, path,
+// allowed headers and optionally the body) against this endpoint and lets it
+// through on 200; any other response is returned to the client as-is.
+
+use std::net::{IpAddr, SocketAddr};
+
+use hyper::body::Incoming;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Request, Response};
+use tokio::net::TcpListener;
+use zark_waf_common::inspection::{RequestContext, Verdict};
+use zark_waf_config_manager::config::WebServerConfig;
+
+use super::{header_changes, verdict_headers};
+use crate::connectors::http::{block_response, full, is_hop_by_hop, read_request, serve_http, text_response, ResponseBody};
+use crate::connectors::ConnectorContext;
+use crate::core::CoreError;
+
+// response header telling envoy which upstream request headers to drop
+const HEADERS_TO_REMOVE: &str = "x-envoy-auth-headers-to-remove";
+
+pub struct ExtAuthzHttpServer {
+    name: String,
+    listener: TcpListener,
+    context: ConnectorContext,
+}
+
+impl ExtAuthzHttpServer {
+    pub async fn bind(name: &str, config: &WebServerConfig, context: ConnectorContext) -> Result<Self, CoreError> {
+        let listener = TcpListener::bind((config.host.as_str(), config.port)).await?;
+        log::info!("ext_authz http service '{}' listening on {}:{}", name, config.host, config.port);
+
+        Ok(Self {
+            name: name.to_string(),
+            listener,
+            context,
+        })
+    }
+
+    pub async fn serve(self) {
+        let context = self.context.clone();
+        serve_http(self.name, self.listener, self.context, move |request, remote_addr| {
+            check_request(request, remote_addr, context.clone())
+        })
+        .await
+    }
+}
+
+async fn check_request(request: Request<Incoming>, remote_addr: SocketAddr, context: ConnectorContext) -> Response<ResponseBody> {
+    let mut original = match read_request(request, remote_addr, context.max_body_size).await {
+        Ok(original) => original,
+        Err(status) => return text_response(status, status.canonical_reason().unwrap_or_default()),
+    };
+
+    // with use_remote_address set, envoy appends the downstream address to
+    // x-forwarded-for before the filter runs, so the last entry is trusted
+    let client_ip = original
+        .header("x-forwarded-for")
+        .and_then(|forwarded| forwarded.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+    if let Some(ip) = client_ip {
+        original.remote_addr = Some(SocketAddr::new(ip, 0));
+    }
+    original.headers.retain(|(name, _)| !is_hop_by_hop(name));
+
+    let verdict = context.inspect(&original).await;
+    verdict_response(&original, &verdict)
+}
+
+fn verdict_response(request: &RequestContext, verdict: &Verdict) -> Response<ResponseBody> {
+    let mut response = match verdict {
+        Verdict::Block { status, .. } => block_response(*status),
+        _ => Response::new(full("")),
+    };
+
+    let mut headers = verdict_headers(request, verdict);
+    let (set, remove) = header_changes(verdict);
+    headers.extend(set);
+    if !remove.is_empty() {
+        headers.push((HEADERS_TO_REMOVE.to_string(), remove.join(",")));
+    }
+
+    for (name, value) in headers {
+        match (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+            (Ok(name), Ok(value)) => {
+                response.headers_mut().insert(name, value);
+            }
+            _ => log::warn!("Skipping invalid header '{}' in ext_authz response", name),
+        }
+    }
+
+    response
+}
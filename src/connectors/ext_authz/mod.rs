@@ -0,0 +1,91 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// envoy ext_authz connectors: zark as an external authorization service, in
+// both the grpc (mode "ext-authz-grpc") and the http ("ext-authz-http")
+// flavour, e.g.
+//
+//   http_filters:
+//   - name: envoy.filters.http.ext_authz
+//     typed_config:
+//       "@type": type.googleapis.com/envoy.extensions.filters.http.ext_authz.v3.ExtAuthz
+//       transport_api_version: V3
+//       with_request_body: { max_request_bytes: 1048576, allow_partial_message: true }
+//       grpc_service:
+//         envoy_grpc: { cluster_name: zark }
+//
+// or, for the http flavour, with path_prefix left unset:
+//
+//       http_service:
+//         server_uri: { uri: "http://127.0.0.1:9191", cluster: zark, timeout: 0.5s }
+//         authorization_request:
+//           allowed_headers: { patterns: [{ prefix: "" }] }
+//         authorization_response:
+//           allowed_upstream_headers: { patterns: [{ prefix: "" }] }
+//
+// allowed requests carry the X-Zark-* verdict headers (and any header changes
+// from a modify verdict) upstream; blocked ones are answered by envoy with the
+// block status, the X-Zark-* headers and a short body. body modifications
+// (mask/sanitize) cannot be expressed through ext_authz and are skipped.
+
+mod grpc_service;
+mod http_service;
+mod proto;
+
+pub use grpc_service::ExtAuthzGrpcServer;
+pub use http_service::ExtAuthzHttpServer;
+
+use zark_waf_common::inspection::{Modification, RequestContext, Verdict};
+
+// headers describing the verdict: sent upstream when the request is allowed
+// and to the client when it is denied
+fn verdict_headers(request: &RequestContext, verdict: &Verdict) -> Vec<(String, String)> {
+    let mut headers = vec![
+        ("x-zark-verdict".to_string(), verdict.name().to_string()),
+        ("x-zark-request-id".to_string(), request.id.clone()),
+    ];
+    if !verdict.rules().is_empty() {
+        headers.push(("x-zark-rules".to_string(), verdict.rules().join(", ")));
+    }
+    if let Verdict::Block { reason, .. } = verdict {
+        headers.push(("x-zark-reason".to_string(), reason.clone()));
+    }
+    headers
+}
+
+// header changes requested by a modify verdict: (headers to set, headers to remove)
+fn header_changes(verdict: &Verdict) -> (Vec<(String, String)>, Vec<String>) {
+    let mut set = Vec::new();
+    let mut remove = Vec::new();
+    for modification in verdict.modifications() {
+        match modification {
+            Modification::SetHeader { name, value } => set.push((name.clone(), value.clone())),
+            Modification::RemoveHeader { name } => remove.push(name.clone()),
+            Modification::Mask { .. } | Modification::Sanitize { .. } => {
+                log::debug!("Skipping body modification, not supported by ext_authz")
+            }
+        }
+    }
+    (set, remove)
+}
@@ -0,0 +1,170 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// http helpers shared by the http based connectors
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use zark_waf_common::inspection::RequestContext;
+
+use super::ConnectorContext;
+
+pub type ResponseBody = BoxBody<Bytes, hyper::Error>;
+
+// body of the response sent to a client whose request was blocked
+pub const BLOCK_MESSAGE: &str = "Request blocked by ZARK-WAF\n";
+
+// headers that only apply to a single connection and must not be forwarded
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+pub fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name))
+}
+
+pub fn full<T: Into<Bytes>>(body: T) -> ResponseBody {
+    Full::new(body.into())
+        .map_err(|never: Infallible| match never {})
+        .boxed()
+}
+
+pub fn text_response(status: StatusCode, body: impl Into<Bytes>) -> Response<ResponseBody> {
+    let mut response = Response::new(full(body));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    response
+}
+
+// response sent to the client when the pipeline blocks its request
+pub fn block_response(status: u16) -> Response<ResponseBody> {
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::FORBIDDEN);
+    text_response(status, BLOCK_MESSAGE)
+}
+
+// accept http/1.1 connections and hand every request to the handler, along
+// with the address of the peer that sent it. on shutdown the listener is
+// closed and open connections are closed once their current request is done
+pub async fn serve_http<F, Fut>(name: String, listener: TcpListener, context: ConnectorContext, handler: F)
+where
+    F: Fn(Request<Incoming>, SocketAddr) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Response<ResponseBody>> + Send + 'static,
+{
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = context.state.stopped() => {
+                log::info!("Connector '{}' stopped accepting connections", name);
+                return;
+            }
+        };
+        let (stream, remote_addr) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::error!("Connector '{}' failed to accept a connection: {}", name, e);
+                continue;
+            }
+        };
+
+        let Some(guard) = context.track_connection() else {
+            log::warn!("Connection limit reached, dropping connection from {}", remote_addr);
+            continue;
+        };
+
+        let handler = handler.clone();
+        let state = context.state.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            let service = service_fn(move |request| {
+                let response = handler(request, remote_addr);
+                async move { Ok::<_, Infallible>(response.await) }
+            });
+            let connection = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+            tokio::pin!(connection);
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = state.stopped() => {
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(e) = result {
+                log::debug!("Connection from {} closed with error: {}", remote_addr, e);
+            }
+        });
+    }
+}
+
+// read a request and its body (up to max_body_size bytes) into a RequestContext
+pub async fn read_request(
+    request: Request<Incoming>,
+    remote_addr: SocketAddr,
+    max_body_size: usize,
+) -> Result<RequestContext, StatusCode> {
+    let (parts, body) = request.into_parts();
+    let body = Limited::new(body, max_body_size)
+        .collect()
+        .await
+        .map_err(|e| {
+            if e.is::<LengthLimitError>() {
+                StatusCode::PAYLOAD_TOO_LARGE
+            } else {
+                StatusCode::BAD_REQUEST
+            }
+        })?
+        .to_bytes();
+
+    let mut context = RequestContext::new(parts.method.as_str(), parts.uri.to_string())
+        .with_body(body)
+        .with_remote_addr(remote_addr);
+    for (name, value) in &parts.headers {
+        context.headers.push((
+            name.as_str().to_string(),
+            String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        ));
+    }
+
+    Ok(context)
+}
@@ -0,0 +1,363 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// icap connector: an rfc 3507 REQMOD/RESPMOD service for squid and other
+// forward proxies, e.g. in squid.conf
+//
+//   icap_enable on
+//   icap_send_client_ip on
+//   icap_service zark_req reqmod_precache icap://127.0.0.1:1344/reqmod bypass=off
+//   icap_service zark_resp respmod_precache icap://127.0.0.1:1344/respmod bypass=off
+//   adaptation_access zark_req allow all
+//   adaptation_access zark_resp allow all
+//
+// requests go through the request rules and responses through the response
+// rules. the proxy gets 204 when nothing changes (or an echo of the message
+// if it doesn't allow 204), a replacement block page when a rule blocks, and
+// the modified message when a rule masks or sanitizes it. every service path
+// is accepted; OPTIONS on a path containing "resp" advertises RESPMOD.
+
+mod protocol;
+
+use std::net::{IpAddr, SocketAddr};
+
+use hyper::StatusCode;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use zark_waf_common::inspection::{RequestContext, ResponseContext, Verdict};
+use zark_waf_config_manager::config::WebServerConfig;
+
+use self::protocol::{
+    read_chunks, read_encapsulated, read_request_head, HttpHead, IcapError, IcapMethod, IcapRequest, IcapResponse,
+};
+use super::http::BLOCK_MESSAGE;
+use super::ConnectorContext;
+use crate::core::CoreError;
+
+// identifies the service state to caching proxies; bumped with every release
+const ISTAG: &str = concat!("\"zark-", env!("CARGO_PKG_VERSION"), "\"");
+
+// body bytes we ask proxies to send up front, enough to block most attacks
+// without waiting for the rest of the body
+const PREVIEW_SIZE: usize = 4096;
+
+pub struct IcapServer {
+    name: String,
+    listener: TcpListener,
+    context: ConnectorContext,
+}
+
+impl IcapServer {
+    pub async fn bind(name: &str, config: &WebServerConfig, context: ConnectorContext) -> Result<Self, CoreError> {
+        let listener = TcpListener::bind((config.host.as_str(), config.port)).await?;
+        log::info!("ICAP service '{}' listening on {}:{}", name, config.host, config.port);
+
+        Ok(Self {
+            name: name.to_string(),
+            listener,
+            context,
+        })
+    }
+
+    pub async fn serve(self) {
+        loop {
+            let accepted = tokio::select! {
+                accepted = self.listener.accept() => accepted,
+                _ = self.context.state.stopped() => {
+                    log::info!("ICAP service '{}' stopped accepting connections", self.name);
+                    return;
+                }
+            };
+            let (stream, remote_addr) = match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::error!("ICAP service '{}' failed to accept a connection: {}", self.name, e);
+                    continue;
+                }
+            };
+
+            let Some(guard) = self.context.track_connection() else {
+                log::warn!("Connection limit reached, dropping connection from {}", remote_addr);
+                continue;
+            };
+
+            let context = self.context.clone();
+            tokio::spawn(async move {
+                let _guard = guard;
+                handle_connection(stream, remote_addr, context).await;
+            });
+        }
+    }
+}
+
+// serve icap requests one after the other until the proxy closes the
+// connection or asks us to. on shutdown an idle connection is closed right
+// away and a busy one after its current request
+async fn handle_connection(stream: TcpStream, remote_addr: SocketAddr, context: ConnectorContext) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        // wait for the next request without consuming it, so shutdown never
+        // interrupts a request half-way through its head
+        let idle = tokio::select! {
+            buffered = reader.fill_buf() => buffered.map(|buffered| buffered.is_empty()).unwrap_or(true),
+            _ = context.state.stopped() => true,
+        };
+        if idle {
+            return;
+        }
+
+        let result = match read_request_head(&mut reader).await {
+            Ok(Some(request)) => handle_request(&request, &mut reader, &mut writer, &context)
+                .await
+                .map(|response| (response, request.wants_close())),
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+
+        let (response, close) = match result {
+            Ok((response, _)) if !context.state.is_running() => (response.with_header("Connection", "close"), true),
+            Ok(handled) => handled,
+            Err(e) => {
+                // the framing of whatever follows is unknown, so give up on the connection
+                log::warn!("ICAP request from {} failed: {}", remote_addr, e);
+                (IcapResponse::new(e.status()).with_header("Connection", "close"), true)
+            }
+        };
+
+        if let Err(e) = writer.write_all(&response.to_bytes(ISTAG)).await {
+            log::debug!("Failed to write ICAP response to {}: {}", remote_addr, e);
+            return;
+        }
+        if close {
+            return;
+        }
+    }
+}
+
+async fn handle_request<R, W>(
+    request: &IcapRequest,
+    reader: &mut R,
+    writer: &mut W,
+    context: &ConnectorContext,
+) -> Result<IcapResponse, IcapError>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    match request.method {
+        IcapMethod::Options => Ok(options_response(request)),
+        IcapMethod::Reqmod | IcapMethod::Respmod => modify(request, reader, writer, context).await,
+    }
+}
+
+fn options_response(request: &IcapRequest) -> IcapResponse {
+    let method = if request.uri.to_ascii_lowercase().contains("resp") {
+        "RESPMOD"
+    } else {
+        "REQMOD"
+    };
+    IcapResponse::new(200)
+        .with_header("Methods", method)
+        .with_header("Service", "ZARK-WAF")
+        .with_header("Options-TTL", "3600")
+        .with_header("Allow", "204")
+        .with_header("Preview", PREVIEW_SIZE.to_string())
+        .with_header("Transfer-Preview", "*")
+}
+
+async fn modify<R, W>(
+    request: &IcapRequest,
+    reader: &mut R,
+    writer: &mut W,
+    context: &ConnectorContext,
+) -> Result<IcapResponse, IcapError>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let encapsulated = read_encapsulated(reader, request).await?;
+    let mut exchange = Exchange::new(request, encapsulated.request_head, encapsulated.response_head)?;
+
+    let mut body = Vec::new();
+    let mut overflow = false;
+    if encapsulated.has_body {
+        let end = read_chunks(reader, &mut body, context.max_body_size).await?;
+        overflow = end.overflow;
+
+        if request.preview().is_some() && !end.ieof && !overflow {
+            // decide on the preview first: a block doesn't need the rest of the body.
+            // anything else is decided again once the whole body is in
+            exchange.set_body(body.clone());
+            let verdict = exchange.inspect(context).await;
+            if let Verdict::Block { status, .. } = verdict {
+                return Ok(block_response(status, &exchange.request.id));
+            }
+
+            writer.write_all(&IcapResponse::new(100).to_bytes(ISTAG)).await?;
+            overflow = read_chunks(reader, &mut body, context.max_body_size).await?.overflow;
+        }
+    }
+
+    if overflow {
+        log::warn!("Request {} body exceeds max-body-size, blocking", exchange.request.id);
+        return Ok(block_response(StatusCode::PAYLOAD_TOO_LARGE.as_u16(), &exchange.request.id));
+    }
+
+    exchange.set_body(body);
+    let verdict = exchange.inspect(context).await;
+    Ok(match verdict {
+        Verdict::Block { status, .. } => block_response(status, &exchange.request.id),
+        verdict if verdict.modifications().is_empty() && request.allows_204() => IcapResponse::new(204),
+        // an empty modification list echoes the message back unchanged
+        verdict => exchange.modified_response(&verdict, encapsulated.has_body),
+    })
+}
+
+// the http message carried by a REQMOD (request) or RESPMOD (response) request
+struct Exchange {
+    request: RequestContext,
+    request_head: Option<HttpHead>,
+    response: Option<(ResponseContext, HttpHead)>,
+}
+
+impl Exchange {
+    fn new(
+        icap: &IcapRequest,
+        request_head: Option<HttpHead>,
+        response_head: Option<HttpHead>,
+    ) -> Result<Self, IcapError> {
+        // a RESPMOD without the original request is matched against an empty GET /
+        let mut request = match &request_head {
+            Some(head) => {
+                let (method, uri) = head.start_line_parts();
+                let mut request = RequestContext::new(method, uri);
+                request.headers = head.headers.clone();
+                request
+            }
+            None => RequestContext::new("GET", "/"),
+        };
+        // squid passes the client address with icap_send_client_ip
+        request.remote_addr = icap
+            .header("x-client-ip")
+            .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+            .map(|ip| SocketAddr::new(ip, 0));
+
+        let response = match (icap.method, response_head) {
+            (IcapMethod::Respmod, Some(head)) => {
+                let status = head
+                    .start_line_parts()
+                    .1
+                    .parse()
+                    .map_err(|_| IcapError::BadRequest(format!("invalid status line '{}'", head.start_line)))?;
+                let mut response = ResponseContext::new(status);
+                response.headers = head.headers.clone();
+                Some((response, head))
+            }
+            (IcapMethod::Respmod, None) => return Err(IcapError::BadRequest("RESPMOD without res-hdr".to_string())),
+            _ => None,
+        };
+        if icap.method == IcapMethod::Reqmod && request_head.is_none() {
+            return Err(IcapError::BadRequest("REQMOD without req-hdr".to_string()));
+        }
+
+        Ok(Self {
+            request,
+            request_head,
+            response,
+        })
+    }
+
+    fn set_body(&mut self, body: Vec<u8>) {
+        match &mut self.response {
+            Some((response, _)) => response.body = body.into(),
+            None => self.request.body = body.into(),
+        }
+    }
+
+    async fn inspect(&self, context: &ConnectorContext) -> Verdict {
+        match &self.response {
+            Some((response, _)) => context.inspect_response(&self.request, response).await,
+            None => context.inspect(&self.request).await,
+        }
+    }
+
+    // the encapsulated message with the verdict's modifications applied
+    fn modified_response(mut self, verdict: &Verdict, has_body: bool) -> IcapResponse {
+        let (section, body_section, mut head, body) = match self.response {
+            Some((mut response, head)) => {
+                response.apply(verdict.modifications());
+                let head = HttpHead {
+                    start_line: head.start_line,
+                    headers: response.headers,
+                };
+                ("res-hdr", "res-body", head, response.body)
+            }
+            None => {
+                self.request.apply(verdict.modifications());
+                let original = self.request_head.unwrap_or_else(|| HttpHead {
+                    start_line: String::new(),
+                    headers: Vec::new(),
+                });
+                let version = original.start_line.split_whitespace().nth(2).unwrap_or("HTTP/1.1");
+                let head = HttpHead {
+                    start_line: format!("{} {} {}", self.request.method, self.request.uri, version),
+                    headers: self.request.headers,
+                };
+                ("req-hdr", "req-body", head, self.request.body)
+            }
+        };
+
+        for (name, value) in &mut head.headers {
+            if name.eq_ignore_ascii_case("content-length") {
+                *value = body.len().to_string();
+            }
+        }
+
+        let response = IcapResponse::new(200).with_section(section, &head);
+        if has_body {
+            response.with_body(body_section, body.into())
+        } else {
+            response
+        }
+    }
+}
+
+// replace the message with the block page; REQMOD may answer with a response
+fn block_response(status: u16, request_id: &str) -> IcapResponse {
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::FORBIDDEN);
+    let head = HttpHead {
+        start_line: format!("HTTP/1.1 {} {}", status.as_u16(), status.canonical_reason().unwrap_or_default()),
+        headers: vec![
+            ("Content-Type".to_string(), "text/plain; charset=utf-8".to_string()),
+            ("Content-Length".to_string(), BLOCK_MESSAGE.len().to_string()),
+            ("Cache-Control".to_string(), "no-store".to_string()),
+            ("X-Zark-Request-Id".to_string(), request_id.to_string()),
+        ],
+    };
+    IcapResponse::new(200)
+        .with_section("res-hdr", &head)
+        .with_body("res-body", BLOCK_MESSAGE.as_bytes().to_vec())
+}
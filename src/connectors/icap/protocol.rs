@@ -0,0 +1,439 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// icap (rfc 3507) message framing: request heads, the Encapsulated header,
+// encapsulated http header blocks and chunked bodies
+
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+pub const ICAP_VERSION: &str = "ICAP/1.0";
+
+// upper bound for the icap head and for the encapsulated http headers
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+
+#[derive(Error, Debug)]
+pub enum IcapError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Method not implemented: {0}")]
+    UnsupportedMethod(String),
+    #[error("Unsupported ICAP version: {0}")]
+    UnsupportedVersion(String),
+}
+
+impl IcapError {
+    // icap status reported to the client for this error
+    pub fn status(&self) -> u16 {
+        match self {
+            IcapError::IoError(_) => 500,
+            IcapError::BadRequest(_) => 400,
+            IcapError::UnsupportedMethod(_) => 501,
+            IcapError::UnsupportedVersion(_) => 505,
+        }
+    }
+}
+
+fn bad_request(message: impl Into<String>) -> IcapError {
+    IcapError::BadRequest(message.into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcapMethod {
+    Options,
+    Reqmod,
+    Respmod,
+}
+
+// request line and headers of an icap request
+#[derive(Debug)]
+pub struct IcapRequest {
+    pub method: IcapMethod,
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl IcapRequest {
+    // first value of the header with the given name (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // whether the client accepts 204 No Content outside of a preview
+    pub fn allows_204(&self) -> bool {
+        self.header("allow")
+            .is_some_and(|allow| allow.split(',').any(|code| code.trim() == "204"))
+    }
+
+    // number of body bytes sent as a preview, if the client uses one
+    pub fn preview(&self) -> Option<usize> {
+        self.header("preview").and_then(|preview| preview.trim().parse().ok())
+    }
+
+    pub fn wants_close(&self) -> bool {
+        self.header("connection").is_some_and(|c| c.trim().eq_ignore_ascii_case("close"))
+    }
+}
+
+// the http message encapsulated in a REQMOD or RESPMOD request
+#[derive(Debug, Default)]
+pub struct Encapsulated {
+    pub request_head: Option<HttpHead>,
+    pub response_head: Option<HttpHead>,
+    // whether a chunked body follows the headers
+    pub has_body: bool,
+}
+
+// start line and headers of an encapsulated http message
+#[derive(Debug, Clone)]
+pub struct HttpHead {
+    pub start_line: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl HttpHead {
+    fn parse(block: &[u8]) -> Result<Self, IcapError> {
+        let text = String::from_utf8_lossy(block);
+        let mut lines = text.lines().map(|line| line.trim_end_matches('\r'));
+        let start_line = lines
+            .next()
+            .filter(|line| !line.is_empty())
+            .ok_or_else(|| bad_request("empty encapsulated header"))?
+            .to_string();
+        let headers = lines
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                line.split_once(':')
+                    .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                    .ok_or_else(|| bad_request(format!("invalid header line '{}'", line)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { start_line, headers })
+    }
+
+    // "METHOD URI VERSION" for requests, "VERSION STATUS REASON" for responses
+    pub fn start_line_parts(&self) -> (&str, &str) {
+        let mut parts = self.start_line.split_whitespace();
+        (parts.next().unwrap_or_default(), parts.next().unwrap_or_default())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = format!("{}\r\n", self.start_line);
+        for (name, value) in &self.headers {
+            out.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        out.push_str("\r\n");
+        out.into_bytes()
+    }
+}
+
+// how a run of chunks ended
+pub struct ChunksEnd {
+    // the client marked the end of the body inside a preview (0; ieof)
+    pub ieof: bool,
+    // more than the limit was sent; the excess was read and discarded
+    pub overflow: bool,
+}
+
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R, limit: usize) -> Result<Option<String>, IcapError> {
+    let mut line = Vec::new();
+    let read = (&mut *reader).take(limit as u64 + 1).read_until(b'\n', &mut line).await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.len() > limit {
+        return Err(bad_request("header line too long"));
+    }
+    if !line.ends_with(b"\n") {
+        return Err(bad_request("truncated message"));
+    }
+    let line = String::from_utf8_lossy(&line);
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+// read the request line and icap headers; Ok(None) when the client closed an
+// idle connection
+pub async fn read_request_head<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<IcapRequest>, IcapError> {
+    let request_line = loop {
+        match read_line(reader, MAX_HEADER_SIZE).await? {
+            None => return Ok(None),
+            // tolerate blank lines between requests
+            Some(line) if line.is_empty() => continue,
+            Some(line) => break line,
+        }
+    };
+
+    let mut parts = request_line.split_whitespace();
+    let (method, uri, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(uri), Some(version)) => (method, uri, version),
+        _ => return Err(bad_request(format!("invalid request line '{}'", request_line))),
+    };
+    if version != ICAP_VERSION {
+        return Err(IcapError::UnsupportedVersion(version.to_string()));
+    }
+    let method = match method {
+        "OPTIONS" => IcapMethod::Options,
+        "REQMOD" => IcapMethod::Reqmod,
+        "RESPMOD" => IcapMethod::Respmod,
+        other => return Err(IcapError::UnsupportedMethod(other.to_string())),
+    };
+
+    let mut headers = Vec::new();
+    let mut size = request_line.len();
+    loop {
+        let line = read_line(reader, MAX_HEADER_SIZE).await?.ok_or_else(|| bad_request("truncated message"))?;
+        if line.is_empty() {
+            break;
+        }
+        size += line.len();
+        if size > MAX_HEADER_SIZE {
+            return Err(bad_request("icap headers too large"));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| bad_request(format!("invalid header line '{}'", line)))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    Ok(Some(IcapRequest {
+        method,
+        uri: uri.to_string(),
+        headers,
+    }))
+}
+
+// read the encapsulated http headers announced by the Encapsulated header
+pub async fn read_encapsulated<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    request: &IcapRequest,
+) -> Result<Encapsulated, IcapError> {
+    let header = request
+        .header("encapsulated")
+        .ok_or_else(|| bad_request("missing Encapsulated header"))?;
+
+    let mut entries = Vec::new();
+    for entry in header.split(',') {
+        let (name, offset) = entry
+            .trim()
+            .split_once('=')
+            .ok_or_else(|| bad_request(format!("invalid Encapsulated entry '{}'", entry)))?;
+        let offset: usize = offset
+            .trim()
+            .parse()
+            .map_err(|_| bad_request(format!("invalid Encapsulated offset '{}'", offset)))?;
+        entries.push((name.trim().to_ascii_lowercase(), offset));
+    }
+
+    // the last entry marks where the body starts (or that there is none)
+    let (body_name, headers_size) = entries.pop().ok_or_else(|| bad_request("empty Encapsulated header"))?;
+    if !matches!(body_name.as_str(), "req-body" | "res-body" | "null-body" | "opt-body") {
+        return Err(bad_request(format!("Encapsulated header must end with a body entry, got '{}'", body_name)));
+    }
+    if headers_size > MAX_HEADER_SIZE {
+        return Err(bad_request("encapsulated headers too large"));
+    }
+
+    let mut block = vec![0u8; headers_size];
+    reader.read_exact(&mut block).await?;
+
+    let mut encapsulated = Encapsulated {
+        has_body: body_name != "null-body",
+        ..Encapsulated::default()
+    };
+    for (i, (name, offset)) in entries.iter().enumerate() {
+        let end = entries.get(i + 1).map(|(_, next)| *next).unwrap_or(headers_size);
+        let section = block
+            .get(*offset..end)
+            .ok_or_else(|| bad_request("Encapsulated offsets out of order"))?;
+        match name.as_str() {
+            "req-hdr" => encapsulated.request_head = Some(HttpHead::parse(section)?),
+            "res-hdr" => encapsulated.response_head = Some(HttpHead::parse(section)?),
+            other => return Err(bad_request(format!("unexpected Encapsulated entry '{}'", other))),
+        }
+    }
+
+    Ok(encapsulated)
+}
+
+// read chunks up to the terminating zero-size chunk, keeping at most `limit`
+// bytes of body in total
+pub async fn read_chunks<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    body: &mut Vec<u8>,
+    limit: usize,
+) -> Result<ChunksEnd, IcapError> {
+    let mut overflow = false;
+    loop {
+        let line = read_line(reader, MAX_HEADER_SIZE).await?.ok_or_else(|| bad_request("truncated body"))?;
+        let (size, extension) = match line.split_once(';') {
+            Some((size, extension)) => (size, extension.trim()),
+            None => (line.as_str(), ""),
+        };
+        let size = usize::from_str_radix(size.trim(), 16)
+            .map_err(|_| bad_request(format!("invalid chunk size '{}'", size)))?;
+
+        if size == 0 {
+            // skip trailers up to the empty line
+            while !read_line(reader, MAX_HEADER_SIZE).await?.unwrap_or_default().is_empty() {}
+            return Ok(ChunksEnd {
+                ieof: extension == "ieof",
+                overflow,
+            });
+        }
+
+        // the size comes from the client, so nothing is allocated for a chunk
+        // that wouldn't fit; its bytes are read and thrown away
+        if size <= limit.saturating_sub(body.len()) {
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..]).await?;
+        } else {
+            overflow = true;
+            let discarded = tokio::io::copy(&mut (&mut *reader).take(size as u64), &mut tokio::io::sink()).await?;
+            if discarded != size as u64 {
+                return Err(bad_request("truncated body"));
+            }
+        }
+        let mut crlf = [0u8; 2];
+        reader.read_exact(&mut crlf).await?;
+        if &crlf != b"\r\n" {
+            return Err(bad_request("chunk not terminated by CRLF"));
+        }
+    }
+}
+
+// an icap response, with an optional encapsulated http message
+pub struct IcapResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    // encapsulated header sections in order, e.g. ("res-hdr", bytes)
+    sections: Vec<(&'static str, Vec<u8>)>,
+    // encapsulated body, sent as a single chunk
+    body: Option<(&'static str, Vec<u8>)>,
+}
+
+impl IcapResponse {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            sections: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn with_section(mut self, name: &'static str, head: &HttpHead) -> Self {
+        self.sections.push((name, head.to_bytes()));
+        self
+    }
+
+    pub fn with_body(mut self, name: &'static str, body: Vec<u8>) -> Self {
+        self.body = Some((name, body));
+        self
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            100 => "Continue",
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            404 => "ICAP Service Not Found",
+            405 => "Method Not Allowed For Service",
+            501 => "Method Not Implemented",
+            505 => "ICAP Version Not Supported",
+            _ => "Server Error",
+        }
+    }
+
+    pub fn to_bytes(&self, istag: &str) -> Vec<u8> {
+        let mut out = format!("{} {} {}\r\nISTag: {}\r\n", ICAP_VERSION, self.status, self.reason(), istag);
+        for (name, value) in &self.headers {
+            out.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        let mut offset = 0;
+        let mut encapsulated = Vec::new();
+        for (name, bytes) in &self.sections {
+            encapsulated.push(format!("{}={}", name, offset));
+            offset += bytes.len();
+        }
+        match &self.body {
+            Some((name, _)) => encapsulated.push(format!("{}={}", name, offset)),
+            None => encapsulated.push(format!("null-body={}", offset)),
+        }
+        // 100 Continue and 204 No Content carry no encapsulated message
+        if self.status != 100 && self.status != 204 {
+            out.push_str(&format!("Encapsulated: {}\r\n", encapsulated.join(", ")));
+        }
+        out.push_str("\r\n");
+
+        let mut out = out.into_bytes();
+        for (_, bytes) in &self.sections {
+            out.extend_from_slice(bytes);
+        }
+        if let Some((_, body)) = &self.body {
+            if !body.is_empty() {
+                out.extend_from_slice(format!("{:x}\r\n", body.len()).as_bytes());
+                out.extend_from_slice(body);
+                out.extend_from_slice(b"\r\n");
+            }
+            out.extend_from_slice(b"0\r\n\r\n");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn oversized_chunk_is_discarded_without_allocating() {
+        let mut input: &[u8] = b"ffffffffffffffff\r\nabc";
+        let mut body = Vec::new();
+        let result = read_chunks(&mut input, &mut body, 1024).await;
+        assert!(matches!(result, Err(IcapError::BadRequest(_))));
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn chunks_past_the_limit_are_dropped() {
+        let mut input: &[u8] = b"3\r\nabc\r\n8\r\n01234567\r\n2\r\nde\r\n0\r\n\r\n";
+        let mut body = Vec::new();
+        let end = read_chunks(&mut input, &mut body, 6).await.unwrap();
+        assert!(end.overflow);
+        assert_eq!(body, b"abcde");
+    }
+}
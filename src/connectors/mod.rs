@@ -0,0 +1,147 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// connectors: the ways traffic reaches the inspection pipeline
+
+mod auth_request;
+mod ext_authz;
+pub mod http;
+mod icap;
+mod passive;
+mod proxy;
+mod spoa;
+
+pub use auth_request::AuthRequestServer;
+pub use ext_authz::{ExtAuthzGrpcServer, ExtAuthzHttpServer};
+pub use icap::IcapServer;
+pub use passive::PassiveMonitor;
+pub use proxy::ReverseProxy;
+pub use spoa::SpoaServer;
+
+use std::sync::Arc;
+use arc_swap::ArcSwap;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+use zark_waf_common::inspection::{RequestContext, ResponseContext, Verdict};
+use zark_waf_common::messenger::Messenger;
+use zark_waf_config_manager::config::{ConnectorMode, WebServerConfig};
+
+use crate::core::{CoreError, CoreState, InspectionPipeline};
+
+// everything a connector needs from the core, cheap to clone per connection
+#[derive(Clone)]
+pub struct ConnectorContext {
+    // the current inspection chain, swapped on reload
+    pub pipeline: Arc<ArcSwap<InspectionPipeline>>,
+    pub state: Arc<CoreState>,
+    pub messenger: Arc<Messenger>,
+    // bounds the number of concurrent connections across all connectors
+    pub connection_limit: Arc<Semaphore>,
+    pub max_body_size: usize,
+}
+
+impl ConnectorContext {
+    // run a request through the pipeline and count the verdict
+    pub async fn inspect(&self, request: &RequestContext) -> Verdict {
+        let verdict = self.pipeline.load_full().inspect(request).await;
+        self.state.inspections.record(&verdict);
+        verdict
+    }
+
+    pub async fn inspect_response(&self, request: &RequestContext, response: &ResponseContext) -> Verdict {
+        let verdict = self.pipeline.load_full().inspect_response(request, response).await;
+        self.state.inspections.record(&verdict);
+        verdict
+    }
+
+    // reserve a connection slot, or None when max-connections is reached
+    pub fn track_connection(&self) -> Option<ConnectionGuard> {
+        let permit = self.connection_limit.clone().try_acquire_owned().ok()?;
+        self.state.increment_connections();
+        Some(ConnectionGuard {
+            state: self.state.clone(),
+            _permit: permit,
+        })
+    }
+}
+
+// keeps a connection counted in CoreState until dropped
+pub struct ConnectionGuard {
+    state: Arc<CoreState>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.state.decrement_connections();
+    }
+}
+
+// check a web-servers entry for the mistakes start() would fail on, without
+// binding or opening anything
+pub fn validate(name: &str, config: &WebServerConfig) -> Result<(), CoreError> {
+    match config.mode {
+        ConnectorMode::Proxy => ReverseProxy::upstream(name, config).map(drop),
+        ConnectorMode::Passive => PassiveMonitor::log_format(name, config).map(drop),
+        ConnectorMode::AuthRequest
+        | ConnectorMode::Spoa
+        | ConnectorMode::ExtAuthzGrpc
+        | ConnectorMode::ExtAuthzHttp
+        | ConnectorMode::Icap => Ok(()),
+    }
+}
+
+// bind the connector for a web-servers entry according to its mode and spawn
+// it onto the given set
+pub async fn start(
+    name: &str,
+    config: &WebServerConfig,
+    context: ConnectorContext,
+    connectors: &mut JoinSet<()>,
+) -> Result<(), CoreError> {
+    match config.mode {
+        ConnectorMode::Proxy => {
+            connectors.spawn(ReverseProxy::bind(name, config, context).await?.serve());
+        }
+        ConnectorMode::AuthRequest => {
+            connectors.spawn(AuthRequestServer::bind(name, config, context).await?.serve());
+        }
+        ConnectorMode::Spoa => {
+            connectors.spawn(SpoaServer::bind(name, config, context).await?.serve());
+        }
+        ConnectorMode::ExtAuthzGrpc => {
+            connectors.spawn(ExtAuthzGrpcServer::bind(name, config, context).await?.serve());
+        }
+        ConnectorMode::ExtAuthzHttp => {
+            connectors.spawn(ExtAuthzHttpServer::bind(name, config, context).await?.serve());
+        }
+        ConnectorMode::Icap => {
+            connectors.spawn(IcapServer::bind(name, config, context).await?.serve());
+        }
+        ConnectorMode::Passive => {
+            connectors.spawn(PassiveMonitor::open(name, config, context).await?.serve());
+        }
+    }
+    Ok(())
+}
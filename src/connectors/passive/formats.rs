@@ -0,0 +1,219 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// access log parsers for the formats passive mode understands
+
+use std::net::{IpAddr, SocketAddr};
+
+use zark_waf_common::inspection::RequestContext;
+use zark_waf_config_manager::config::LogFormat;
+
+// fields iis logs when no #Fields directive has been seen yet
+const IIS_DEFAULT_FIELDS: &str = "date time s-ip cs-method cs-uri-stem cs-uri-query s-port cs-username c-ip \
+                                  cs(User-Agent) cs(Referer) sc-status sc-substatus sc-win32-status time-taken";
+
+// a logged request and the status it was answered with
+pub struct LogEntry {
+    pub request: RequestContext,
+    pub status: Option<u16>,
+}
+
+pub struct LogParser {
+    format: LogFormat,
+    // current w3c field list, updated by #Fields directives
+    iis_fields: Vec<String>,
+}
+
+impl LogParser {
+    pub fn new(format: LogFormat) -> Self {
+        Self {
+            format,
+            iis_fields: IIS_DEFAULT_FIELDS.split_whitespace().map(str::to_string).collect(),
+        }
+    }
+
+    // None for directives and lines that don't describe a request
+    pub fn parse(&mut self, line: &str) -> Option<LogEntry> {
+        match self.format {
+            LogFormat::NginxCombined | LogFormat::ApacheCombined => parse_combined(line),
+            LogFormat::HaproxyHttp => parse_haproxy(line),
+            LogFormat::IisW3c => self.parse_iis(line),
+        }
+    }
+
+    // date time s-ip cs-method cs-uri-stem cs-uri-query ... with "-" for empty
+    // values and "+" for spaces in user agents
+    fn parse_iis(&mut self, line: &str) -> Option<LogEntry> {
+        if let Some(directive) = line.strip_prefix('#') {
+            if let Some(fields) = directive.strip_prefix("Fields:") {
+                self.iis_fields = fields.split_whitespace().map(str::to_string).collect();
+            }
+            return None;
+        }
+
+        let values: Vec<&str> = line.split_whitespace().collect();
+        let field = |name: &str| {
+            self.iis_fields
+                .iter()
+                .position(|f| f.eq_ignore_ascii_case(name))
+                .and_then(|i| values.get(i).copied())
+                .filter(|value| *value != "-")
+        };
+
+        let mut uri = field("cs-uri-stem")?.to_string();
+        if let Some(query) = field("cs-uri-query") {
+            uri = format!("{}?{}", uri, query);
+        }
+        let mut request = RequestContext::new(field("cs-method")?, uri);
+        request.remote_addr = field("c-ip").and_then(parse_ip);
+        for (name, header) in [("cs(User-Agent)", "User-Agent"), ("cs(Referer)", "Referer"), ("cs(Cookie)", "Cookie"), ("cs-host", "Host")] {
+            if let Some(value) = field(name) {
+                request.headers.push((header.to_string(), value.replace('+', " ")));
+            }
+        }
+
+        Some(LogEntry {
+            status: field("sc-status").and_then(|status| status.parse().ok()),
+            request,
+        })
+    }
+}
+
+// nginx and apache "combined":
+// 1.2.3.4 - user [10/Oct/2024:13:55:36 +0000] "GET /a?b=c HTTP/1.1" 200 512 "referer" "user agent"
+fn parse_combined(line: &str) -> Option<LogEntry> {
+    let (remote, rest) = line.split_once(' ')?;
+    let rest = &rest[rest.find(']')? + 1..];
+
+    let mut fields = Fields::new(rest);
+    let request_line = fields.next()?;
+    let status = fields.next().and_then(|status| status.parse().ok());
+    let _bytes_sent = fields.next();
+    let referer = fields.next().filter(|value| value != "-");
+    let user_agent = fields.next().filter(|value| value != "-");
+
+    let mut request = request_from_line(&request_line)?;
+    request.remote_addr = parse_ip(remote);
+    if let Some(referer) = referer {
+        request.headers.push(("Referer".to_string(), referer));
+    }
+    if let Some(user_agent) = user_agent {
+        request.headers.push(("User-Agent".to_string(), user_agent));
+    }
+
+    Some(LogEntry { request, status })
+}
+
+// haproxy "option httplog", optionally behind a syslog prefix:
+// haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 {1wt.eu} {} "GET /index.html HTTP/1.1"
+fn parse_haproxy(line: &str) -> Option<LogEntry> {
+    let rest = match line.find("]: ") {
+        Some(i) if line[..i].contains("haproxy[") => &line[i + 3..],
+        _ => line,
+    };
+
+    let mut tokens = rest.split_whitespace();
+    let client = tokens.next()?;
+    // accept date, frontend, backend/server, timers
+    let status = tokens.nth(4).and_then(|status| status.parse().ok());
+
+    let start = rest.find('"')?;
+    let end = rest.rfind('"')?;
+    if end <= start {
+        return None;
+    }
+    let mut request = request_from_line(&rest[start + 1..end])?;
+    request.remote_addr = client.rsplit_once(':').and_then(|(ip, port)| {
+        let ip: IpAddr = ip.trim_start_matches('[').trim_end_matches(']').parse().ok()?;
+        Some(SocketAddr::new(ip, port.parse().unwrap_or(0)))
+    });
+
+    Some(LogEntry { request, status })
+}
+
+// "GET /path?query HTTP/1.1"
+fn request_from_line(line: &str) -> Option<RequestContext> {
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?;
+    let uri = parts.next()?;
+    Some(RequestContext::new(method, uri))
+}
+
+fn parse_ip(value: &str) -> Option<SocketAddr> {
+    value.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, 0))
+}
+
+// splits the rest of a combined line into bare tokens and quoted strings,
+// undoing the \" and \xHH escapes apache and nginx write inside quotes
+struct Fields<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Fields<'a> {
+    fn new(rest: &'a str) -> Self {
+        Self { rest }
+    }
+}
+
+impl Iterator for Fields<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.rest = self.rest.trim_start();
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        let Some(quoted) = self.rest.strip_prefix('"') else {
+            let end = self.rest.find(char::is_whitespace).unwrap_or(self.rest.len());
+            let token = &self.rest[..end];
+            self.rest = &self.rest[end..];
+            return Some(token.to_string());
+        };
+
+        let bytes = quoted.as_bytes();
+        let mut value = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() && bytes[i] != b'"' {
+            if bytes[i] == b'\\' && i + 1 < bytes.len() {
+                let hex = quoted.get(i + 2..i + 4).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match (bytes[i + 1], hex) {
+                    (b'x', Some(byte)) => {
+                        value.push(byte);
+                        i += 4;
+                    }
+                    (escaped, _) => {
+                        value.push(escaped);
+                        i += 2;
+                    }
+                }
+                continue;
+            }
+            value.push(bytes[i]);
+            i += 1;
+        }
+        self.rest = quoted.get(i + 1..).unwrap_or_default();
+        Some(String::from_utf8_lossy(&value).into_owned())
+    }
+}
@@ -0,0 +1,174 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// passive connector: detect-only monitoring of a web server's access log.
+//
+// log-path is followed across rotation and every logged request goes through
+// the request rules, and through the response rules with the logged status.
+// nothing is enforced: matches are counted in CoreState, logged, published as
+// events on "zark.events" and, for requests that would have been blocked, as
+// alerts on "zark.alerts". meant for onboarding applications before putting
+// zark inline.
+
+mod formats;
+mod tail;
+
+use std::time::Duration;
+
+use serde::Serialize;
+use zark_waf_common::inspection::{ResponseContext, Verdict};
+use zark_waf_config_manager::config::{LogFormat, WebServerConfig};
+
+use self::formats::{LogEntry, LogParser};
+use self::tail::LogTailer;
+use super::ConnectorContext;
+use crate::core::CoreError;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const EVENT_TOPIC: &str = "zark.events";
+const ALERT_TOPIC: &str = "zark.alerts";
+
+// published for every logged request that matched a rule
+#[derive(Serialize)]
+struct DetectionEvent<'a> {
+    source: &'a str,
+    mode: &'static str,
+    request_id: &'a str,
+    method: &'a str,
+    uri: &'a str,
+    remote_addr: Option<String>,
+    status: Option<u16>,
+    verdict: &'a Verdict,
+}
+
+pub struct PassiveMonitor {
+    name: String,
+    tailer: LogTailer,
+    parser: LogParser,
+    context: ConnectorContext,
+}
+
+impl PassiveMonitor {
+    pub async fn open(name: &str, config: &WebServerConfig, context: ConnectorContext) -> Result<Self, CoreError> {
+        let format = Self::log_format(name, config)?;
+        let tailer = LogTailer::open(&config.log_path).await?;
+        log::info!("Passive monitor '{}' following {} ({:?})", name, config.log_path, format);
+
+        Ok(Self {
+            name: name.to_string(),
+            tailer,
+            parser: LogParser::new(format),
+            context,
+        })
+    }
+
+    pub fn log_format(name: &str, config: &WebServerConfig) -> Result<LogFormat, CoreError> {
+        config.log_format.ok_or_else(|| {
+            CoreError::InitError(format!("web server '{}' needs a log-format for passive mode", name))
+        })
+    }
+
+    pub async fn serve(mut self) {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        // only warn when the error changes, the log is polled twice a second
+        let mut last_error = None;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.context.state.stopped() => {
+                    log::info!("Passive monitor '{}' stopped", self.name);
+                    return;
+                }
+            }
+
+            let lines = match self.tailer.read_lines().await {
+                Ok(lines) => {
+                    last_error = None;
+                    lines
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    if last_error.as_ref() != Some(&error) {
+                        log::warn!("Passive monitor '{}' failed to read its log: {}", self.name, error);
+                        last_error = Some(error);
+                    }
+                    continue;
+                }
+            };
+
+            for line in lines {
+                match self.parser.parse(&line) {
+                    Some(entry) => self.inspect(entry).await,
+                    None => log::debug!("Passive monitor '{}' skipped line: {}", self.name, line),
+                }
+            }
+        }
+    }
+
+    async fn inspect(&self, entry: LogEntry) {
+        let request = &entry.request;
+        let mut verdict = self.context.inspect(request).await;
+        if let (Some(status), false) = (entry.status, verdict.is_blocked()) {
+            let response = ResponseContext::new(status);
+            verdict = verdict.merge(self.context.inspect_response(request, &response).await);
+        }
+        if verdict == Verdict::Allow {
+            return;
+        }
+
+        let event = DetectionEvent {
+            source: &self.name,
+            mode: "passive",
+            request_id: &request.id,
+            method: &request.method,
+            uri: &request.uri,
+            remote_addr: request.remote_addr.map(|addr| addr.ip().to_string()),
+            status: entry.status,
+            verdict: &verdict,
+        };
+        if verdict.is_blocked() {
+            log::warn!(
+                "Passive monitor '{}': request {} {} {} would have been blocked by [{}]",
+                self.name, request.id, request.method, request.uri, verdict.rules().join(", ")
+            );
+            self.publish(ALERT_TOPIC, &event).await;
+        }
+        self.publish(EVENT_TOPIC, &event).await;
+    }
+
+    async fn publish(&self, topic: &str, event: &DetectionEvent<'_>) {
+        let payload = match serde_json::to_vec(event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                log::error!("Failed to serialize detection event: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = self.context.messenger.send(topic, &payload).await {
+            log::debug!("Failed to publish detection event on {}: {}", topic, e);
+        }
+    }
+}
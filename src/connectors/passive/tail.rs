@@ -0,0 +1,145 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// follows a log file like `tail -F`: only lines written after startup, and
+// across rotation (rename + recreate) or truncation (copytruncate)
+
+use std::fs::Metadata;
+use std::io;
+use std::path::PathBuf;
+
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+// a line longer than this is not a log entry; it is dropped
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+#[cfg(unix)]
+type FileIdentity = (u64, u64);
+#[cfg(not(unix))]
+type FileIdentity = Option<std::time::SystemTime>;
+
+#[cfg(unix)]
+fn identity(metadata: &Metadata) -> FileIdentity {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn identity(metadata: &Metadata) -> FileIdentity {
+    metadata.created().ok()
+}
+
+pub struct LogTailer {
+    path: PathBuf,
+    file: Option<File>,
+    identity: Option<FileIdentity>,
+    offset: u64,
+    // bytes after the last newline, completed by the next read
+    pending: Vec<u8>,
+}
+
+impl LogTailer {
+    // start at the current end of the file; a file that doesn't exist yet is
+    // read from the start once it appears
+    pub async fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let mut tailer = Self {
+            path: path.into(),
+            file: None,
+            identity: None,
+            offset: 0,
+            pending: Vec::new(),
+        };
+
+        match File::open(&tailer.path).await {
+            Ok(mut file) => {
+                tailer.identity = Some(identity(&file.metadata().await?));
+                tailer.offset = file.seek(io::SeekFrom::End(0)).await?;
+                tailer.file = Some(file);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                log::warn!("{} does not exist yet, waiting for it", tailer.path.display());
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(tailer)
+    }
+
+    // complete lines written since the last call
+    pub async fn read_lines(&mut self) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        self.read_available(&mut lines).await?;
+
+        // the old file is drained, switch over if the path points elsewhere now
+        match fs::metadata(&self.path).await {
+            Ok(metadata) => {
+                let rotated = self.identity != Some(identity(&metadata));
+                let truncated = !rotated && metadata.len() < self.offset;
+                if rotated || truncated {
+                    if self.file.is_some() {
+                        log::info!("{} was rotated, reopening", self.path.display());
+                    }
+                    let file = File::open(&self.path).await?;
+                    self.identity = Some(identity(&file.metadata().await?));
+                    self.file = Some(file);
+                    self.offset = 0;
+                    self.pending.clear();
+                    self.read_available(&mut lines).await?;
+                }
+            }
+            // moved away and not recreated yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        Ok(lines)
+    }
+
+    async fn read_available(&mut self, lines: &mut Vec<String>) -> io::Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+
+        let read = file.read_to_end(&mut self.pending).await?;
+        self.offset += read as u64;
+
+        let mut start = 0;
+        while let Some(newline) = self.pending[start..].iter().position(|b| *b == b'\n') {
+            let line = &self.pending[start..start + newline];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if !line.is_empty() {
+                lines.push(String::from_utf8_lossy(line).into_owned());
+            }
+            start += newline + 1;
+        }
+        self.pending.drain(..start);
+
+        if self.pending.len() > MAX_LINE_LENGTH {
+            log::warn!("Dropping over-long line in {}", self.path.display());
+            self.pending.clear();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// reverse proxy connector: inspects each request and forwards allowed traffic upstream
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::body::Incoming;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Request, Response, StatusCode, Uri};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use tokio::net::TcpListener;
+use zark_waf_common::inspection::{RequestContext, Verdict};
+use zark_waf_config_manager::config::WebServerConfig;
+
+use super::http::{block_response, is_hop_by_hop, read_request, serve_http, text_response, ResponseBody};
+use super::ConnectorContext;
+use crate::core::CoreError;
+
+type UpstreamClient = Client<HttpConnector, http_body_util::Full<Bytes>>;
+
+pub struct ReverseProxy {
+    name: String,
+    listener: TcpListener,
+    upstream: Arc<Uri>,
+    client: UpstreamClient,
+    context: ConnectorContext,
+}
+
+impl ReverseProxy {
+    // bind the listen address of a web-servers entry
+    pub async fn bind(name: &str, config: &WebServerConfig, context: ConnectorContext) -> Result<Self, CoreError> {
+        let upstream = Self::upstream(name, config)?;
+        if config.ssl_enabled {
+            log::warn!("Web server '{}' has ssl-enabled set, but the proxy only serves plain http", name);
+        }
+
+        let listener = TcpListener::bind((config.host.as_str(), config.port)).await?;
+        log::info!("Reverse proxy '{}' listening on {}:{}, forwarding to {}", name, config.host, config.port, upstream);
+
+        Ok(Self {
+            name: name.to_string(),
+            listener,
+            upstream: Arc::new(upstream),
+            client: Client::builder(TokioExecutor::new()).build_http(),
+            context,
+        })
+    }
+
+    // the upstream of a web-servers entry, which must be an absolute http uri
+    pub fn upstream(name: &str, config: &WebServerConfig) -> Result<Uri, CoreError> {
+        let upstream = config.upstream.as_deref().ok_or_else(|| {
+            CoreError::InitError(format!("Web server '{}' has no upstream configured", name))
+        })?;
+        let upstream: Uri = upstream.parse().map_err(|e| {
+            CoreError::InitError(format!("Invalid upstream '{}' for web server '{}': {}", upstream, name, e))
+        })?;
+        if upstream.scheme_str() != Some("http") || upstream.authority().is_none() {
+            return Err(CoreError::InitError(format!(
+                "Upstream for web server '{}' must be an absolute http:// uri",
+                name
+            )));
+        }
+        Ok(upstream)
+    }
+
+    pub async fn serve(self) {
+        let context = self.context.clone();
+        let upstream = self.upstream;
+        let client = self.client;
+        serve_http(self.name, self.listener, self.context, move |request, remote_addr| {
+            proxy_request(request, remote_addr, context.clone(), upstream.clone(), client.clone())
+        })
+        .await
+    }
+}
+
+async fn proxy_request(
+    request: Request<Incoming>,
+    remote_addr: SocketAddr,
+    context: ConnectorContext,
+    upstream: Arc<Uri>,
+    client: UpstreamClient,
+) -> Response<ResponseBody> {
+    let mut request = match read_request(request, remote_addr, context.max_body_size).await {
+        Ok(request) => request,
+        Err(status) => return text_response(status, status.canonical_reason().unwrap_or_default()),
+    };
+
+    match context.inspect(&request).await {
+        Verdict::Block { status, .. } => return block_response(status),
+        Verdict::Modify { modifications, .. } => request.apply(&modifications),
+        Verdict::Allow | Verdict::Log { .. } => {}
+    }
+
+    let forwarded = match upstream_request(&request, &upstream) {
+        Ok(forwarded) => forwarded,
+        Err(e) => {
+            log::warn!("Request {} could not be forwarded: {}", request.id, e);
+            return text_response(StatusCode::BAD_REQUEST, "Bad Request\n");
+        }
+    };
+
+    match client.request(forwarded).await {
+        Ok(response) => {
+            let (mut parts, body) = response.into_parts();
+            strip_hop_by_hop(&mut parts.headers);
+            Response::from_parts(parts, body.boxed())
+        }
+        Err(e) => {
+            log::error!("Upstream {} failed for request {}: {}", upstream, request.id, e);
+            text_response(StatusCode::BAD_GATEWAY, "Bad Gateway\n")
+        }
+    }
+}
+
+// rebuild the (possibly modified) request for the upstream
+fn upstream_request(request: &RequestContext, upstream: &Uri) -> Result<Request<http_body_util::Full<Bytes>>, hyper::http::Error> {
+    let base_path = upstream.path().trim_end_matches('/');
+    let path = request.path();
+    let target = match request.query() {
+        Some(query) => format!("{}{}?{}", base_path, path, query),
+        None => format!("{}{}", base_path, path),
+    };
+    let uri = Uri::builder()
+        .scheme("http")
+        .authority(upstream.authority().map(|a| a.as_str()).unwrap_or_default())
+        .path_and_query(target)
+        .build()?;
+
+    let mut builder = Request::builder().method(request.method.as_str()).uri(uri);
+    let mut forwarded_for = None;
+    for (name, value) in &request.headers {
+        if is_hop_by_hop(name) || name.eq_ignore_ascii_case("content-length") {
+            continue;
+        }
+        if name.eq_ignore_ascii_case("x-forwarded-for") {
+            forwarded_for = Some(value.clone());
+            continue;
+        }
+        builder = builder.header(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+    }
+
+    if let Some(remote_addr) = request.remote_addr {
+        let client_ip = remote_addr.ip().to_string();
+        let forwarded_for = match forwarded_for {
+            Some(existing) => format!("{}, {}", existing, client_ip),
+            None => client_ip,
+        };
+        builder = builder
+            .header("x-forwarded-for", forwarded_for)
+            .header("x-forwarded-proto", "http");
+    }
+
+    builder.body(http_body_util::Full::new(request.body.clone()))
+}
+
+fn strip_hop_by_hop(headers: &mut hyper::HeaderMap) {
+    let names: Vec<HeaderName> = headers
+        .keys()
+        .filter(|name| is_hop_by_hop(name.as_str()))
+        .cloned()
+        .collect();
+    for name in names {
+        headers.remove(name);
+    }
+}
@@ -0,0 +1,360 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// haproxy spoa connector: zark as a stream processing offload agent.
+//
+// haproxy sends the request through a spoe filter and applies the verdict
+// from the transaction variables set in the reply, e.g.
+//
+//   # haproxy.cfg
+//   frontend web
+//       option http-buffer-request
+//       filter spoe engine zark config /etc/haproxy/zark-spoe.conf
+//       http-request deny deny_status 403 if { var(txn.zark.verdict) -m str block }
+//
+//   backend zark-agents
+//       mode tcp
+//       server zark 127.0.0.1:12345
+//
+//   # zark-spoe.conf
+//   [zark]
+//   spoe-agent zark-agent
+//       messages zark-request
+//       option var-prefix zark
+//       timeout hello 2s
+//       timeout idle 2m
+//       timeout processing 500ms
+//       use-backend zark-agents
+//
+//   spoe-message zark-request
+//       args method=method path=path query=query headers=req.hdrs_bin body=req.body ip=src
+//       event on-frontend-http-request
+//
+// the reply sets txn.zark.verdict (allow/log/modify/block), txn.zark.rule
+// (matched rules, comma separated), txn.zark.status (block status) and
+// txn.zark.request_id.
+
+mod protocol;
+
+use std::net::{IpAddr, SocketAddr};
+
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use zark_waf_common::inspection::{RequestContext, TlsInfo, Verdict};
+use zark_waf_config_manager::config::WebServerConfig;
+
+use self::protocol::{
+    decode_headers, decode_kv_list, decode_messages, encode_actions, encode_kv_list, read_frame, write_frame, Frame,
+    FrameType, Message, SetVar, SpopError, TypedData, FLAG_FIN, MAX_FRAME_SIZE, SPOP_VERSION,
+};
+use super::ConnectorContext;
+use crate::core::CoreError;
+
+// ACK frames waiting to be written, per connection
+const PENDING_FRAMES: usize = 64;
+
+pub struct SpoaServer {
+    name: String,
+    listener: TcpListener,
+    context: ConnectorContext,
+}
+
+impl SpoaServer {
+    pub async fn bind(name: &str, config: &WebServerConfig, context: ConnectorContext) -> Result<Self, CoreError> {
+        let listener = TcpListener::bind((config.host.as_str(), config.port)).await?;
+        log::info!("SPOA agent '{}' listening on {}:{}", name, config.host, config.port);
+
+        Ok(Self {
+            name: name.to_string(),
+            listener,
+            context,
+        })
+    }
+
+    pub async fn serve(self) {
+        loop {
+            let accepted = tokio::select! {
+                accepted = self.listener.accept() => accepted,
+                _ = self.context.state.stopped() => {
+                    log::info!("SPOA agent '{}' stopped accepting connections", self.name);
+                    return;
+                }
+            };
+            let (stream, remote_addr) = match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::error!("SPOA agent '{}' failed to accept a connection: {}", self.name, e);
+                    continue;
+                }
+            };
+
+            let Some(guard) = self.context.track_connection() else {
+                log::warn!("Connection limit reached, dropping connection from {}", remote_addr);
+                continue;
+            };
+
+            let context = self.context.clone();
+            tokio::spawn(async move {
+                let _guard = guard;
+                handle_connection(stream, remote_addr, context).await;
+            });
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream, remote_addr: SocketAddr, context: ConnectorContext) {
+    let (mut reader, mut writer) = stream.into_split();
+
+    let max_frame_size = match handshake(&mut reader, &mut writer).await {
+        Ok(Some(max_frame_size)) => max_frame_size,
+        Ok(None) => return,
+        Err(e) => {
+            log::warn!("SPOA handshake with {} failed: {}", remote_addr, e);
+            let _ = write_frame(&mut writer, &agent_disconnect(e.status_code(), &e.to_string())).await;
+            return;
+        }
+    };
+
+    // frames are answered out of order (pipelining), so ACKs go through a
+    // single writer task
+    let (tx, mut rx) = mpsc::channel::<Frame>(PENDING_FRAMES);
+    let writer_task = tokio::spawn(async move {
+        while let Some(frame) = rx.recv().await {
+            if let Err(e) = write_frame(&mut writer, &frame).await {
+                log::debug!("Failed to write SPOA frame to {}: {}", remote_addr, e);
+                break;
+            }
+        }
+    });
+
+    let result = read_notifications(&mut reader, max_frame_size, &context, &tx).await;
+    let disconnect = match &result {
+        Ok(()) => agent_disconnect(0, "normal"),
+        Err(e) => {
+            log::warn!("SPOA connection from {} failed: {}", remote_addr, e);
+            agent_disconnect(e.status_code(), &e.to_string())
+        }
+    };
+    let _ = tx.send(disconnect).await;
+    drop(tx);
+    let _ = writer_task.await;
+}
+
+// exchange HELLO frames; returns the negotiated max frame size, or None when
+// the connection was only a health check
+async fn handshake(reader: &mut OwnedReadHalf, writer: &mut OwnedWriteHalf) -> Result<Option<u32>, SpopError> {
+    let hello = match read_frame(reader, MAX_FRAME_SIZE).await? {
+        Some(frame) if frame.frame_type == FrameType::HaproxyHello => frame,
+        Some(frame) => {
+            return Err(SpopError::InvalidFrame(format!(
+                "expected HAPROXY-HELLO, got {:?}",
+                frame.frame_type
+            )))
+        }
+        None => return Ok(None),
+    };
+
+    let values = decode_kv_list(&hello.payload)?;
+    let value = |name: &str| values.iter().find(|(n, _)| n == name).map(|(_, v)| v);
+
+    let versions = value("supported-versions")
+        .and_then(TypedData::as_str)
+        .ok_or(SpopError::MissingHelloValue("supported-versions"))?;
+    if !versions.split(',').any(|v| v.trim() == SPOP_VERSION) {
+        return Err(SpopError::UnsupportedVersion(versions.to_string()));
+    }
+    let max_frame_size = value("max-frame-size")
+        .and_then(TypedData::as_u64)
+        .ok_or(SpopError::MissingHelloValue("max-frame-size"))?
+        .min(u64::from(MAX_FRAME_SIZE)) as u32;
+    value("capabilities").ok_or(SpopError::MissingHelloValue("capabilities"))?;
+    let healthcheck = matches!(value("healthcheck"), Some(TypedData::Bool(true)));
+
+    let payload = encode_kv_list(&[
+        ("version", TypedData::String(SPOP_VERSION.to_string())),
+        ("max-frame-size", TypedData::Uint32(max_frame_size)),
+        ("capabilities", TypedData::String("pipelining".to_string())),
+    ]);
+    write_frame(writer, &Frame::new(FrameType::AgentHello, 0, 0, payload)).await?;
+
+    Ok(if healthcheck { None } else { Some(max_frame_size) })
+}
+
+// read NOTIFY frames until haproxy disconnects, inspecting each one in its own
+// task. on shutdown, stop reading, answer the frames already received and
+// return so the caller can send AGENT-DISCONNECT
+async fn read_notifications(
+    reader: &mut OwnedReadHalf,
+    max_frame_size: u32,
+    context: &ConnectorContext,
+    acks: &mpsc::Sender<Frame>,
+) -> Result<(), SpopError> {
+    let mut pending = JoinSet::new();
+    let mut peeked = [0u8; 1];
+    loop {
+        while pending.try_join_next().is_some() {}
+
+        // only wait for the next frame to start, a frame is never read in part
+        tokio::select! {
+            result = reader.peek(&mut peeked) => {
+                result?;
+            }
+            _ = context.state.stopped() => {
+                while pending.join_next().await.is_some() {}
+                return Ok(());
+            }
+        }
+        let Some(frame) = read_frame(reader, max_frame_size).await? else {
+            return Ok(());
+        };
+
+        match frame.frame_type {
+            FrameType::Notify => {
+                if frame.flags & FLAG_FIN == 0 {
+                    return Err(SpopError::FragmentationNotSupported);
+                }
+                let messages = decode_messages(&frame.payload)?;
+                let context = context.clone();
+                let acks = acks.clone();
+                pending.spawn(async move {
+                    let vars = inspect_messages(&messages, &context).await;
+                    let ack = Frame::new(FrameType::Ack, frame.stream_id, frame.frame_id, encode_actions(&vars));
+                    let _ = acks.send(ack).await;
+                });
+            }
+            FrameType::HaproxyDisconnect => {
+                let values = decode_kv_list(&frame.payload)?;
+                let message = values
+                    .iter()
+                    .find(|(n, _)| n == "message")
+                    .and_then(|(_, v)| v.as_str())
+                    .unwrap_or_default();
+                log::debug!("HAProxy disconnected: {}", message);
+                return Ok(());
+            }
+            other => return Err(SpopError::InvalidFrame(format!("unexpected {:?} frame", other))),
+        }
+    }
+}
+
+// inspect every http request message of a NOTIFY frame and turn the merged
+// verdict into transaction variables
+async fn inspect_messages(messages: &[Message], context: &ConnectorContext) -> Vec<SetVar> {
+    let mut verdict = Verdict::Allow;
+    let mut request_ids = Vec::new();
+
+    for message in messages {
+        let Some(request) = message_request(message) else {
+            log::debug!("Ignoring SPOE message '{}' without an http request", message.name);
+            continue;
+        };
+        verdict = verdict.merge(context.inspect(&request).await);
+        request_ids.push(request.id);
+    }
+
+    let mut vars = vec![SetVar::new("verdict", TypedData::String(verdict.name().to_string()))];
+    if !verdict.rules().is_empty() {
+        vars.push(SetVar::new("rule", TypedData::String(verdict.rules().join(","))));
+    }
+    if let Verdict::Block { status, .. } = &verdict {
+        vars.push(SetVar::new("status", TypedData::Uint32(u32::from(*status))));
+    }
+    if !request_ids.is_empty() {
+        vars.push(SetVar::new("request_id", TypedData::String(request_ids.join(","))));
+    }
+    vars
+}
+
+// build a request from the message arguments; a message is an http request
+// when it carries a method, a path or a uri
+fn message_request(message: &Message) -> Option<RequestContext> {
+    let text = |name: &str| message.arg(name).and_then(TypedData::as_str);
+
+    let uri = match text("uri").or_else(|| text("url")) {
+        Some(uri) => uri.to_string(),
+        None => {
+            let path = text("path")?;
+            match text("query").filter(|q| !q.is_empty()) {
+                Some(query) => format!("{}?{}", path, query),
+                None => path.to_string(),
+            }
+        }
+    };
+    let mut request = RequestContext::new(text("method").unwrap_or("GET"), uri);
+
+    match message.arg("headers") {
+        Some(TypedData::Binary(data)) => match decode_headers(data) {
+            Ok(headers) => request.headers = headers,
+            Err(e) => log::warn!("Invalid req.hdrs_bin in SPOE message '{}': {}", message.name, e),
+        },
+        Some(TypedData::String(raw)) => {
+            request.headers = raw
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .collect();
+        }
+        _ => {}
+    }
+
+    match message.arg("body") {
+        Some(TypedData::Binary(body)) => request.body = body.clone().into(),
+        Some(TypedData::String(body)) => request.body = body.clone().into_bytes().into(),
+        _ => {}
+    }
+
+    let ip = match message.arg("ip").or_else(|| message.arg("src")) {
+        Some(TypedData::Ipv4(ip)) => Some(IpAddr::V4(*ip)),
+        Some(TypedData::Ipv6(ip)) => Some(IpAddr::V6(*ip)),
+        Some(other) => other.as_str().and_then(|s| s.parse().ok()),
+        None => None,
+    };
+    let port = message
+        .arg("port")
+        .or_else(|| message.arg("src_port"))
+        .and_then(TypedData::as_u64)
+        .and_then(|port| u16::try_from(port).ok())
+        .unwrap_or(0);
+    request.remote_addr = ip.map(|ip| SocketAddr::new(ip, port));
+
+    if matches!(message.arg("ssl"), Some(TypedData::Bool(true))) {
+        request.tls = Some(TlsInfo {
+            version: text("ssl_version").map(str::to_string),
+            cipher: text("ssl_cipher").map(str::to_string),
+            server_name: text("ssl_sni").map(str::to_string),
+        });
+    }
+
+    Some(request)
+}
+
+fn agent_disconnect(status_code: u32, message: &str) -> Frame {
+    let payload = encode_kv_list(&[
+        ("status-code", TypedData::Uint32(status_code)),
+        ("message", TypedData::String(message.to_string())),
+    ]);
+    Frame::new(FrameType::AgentDisconnect, 0, 0, payload)
+}
@@ -0,0 +1,494 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+// encoding and decoding of haproxy's stream processing offload protocol (spop 2.0)
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub const SPOP_VERSION: &str = "2.0";
+
+// largest frame the agent accepts, haproxy may negotiate it down
+pub const MAX_FRAME_SIZE: u32 = 16384;
+
+pub const FLAG_FIN: u32 = 0x01;
+
+// scope of the variables set by the agent; haproxy exposes them as
+// txn.<var-prefix>.<name>
+const VAR_SCOPE_TRANSACTION: u8 = 2;
+const ACTION_SET_VAR: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum SpopError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Frame of {0} bytes exceeds the negotiated max-frame-size")]
+    FrameTooBig(usize),
+
+    #[error("Invalid frame: {0}")]
+    InvalidFrame(String),
+
+    #[error("HAPROXY-HELLO is missing '{0}'")]
+    MissingHelloValue(&'static str),
+
+    #[error("Unsupported SPOP versions: {0}")]
+    UnsupportedVersion(String),
+
+    #[error("Fragmented frames are not supported")]
+    FragmentationNotSupported,
+}
+
+impl SpopError {
+    // status code reported to haproxy in AGENT-DISCONNECT
+    pub fn status_code(&self) -> u32 {
+        match self {
+            SpopError::IoError(_) => 1,
+            SpopError::FrameTooBig(_) => 3,
+            SpopError::InvalidFrame(_) => 4,
+            SpopError::MissingHelloValue("supported-versions") => 5,
+            SpopError::MissingHelloValue("max-frame-size") => 6,
+            SpopError::MissingHelloValue(_) => 7,
+            SpopError::UnsupportedVersion(_) => 8,
+            SpopError::FragmentationNotSupported => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    HaproxyHello,
+    HaproxyDisconnect,
+    Notify,
+    AgentHello,
+    AgentDisconnect,
+    Ack,
+}
+
+impl FrameType {
+    fn from_u8(value: u8) -> Result<Self, SpopError> {
+        match value {
+            1 => Ok(FrameType::HaproxyHello),
+            2 => Ok(FrameType::HaproxyDisconnect),
+            3 => Ok(FrameType::Notify),
+            101 => Ok(FrameType::AgentHello),
+            102 => Ok(FrameType::AgentDisconnect),
+            103 => Ok(FrameType::Ack),
+            other => Err(SpopError::InvalidFrame(format!("unknown frame type {}", other))),
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            FrameType::HaproxyHello => 1,
+            FrameType::HaproxyDisconnect => 2,
+            FrameType::Notify => 3,
+            FrameType::AgentHello => 101,
+            FrameType::AgentDisconnect => 102,
+            FrameType::Ack => 103,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub frame_type: FrameType,
+    pub flags: u32,
+    pub stream_id: u64,
+    pub frame_id: u64,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(frame_type: FrameType, stream_id: u64, frame_id: u64, payload: Vec<u8>) -> Self {
+        Self {
+            frame_type,
+            flags: FLAG_FIN,
+            stream_id,
+            frame_id,
+            payload,
+        }
+    }
+}
+
+// a value as typed on the wire
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedData {
+    Null,
+    Bool(bool),
+    Int32(i32),
+    Uint32(u32),
+    Int64(i64),
+    Uint64(u64),
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    String(String),
+    Binary(Vec<u8>),
+}
+
+impl TypedData {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            TypedData::String(s) => Some(s),
+            TypedData::Binary(b) => std::str::from_utf8(b).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            TypedData::Int32(v) => u64::try_from(*v).ok(),
+            TypedData::Uint32(v) => Some(u64::from(*v)),
+            TypedData::Int64(v) => u64::try_from(*v).ok(),
+            TypedData::Uint64(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+// a message sent by haproxy in a NOTIFY frame
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub name: String,
+    pub args: Vec<(String, TypedData)>,
+}
+
+impl Message {
+    pub fn arg(&self, name: &str) -> Option<&TypedData> {
+        self.args.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+}
+
+// a transaction variable set through an ACK frame
+#[derive(Debug, Clone)]
+pub struct SetVar {
+    pub name: String,
+    pub value: TypedData,
+}
+
+impl SetVar {
+    pub fn new(name: &str, value: TypedData) -> Self {
+        Self {
+            name: name.to_string(),
+            value,
+        }
+    }
+}
+
+// cursor over a frame payload
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn u8(&mut self) -> Result<u8, SpopError> {
+        let byte = *self.buf.get(self.pos).ok_or_else(truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SpopError> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.buf.len()).ok_or_else(truncated)?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, SpopError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // spop varint: values below 240 take one byte, larger ones continue in
+    // 7-bit groups while the high bit is set
+    fn varint(&mut self) -> Result<u64, SpopError> {
+        let mut value = u64::from(self.u8()?);
+        if value < 240 {
+            return Ok(value);
+        }
+        let mut shift: u32 = 4;
+        loop {
+            let byte = u64::from(self.u8()?);
+            // a group whose bits don't fit in 64 is as bad as one that
+            // carries past the top
+            let group = byte.checked_shl(shift).filter(|group| group >> shift == byte);
+            value = match group.and_then(|group| value.checked_add(group)) {
+                Some(value) => value,
+                None => return Err(SpopError::InvalidFrame("varint overflow".to_string())),
+            };
+            shift += 7;
+            if byte < 128 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SpopError> {
+        let len = self.varint()? as usize;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SpopError::InvalidFrame("string is not utf-8".to_string()))
+    }
+
+    fn typed(&mut self) -> Result<TypedData, SpopError> {
+        let type_byte = self.u8()?;
+        let value = match type_byte & 0x0f {
+            0 => TypedData::Null,
+            1 => TypedData::Bool(type_byte & 0x10 != 0),
+            2 => TypedData::Int32(self.varint()? as i32),
+            3 => TypedData::Uint32(self.varint()? as u32),
+            4 => TypedData::Int64(self.varint()? as i64),
+            5 => TypedData::Uint64(self.varint()?),
+            6 => {
+                let b = self.bytes(4)?;
+                TypedData::Ipv4(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+            }
+            7 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(self.bytes(16)?);
+                TypedData::Ipv6(Ipv6Addr::from(octets))
+            }
+            8 => TypedData::String(self.string()?),
+            9 => {
+                let len = self.varint()? as usize;
+                TypedData::Binary(self.bytes(len)?.to_vec())
+            }
+            other => return Err(SpopError::InvalidFrame(format!("unknown data type {}", other))),
+        };
+        Ok(value)
+    }
+}
+
+fn truncated() -> SpopError {
+    SpopError::InvalidFrame("truncated payload".to_string())
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    if value < 240 {
+        out.push(value as u8);
+        return;
+    }
+    out.push((value as u8) | 240);
+    value = (value - 240) >> 4;
+    while value >= 128 {
+        out.push((value as u8) | 128);
+        value = (value - 128) >> 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn write_typed(out: &mut Vec<u8>, value: &TypedData) {
+    match value {
+        TypedData::Null => out.push(0),
+        TypedData::Bool(b) => out.push(if *b { 0x11 } else { 0x01 }),
+        TypedData::Int32(v) => {
+            out.push(2);
+            write_varint(out, *v as u32 as u64);
+        }
+        TypedData::Uint32(v) => {
+            out.push(3);
+            write_varint(out, u64::from(*v));
+        }
+        TypedData::Int64(v) => {
+            out.push(4);
+            write_varint(out, *v as u64);
+        }
+        TypedData::Uint64(v) => {
+            out.push(5);
+            write_varint(out, *v);
+        }
+        TypedData::Ipv4(ip) => {
+            out.push(6);
+            out.extend_from_slice(&ip.octets());
+        }
+        TypedData::Ipv6(ip) => {
+            out.push(7);
+            out.extend_from_slice(&ip.octets());
+        }
+        TypedData::String(s) => {
+            out.push(8);
+            write_bytes(out, s.as_bytes());
+        }
+        TypedData::Binary(b) => {
+            out.push(9);
+            write_bytes(out, b);
+        }
+    }
+}
+
+// read one frame; Ok(None) means the peer closed the connection cleanly
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, max_frame_size: u32) -> Result<Option<Frame>, SpopError> {
+    let len = match reader.read_u32().await {
+        Ok(len) => len,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if len > max_frame_size {
+        return Err(SpopError::FrameTooBig(len as usize));
+    }
+
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf).await?;
+
+    let mut r = Reader::new(&buf);
+    let frame_type = FrameType::from_u8(r.u8()?)?;
+    let flags = r.u32()?;
+    let stream_id = r.varint()?;
+    let frame_id = r.varint()?;
+    let payload = buf[r.pos..].to_vec();
+
+    Ok(Some(Frame {
+        frame_type,
+        flags,
+        stream_id,
+        frame_id,
+        payload,
+    }))
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, frame: &Frame) -> Result<(), SpopError> {
+    let mut body = Vec::with_capacity(frame.payload.len() + 16);
+    body.push(frame.frame_type.as_u8());
+    body.extend_from_slice(&frame.flags.to_be_bytes());
+    write_varint(&mut body, frame.stream_id);
+    write_varint(&mut body, frame.frame_id);
+    body.extend_from_slice(&frame.payload);
+
+    writer.write_u32(body.len() as u32).await?;
+    writer.write_all(&body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+// decode the key/value list carried by HELLO and DISCONNECT frames
+pub fn decode_kv_list(payload: &[u8]) -> Result<Vec<(String, TypedData)>, SpopError> {
+    let mut r = Reader::new(payload);
+    let mut list = Vec::new();
+    while !r.is_empty() {
+        let name = r.string()?;
+        let value = r.typed()?;
+        list.push((name, value));
+    }
+    Ok(list)
+}
+
+pub fn encode_kv_list(list: &[(&str, TypedData)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, value) in list {
+        write_bytes(&mut out, name.as_bytes());
+        write_typed(&mut out, value);
+    }
+    out
+}
+
+// decode the list of messages carried by a NOTIFY frame
+pub fn decode_messages(payload: &[u8]) -> Result<Vec<Message>, SpopError> {
+    let mut r = Reader::new(payload);
+    let mut messages = Vec::new();
+    while !r.is_empty() {
+        let name = r.string()?;
+        let nb_args = r.u8()?;
+        let mut args = Vec::with_capacity(nb_args as usize);
+        for _ in 0..nb_args {
+            let arg_name = r.string()?;
+            args.push((arg_name, r.typed()?));
+        }
+        messages.push(Message { name, args });
+    }
+    Ok(messages)
+}
+
+// encode the list of set-var actions carried by an ACK frame
+pub fn encode_actions(vars: &[SetVar]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for var in vars {
+        // action type, number of arguments (scope, name, value), scope
+        out.extend_from_slice(&[ACTION_SET_VAR, 3, VAR_SCOPE_TRANSACTION]);
+        write_bytes(&mut out, var.name.as_bytes());
+        write_typed(&mut out, &var.value);
+    }
+    out
+}
+
+// decode the headers of a `req.hdrs_bin` sample: name/value string pairs
+// terminated by an empty pair
+pub fn decode_headers(data: &[u8]) -> Result<Vec<(String, String)>, SpopError> {
+    let mut r = Reader::new(data);
+    let mut headers = Vec::new();
+    while !r.is_empty() {
+        let name_len = r.varint()? as usize;
+        let name = r.bytes(name_len)?;
+        let value_len = r.varint()? as usize;
+        let value = r.bytes(value_len)?;
+        if name.is_empty() && value.is_empty() {
+            break;
+        }
+        headers.push((
+            String::from_utf8_lossy(name).into_owned(),
+            String::from_utf8_lossy(value).into_owned(),
+        ));
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_round_trips() {
+        for value in [0, 239, 240, 2287, 2288, u64::from(u32::MAX), u64::MAX] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            assert_eq!(Reader::new(&out).varint().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn varint_past_64_bits_is_rejected() {
+        let mut out = Vec::new();
+        write_varint(&mut out, u64::MAX);
+        // bump the last group so the value carries past the top
+        *out.last_mut().unwrap() += 1;
+        assert!(matches!(Reader::new(&out).varint(), Err(SpopError::InvalidFrame(_))));
+
+        let endless = [0xffu8; 16];
+        assert!(matches!(Reader::new(&endless).varint(), Err(SpopError::InvalidFrame(_))));
+    }
+}
@@ -26,8 +26,11 @@ use thiserror::Error;
 use zark_waf_config_manager::error::ConfigError;
 use zark_waf_module_manager::ModuleManagerError;
 use zark_waf_plugin_system::PluginError;
+use zark_waf_common::messenger::MessengerError;
+use zark_waf_dsl::DslError;
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum CoreError {
 
     #[error("Configuration error: {0}")]
@@ -39,6 +42,9 @@ pub enum CoreError {
     #[error("Runtime error: {0}")]
     RuntimeError(String),
 
+    #[error("Path not allowed: {0}")]
+    PathNotAllowed(String),
+
     #[error("Shutdown error: {0}")]
     ShutdownError(String),  // Add this line
 
@@ -48,6 +54,13 @@ pub enum CoreError {
     #[error("Plugin error: {0}")]
     PluginError(#[from] PluginError),
 
+    #[error("Messenger error: {0}")]
+    MessengerError(#[from] MessengerError),
+
+    #[error("Rule error: {0}")]
+    RuleError(#[from] DslError),
+
+
     #[error("I/O error: {0}")]
     IoError(#[from] std::io::Error),
 
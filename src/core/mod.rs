@@ -21,103 +21,508 @@
 // SOFTWARE.
 //
 // Authors: I. Zeqiri, E. Gjergji
-
 mod error;
+mod pipeline;
+mod signals;
 mod state;
 
-use crate::core::error::CoreError;
-use crate::core::state::CoreState;
-use std::{ffi::c_void, sync::Arc};
-use tokio::sync::RwLock;
-use zark_waf_config_manager::config::Config;
-use zark_waf_module_manager::{Module, ModuleManager};
-use zark_waf_plugin_system::PluginSystem;
-use zark_waf_common::messenger::Messenger;
+pub use crate::core::error::CoreError;
+pub use crate::core::pipeline::InspectionPipeline;
+pub use crate::core::state::CoreState;
+
+use crate::admin::AdminServer;
+use crate::connectors::{self, ConnectorContext};
+use crate::core::pipeline::{ModuleStage, PluginStage, RuleStage};
+use crate::core::signals::{Signal, Signals};
+use crate::core::state::{ModuleState, PluginState};
+use arc_swap::ArcSwap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, Semaphore};
+use tokio::task::JoinSet;
+use zark_waf_config_manager::config::{Config, FailureMode};
+use zark_waf_module_manager::{ModuleInfo, ModuleManager};
+use zark_waf_plugin_system::{PluginMetadata, PluginSystem};
+use zark_waf_common::messenger::{FileStore, FileStoreOptions, LifecycleEvent, Messenger, Priority, SubscriptionStats, SyncPolicy};
+#[cfg(unix)]
+use zark_waf_common::messenger::BridgeServer;
+use zark_waf_common::utils::uid::Uid;
+use zark_waf_dsl::RuleEngine;
+
+// zark-core settings that take effect without a restart
+const RELOADABLE_CORE_SETTINGS: [&str; 2] = ["rules-path", "drain-timeout"];
+
+// how often open connections are counted while draining
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 pub struct ZarkWafCore {
-    config: Config,
-    state: Arc<RwLock<CoreState>>,
-    module_manager: ModuleManager,
-    plugin_system: PluginSystem,
+    config_path: String,
+    config: RwLock<Config>,
+    state: Arc<CoreState>,
+    module_manager: Arc<RwLock<ModuleManager>>,
+    plugin_system: Arc<PluginSystem>,
+    // modules hold a raw pointer to the messenger, so the core keeps it alive
     messenger: Arc<Messenger>,
+    // replaced as a whole whenever rules, modules or plugins change; requests
+    // already in flight finish on the chain they started with
+    pipeline: Arc<ArcSwap<InspectionPipeline>>,
 }
 
 impl ZarkWafCore {
     pub async fn new(config_path: &str) -> Result<Self, CoreError> {
         let config = Config::load(config_path).await.map_err(CoreError::ConfigError)?;
+        if let Some(node_id) = config.core.node_id {
+            Uid::set_node_id(node_id);
+        }
 
-        // Create ZarkMessenger instance using the common crate messenger module
-        let messenger = Arc::new(Messenger::new(""));
+        // The message bus runs in-process unless an external library is configured
+        let messenger = Arc::new(match &config.core.messenger_library {
+            Some(library_path) => Messenger::with_library(library_path)?,
+            None => Messenger::new(),
+        });
+        if !config.core.durable_topics.is_empty() {
+            let directory = config.core.durable_store.as_deref().ok_or_else(|| {
+                CoreError::InitError("zark-core.durable-topics needs zark-core.durable-store".to_string())
+            })?;
+            let options = FileStoreOptions {
+                sync: if config.core.durable_sync { SyncPolicy::Always } else { SyncPolicy::Never },
+                ..FileStoreOptions::default()
+            };
+            let store = Arc::new(FileStore::open_with(directory, options).await?);
+            for topic in &config.core.durable_topics {
+                messenger.make_durable(topic, store.clone())?;
+            }
+        }
         
-        let module_manager = ModuleManager::new();
-        let plugin_system = PluginSystem::new();
+        let module_manager = Arc::new(RwLock::new(ModuleManager::new(messenger.clone())));
+        let plugin_system = Arc::new(PluginSystem::new(messenger.clone()));
 
+        let state = Arc::new(CoreState::new());
         let core = Self {
-            config,
-            state: Arc::new(RwLock::new(CoreState::new())),
+            config_path: config_path.to_string(),
+            config: RwLock::new(config),
+            pipeline: Arc::new(ArcSwap::from_pointee(InspectionPipeline::new(state.clone()))),
+            state,
             module_manager,
             plugin_system,
             messenger,
         };
 
-        core.init()?;
+        core.init().await?;
 
         Ok(core)
     }
 
-    fn init(&mut self) -> Result<(), CoreError> {
+    async fn init(&self) -> Result<(), CoreError> {
+        let config = self.config.read().await.clone();
+
+        // Load the logger first so every other module can log through it
+        if let Some(logger_path) = &config.modules.logger_path {
+            self.load_module_library(logger_path, FailureMode::Open).await.map_err(|e| {
+                CoreError::InitError(format!("Failed to load logger module: {}", e))
+            })?;
+        }
+
         // Load modules
-        for (name, path) in &self.config.modules {
-            self.module_manager.load_module(path).map_err(|e| {
-                CoreError::InitError(format!("Failed to load module {}: {}", name, e))
+        for module in &config.modules.paths {
+            self.load_module_library(&module.path, module.on_failure).await.map_err(|e| {
+                CoreError::InitError(format!("Failed to load module {}: {}", module.name, e))
             })?;
         }
 
+        // Load plugins
+        for plugin in &config.plugins.paths {
+            self.load_plugin_library(&plugin.path, plugin.on_failure).await.map_err(|e| {
+                CoreError::InitError(format!("Failed to load plugin {}: {}", plugin.name, e))
+            })?;
+        }
+
+        let pipeline = self.build_pipeline(config.core.rules_path.as_deref()).await?;
+        self.pipeline.store(Arc::new(pipeline));
+
+        Ok(())
+    }
+
+    async fn load_module_library(&self, path: &str, on_failure: FailureMode) -> Result<String, CoreError> {
+        let name = self.module_manager.write().await.load_module(path).await?;
+        self.state.update_module_state(name.clone(), ModuleState { is_active: true, last_execution: None, on_failure });
+        Ok(name)
+    }
+
+    async fn load_plugin_library(&self, path: &str, on_failure: FailureMode) -> Result<String, CoreError> {
+        let name = self.plugin_system.load_plugin(path).await?;
+        self.state.update_plugin_state(name.clone(), PluginState { is_loaded: true, last_execution: None, on_failure });
+        Ok(name)
+    }
+
+    // the inspection chain: rules first, then modules and plugins in load
+    // order. libraries marked inactive (being unloaded) get no stage
+    async fn build_pipeline(&self, rules_path: Option<&str>) -> Result<InspectionPipeline, CoreError> {
+        let mut pipeline = InspectionPipeline::new(self.state.clone());
+        if let Some(rules_path) = rules_path {
+            pipeline.add_stage(Box::new(RuleStage::new(RuleEngine::from_file(rules_path)?)));
+        }
+        for name in self.module_manager.read().await.module_names() {
+            let (active, on_failure) = self.state.module_states.get(&name)
+                .map(|state| (state.is_active, state.on_failure))
+                .unwrap_or_default();
+            if active {
+                pipeline.add_stage(Box::new(ModuleStage::new(name, self.module_manager.clone(), self.state.clone(), on_failure)));
+            }
+        }
+        for name in self.plugin_system.plugin_names().await {
+            let (loaded, on_failure) = self.state.plugin_states.get(&name)
+                .map(|state| (state.is_loaded, state.on_failure))
+                .unwrap_or_default();
+            if loaded {
+                pipeline.add_stage(Box::new(PluginStage::new(name, self.plugin_system.clone(), self.state.clone(), on_failure)));
+            }
+        }
+        Ok(pipeline)
+    }
+
+    // take a library's stage out of the chain before it is unloaded, and wait
+    // (up to drain-timeout) for inspections still running on the old chain,
+    // so none of them finds the library gone
+    async fn retire_stage(&self) -> Result<(), CoreError> {
+        let (rules_path, drain_timeout) = {
+            let config = self.config.read().await;
+            (config.core.rules_path.clone(), Duration::from_secs(config.core.drain_timeout))
+        };
+        let pipeline = self.build_pipeline(rules_path.as_deref()).await?;
+        let stages = pipeline.stage_names().join(" -> ");
+        let old = self.pipeline.swap(Arc::new(pipeline));
+        log::info!("Inspection chain: [{}]", stages);
+
+        let deadline = Instant::now() + drain_timeout;
+        while Arc::strong_count(&old) > 1 && Instant::now() < deadline {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+        if Arc::strong_count(&old) > 1 {
+            log::warn!("Unloading with inspections still running on the previous chain");
+        }
+        Ok(())
+    }
+
+    // rebuild the chain from the current config and swap it in. the old chain
+    // stays in place if the rules fail to load
+    pub async fn reload_pipeline(&self) -> Result<Vec<String>, CoreError> {
+        let rules_path = self.config.read().await.core.rules_path.clone();
+        let pipeline = self.build_pipeline(rules_path.as_deref()).await?;
+        Ok(self.swap_pipeline(pipeline))
+    }
+
+    fn swap_pipeline(&self, pipeline: InspectionPipeline) -> Vec<String> {
+        let stages: Vec<String> = pipeline.stage_names().into_iter().map(str::to_string).collect();
+        self.pipeline.store(Arc::new(pipeline));
+        log::info!("Inspection chain: [{}]", stages.join(" -> "));
+        stages
+    }
+
+    // re-read the config file and apply what can change at runtime (the rule
+    // set and drain timeout). returns the top-level settings that changed but
+    // need a restart. the config and the chain are replaced together, and
+    // only once the new rules have loaded
+    pub async fn reload_config(&self) -> Result<Vec<String>, CoreError> {
+        let config = Config::load(&self.config_path).await?;
+        // held until the swap so concurrent reloads apply one after the other
+        let mut current_config = self.config.write().await;
+        let pipeline = self.build_pipeline(config.core.rules_path.as_deref()).await?;
+
+        let mut current = serde_json::to_value(&*current_config)?;
+        let mut reloaded = serde_json::to_value(&config)?;
+        for value in [&mut current, &mut reloaded] {
+            if let Some(core) = value.get_mut("zark-core").and_then(|core| core.as_object_mut()) {
+                for setting in RELOADABLE_CORE_SETTINGS {
+                    core.remove(setting);
+                }
+            }
+        }
+        let mut restart_required = Vec::new();
+        if let (Some(current), Some(reloaded)) = (current.as_object(), reloaded.as_object()) {
+            for (section, value) in reloaded {
+                if current.get(section) != Some(value) {
+                    restart_required.push(section.clone());
+                }
+            }
+        }
+
+        *current_config = config;
+        self.swap_pipeline(pipeline);
+        drop(current_config);
+        if !restart_required.is_empty() {
+            log::warn!("Configuration reloaded, changes to [{}] apply after a restart", restart_required.join(", "));
+        }
+        self.announce("reloaded").await;
+        Ok(restart_required)
+    }
+
+    // tell modules about a core lifecycle event ahead of any queued traffic
+    async fn announce(&self, event: &str) {
+        let topic = format!("core.{}", event);
+        let event = LifecycleEvent::core(event);
+        if let Err(e) = self
+            .messenger
+            .send_typed_with_priority(&topic, "zark-core", &event, Priority::Control)
+            .await
+        {
+            log::warn!("Failed to announce {}: {}", topic, e);
+        }
+    }
+
+    pub fn state(&self) -> &CoreState {
+        &self.state
+    }
+
+    pub async fn messenger_stats(&self) -> Vec<SubscriptionStats> {
+        self.messenger.stats().await
+    }
+
+    pub async fn list_modules(&self) -> Vec<ModuleInfo> {
+        self.module_manager.read().await.list_modules().await
+    }
+
+    // load a module at runtime and add it to the end of the module stages
+    pub async fn load_module(&self, path: &str, on_failure: FailureMode) -> Result<ModuleInfo, CoreError> {
+        let directory = self.config.read().await.modules.directory.clone();
+        let path = library_in("modules", path, directory.as_deref()).await?;
+        let name = self.load_module_library(&path, on_failure).await?;
+        let module_manager = self.module_manager.read().await;
+        module_manager.execute_module(&name, serde_json::json!({"action": "start"})).await?;
+        let info = module_manager.get_module_info(&name).await?;
+        drop(module_manager);
+
+        self.reload_pipeline().await?;
+        Ok(info)
+    }
+
+    pub async fn unload_module(&self, name: &str) -> Result<(), CoreError> {
+        let set_active = |active: bool| {
+            if let Some(mut state) = self.state.module_states.get_mut(name) {
+                state.is_active = active;
+            }
+        };
+        set_active(false);
+        let unloaded = match self.retire_stage().await {
+            Ok(()) => self.module_manager.write().await.unload_module(name).await.map_err(CoreError::from),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = unloaded {
+            // still loaded: put its stage back
+            set_active(true);
+            self.reload_pipeline().await?;
+            return Err(e);
+        }
+        self.state.remove_module_state(name);
+        Ok(())
+    }
+
+    pub async fn list_plugins(&self) -> Vec<PluginMetadata> {
+        self.plugin_system.list_plugins().await
+    }
+
+    pub async fn load_plugin(&self, path: &str, on_failure: FailureMode) -> Result<PluginMetadata, CoreError> {
+        let directory = self.config.read().await.plugins.directory.clone();
+        let path = library_in("plugins", path, directory.as_deref()).await?;
+        let name = self.load_plugin_library(&path, on_failure).await?;
+        let metadata = self.plugin_system.get_plugin_metadata(&name).await?;
+        self.reload_pipeline().await?;
+        Ok(metadata)
+    }
+
+    pub async fn unload_plugin(&self, name: &str) -> Result<(), CoreError> {
+        let set_loaded = |loaded: bool| {
+            if let Some(mut state) = self.state.plugin_states.get_mut(name) {
+                state.is_loaded = loaded;
+            }
+        };
+        set_loaded(false);
+        let unloaded = match self.retire_stage().await {
+            Ok(()) => self.plugin_system.unload_plugin(name).await.map_err(CoreError::from),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = unloaded {
+            // still loaded: put its stage back
+            set_loaded(true);
+            self.reload_pipeline().await?;
+            return Err(e);
+        }
+        self.state.remove_plugin_state(name);
         Ok(())
     }
 
-    pub async fn run(&self) -> Result<(), CoreError> {
-        log::info!("ZARK-WAF core is running");
+    async fn connector_context(&self) -> ConnectorContext {
+        let config = self.config.read().await;
+        ConnectorContext {
+            pipeline: self.pipeline.clone(),
+            state: self.state.clone(),
+            messenger: self.messenger.clone(),
+            connection_limit: Arc::new(Semaphore::new(config.core.max_connections)),
+            max_body_size: config.core.max_body_size,
+        }
+    }
+
+    // bind the connector of every enabled web server
+    async fn start_connectors(&self) -> Result<JoinSet<()>, CoreError> {
+        let context = self.connector_context().await;
+        let mut connectors = JoinSet::new();
+
+        for (name, web_server) in &self.config.read().await.web_servers {
+            if !web_server.enabled {
+                continue;
+            }
+            connectors::start(name, web_server, context.clone(), &mut connectors).await?;
+        }
+
+        if connectors.is_empty() {
+            log::warn!("No web servers enabled, ZARK-WAF is not receiving any traffic");
+        }
+
+        Ok(connectors)
+    }
+
+    pub async fn run(self: Arc<Self>) -> Result<(), CoreError> {
+        let mut signals = Signals::new().map_err(|e| {
+            CoreError::RuntimeError(format!("Failed to listen for signals: {}", e))
+        })?;
+
+        self.module_manager.write().await.start_all_modules().await?;
+        let mut connectors = self.start_connectors().await?;
 
-        // Main loop
+        let admin = self.config.read().await.admin.clone().filter(|admin| admin.enabled);
+        if let Some(admin) = admin {
+            connectors.spawn(AdminServer::bind(&admin, self.clone()).await?.serve());
+        }
+
+        // out-of-process modules reach the message bus through the bridge
+        #[cfg(unix)]
+        if let Some(socket) = self.config.read().await.core.messenger_socket.clone() {
+            connectors.spawn(BridgeServer::bind(&socket, self.messenger.clone())?.serve());
+        }
+
+        log::info!(
+            "ZARK-WAF core is running, inspection chain: [{}]",
+            self.pipeline.load().stage_names().join(" -> ")
+        );
+
+        let signal = loop {
+            match signals.recv().await {
+                Signal::Reload => {
+                    log::info!("SIGHUP received, reloading configuration");
+                    if let Err(e) = self.reload_config().await {
+                        log::error!("Reload failed, keeping the current configuration: {}", e);
+                    }
+                }
+                Signal::Shutdown(name) => break name,
+            }
+        };
+
+        // connectors stop accepting and close their connections as they go idle
+        log::info!("{} received, draining connections", signal);
+        self.announce("stopping").await;
+        self.state.stop();
+        let drain_timeout = Duration::from_secs(self.config.read().await.core.drain_timeout);
+        let drain = self.drain(drain_timeout);
+        tokio::pin!(drain);
         loop {
             tokio::select! {
-                _ = tokio::signal::ctrl_c() => {
-                    log::info!("Shutdown signal received");
-                    break;
-                }
-                // Add other async tasks here if needed
+                _ = &mut drain => break,
+                signal = signals.recv() => match signal {
+                    Signal::Shutdown(name) => {
+                        log::warn!(
+                            "{} received again, not waiting for {} open connections",
+                            name,
+                            self.state.active_connections()
+                        );
+                        break;
+                    }
+                    Signal::Reload => log::info!("Ignoring SIGHUP while shutting down"),
+                },
             }
         }
+        connectors.shutdown().await;
 
         // Perform cleanup
         self.shutdown().await
     }
 
+    // wait for every open connection to finish, up to the drain timeout
+    async fn drain(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        let mut interval = tokio::time::interval(DRAIN_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let active = self.state.active_connections();
+            if active == 0 {
+                log::info!("All connections drained");
+                return;
+            }
+            if Instant::now() >= deadline {
+                log::warn!("Drain timeout of {:?} reached, closing {} open connections", timeout, active);
+                return;
+            }
+        }
+    }
+
+    // unload plugins, then modules, each in reverse load order so nothing is
+    // shut down before what was loaded on top of it. failures are logged and
+    // the rest still shut down
     async fn shutdown(&self) -> Result<(), CoreError> {
         log::info!("Shutting down ZARK-WAF core");
+        self.state.stop();
 
-        // Unload all modules
-        for (module_name, _) in &self.config.modules {
-            self.module_manager
-                .unload_module(module_name)
-                .await
-                .map_err(|e| {
-                    CoreError::ShutdownError(format!(
-                        "Failed to unload module {}: {}",
-                        module_name, e
-                    ))
-                })?;
-        }
-
-        // Unload logger module separately
-        self.module_manager
-            .unload_module("logger")
-            .await
-            .map_err(|e| CoreError::ShutdownError(format!("Failed to unload logger: {}", e)))?;
+        let mut failed = Vec::new();
+        for plugin_name in self.plugin_system.plugin_names().await.into_iter().rev() {
+            if let Err(e) = self.plugin_system.unload_plugin(&plugin_name).await {
+                log::error!("Failed to unload plugin {}: {}", plugin_name, e);
+                failed.push(plugin_name);
+            }
+        }
+
+        let mut module_manager = self.module_manager.write().await;
+        for module_name in module_manager.module_names().into_iter().rev() {
+            if let Err(e) = module_manager.unload_module(&module_name).await {
+                log::error!("Failed to unload module {}: {}", module_name, e);
+                failed.push(module_name);
+            }
+        }
 
         // The messenger will be automatically dropped when the Arc reference count reaches zero
 
-        Ok(())
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(CoreError::ShutdownError(format!("Failed to unload {}", failed.join(", "))))
+        }
+    }
+}
+
+// libraries loaded at runtime have to sit inside the configured directory
+// (modules.directory or plugins.directory), so whoever reaches the admin api
+// can't load arbitrary code from elsewhere on the host. returns the path with
+// links resolved, which is what gets loaded
+async fn library_in(section: &str, path: &str, directory: Option<&str>) -> Result<String, CoreError> {
+    let directory = directory.ok_or_else(|| {
+        CoreError::PathNotAllowed(format!("{}.directory is not set, runtime loads are disabled", section))
+    })?;
+    let directory = tokio::fs::canonicalize(directory)
+        .await
+        .map_err(|e| CoreError::PathNotAllowed(format!("{}.directory {}: {}", section, directory, e)))?;
+    let resolved = tokio::fs::canonicalize(path)
+        .await
+        .map_err(|e| CoreError::PathNotAllowed(format!("{}: {}", path, e)))?;
+    if !resolved.starts_with(&directory) {
+        return Err(CoreError::PathNotAllowed(format!("{} is outside {}", path, directory.display())));
+    }
+    Ok(resolved.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    // plugins are matched against the plugin system's version, standing in
+    // for this one
+    #[test]
+    fn plugin_host_version_is_the_core_version() {
+        assert_eq!(zark_waf_plugin_system::manifest::HOST_VERSION, env!("CARGO_PKG_VERSION"));
     }
 }
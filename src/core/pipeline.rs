@@ -0,0 +1,256 @@
+// MIT License
+//
+// Copyright (c) 2024 ZARK-WAF
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+//
+// Authors: I. Zeqiri, E. Gjergji
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use zark_waf_common::inspection::{Inspection, RequestContext, ResponseContext, Verdict};
+use zark_waf_config_manager::config::FailureMode;
+use zark_waf_dsl::RuleEngine;
+use zark_waf_module_manager::ModuleManager;
+use zark_waf_plugin_system::PluginSystem;
+
+use crate::core::error::CoreError;
+use crate::core::state::CoreState;
+
+// a single step of the inspection chain
+#[async_trait]
+pub trait InspectionStage: Send + Sync {
+    fn name(&self) -> &str;
+    async fn inspect(&self, request: &RequestContext) -> Result<Verdict, CoreError>;
+
+    // what happens to a request the stage failed to inspect
+    fn on_failure(&self) -> FailureMode {
+        FailureMode::Open
+    }
+
+    // stages that only look at requests let every response through
+    async fn inspect_response(&self, _request: &RequestContext, _response: &ResponseContext) -> Result<Verdict, CoreError> {
+        Ok(Verdict::Allow)
+    }
+}
+
+// a module or plugin's inspection, reduced to the verdict the chain merges.
+// the rules it matched are only logged
+fn stage_verdict(stage: &str, request: &RequestContext, inspection: Inspection) -> Verdict {
+    for matched in &inspection.matched {
+        log::debug!(
+            "Stage '{}' matched rule '{}' in the {} of request {}: {}",
+            stage, matched.rule, matched.phase.name(), request.id, matched.message
+        );
+    }
+    inspection.verdict
+}
+
+// evaluates the xml rule set
+pub struct RuleStage {
+    engine: RuleEngine,
+}
+
+impl RuleStage {
+    pub fn new(engine: RuleEngine) -> Self {
+        Self { engine }
+    }
+}
+
+#[async_trait]
+impl InspectionStage for RuleStage {
+    fn name(&self) -> &str {
+        "rules"
+    }
+
+    async fn inspect(&self, request: &RequestContext) -> Result<Verdict, CoreError> {
+        Ok(self.engine.evaluate(request))
+    }
+
+    async fn inspect_response(&self, request: &RequestContext, response: &ResponseContext) -> Result<Verdict, CoreError> {
+        Ok(self.engine.evaluate_response(request, response))
+    }
+}
+
+// runs a loaded module through its typed inspection methods
+pub struct ModuleStage {
+    name: String,
+    module_manager: Arc<RwLock<ModuleManager>>,
+    state: Arc<CoreState>,
+    on_failure: FailureMode,
+}
+
+impl ModuleStage {
+    pub fn new(name: String, module_manager: Arc<RwLock<ModuleManager>>, state: Arc<CoreState>, on_failure: FailureMode) -> Self {
+        Self { name, module_manager, state, on_failure }
+    }
+}
+
+#[async_trait]
+impl InspectionStage for ModuleStage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_failure(&self) -> FailureMode {
+        self.on_failure
+    }
+
+    async fn inspect(&self, request: &RequestContext) -> Result<Verdict, CoreError> {
+        let inspection = self.module_manager.read().await
+            .inspect_request(&self.name, request)
+            .await?;
+        self.state.module_executed(&self.name);
+        Ok(stage_verdict(&self.name, request, inspection))
+    }
+
+    async fn inspect_response(&self, request: &RequestContext, response: &ResponseContext) -> Result<Verdict, CoreError> {
+        let inspection = self.module_manager.read().await
+            .inspect_response(&self.name, request, response)
+            .await?;
+        self.state.module_executed(&self.name);
+        Ok(stage_verdict(&self.name, request, inspection))
+    }
+}
+
+// runs a loaded plugin through its typed inspection methods
+pub struct PluginStage {
+    name: String,
+    plugin_system: Arc<PluginSystem>,
+    state: Arc<CoreState>,
+    on_failure: FailureMode,
+}
+
+impl PluginStage {
+    pub fn new(name: String, plugin_system: Arc<PluginSystem>, state: Arc<CoreState>, on_failure: FailureMode) -> Self {
+        Self { name, plugin_system, state, on_failure }
+    }
+}
+
+#[async_trait]
+impl InspectionStage for PluginStage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_failure(&self) -> FailureMode {
+        self.on_failure
+    }
+
+    async fn inspect(&self, request: &RequestContext) -> Result<Verdict, CoreError> {
+        let inspection = self.plugin_system
+            .inspect_request(&self.name, request)
+            .await?;
+        self.state.plugin_executed(&self.name);
+        Ok(stage_verdict(&self.name, request, inspection))
+    }
+
+    async fn inspect_response(&self, request: &RequestContext, response: &ResponseContext) -> Result<Verdict, CoreError> {
+        let inspection = self.plugin_system
+            .inspect_response(&self.name, request, response)
+            .await?;
+        self.state.plugin_executed(&self.name);
+        Ok(stage_verdict(&self.name, request, inspection))
+    }
+}
+
+// ordered chain of inspection stages
+pub struct InspectionPipeline {
+    stages: Vec<Box<dyn InspectionStage>>,
+    // where stage failures are counted
+    state: Arc<CoreState>,
+}
+
+impl InspectionPipeline {
+    pub fn new(state: Arc<CoreState>) -> Self {
+        Self { stages: Vec::new(), state }
+    }
+
+    pub fn add_stage(&mut self, stage: Box<dyn InspectionStage>) {
+        self.stages.push(stage);
+    }
+
+    pub fn stage_names(&self) -> Vec<&str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    // run every stage in order and merge their verdicts. a block stops the
+    // chain early. a failing stage is counted, then skipped or turned into a
+    // block depending on its failure mode.
+    pub async fn inspect(&self, request: &RequestContext) -> Verdict {
+        let mut verdict = Verdict::Allow;
+
+        for stage in &self.stages {
+            verdict = self.merge_stage(verdict, stage.as_ref(), stage.inspect(request).await, request);
+            if verdict.is_blocked() {
+                break;
+            }
+        }
+
+        log_verdict("Request", request, &verdict);
+        verdict
+    }
+
+    // same as inspect, for the response to a request
+    pub async fn inspect_response(&self, request: &RequestContext, response: &ResponseContext) -> Verdict {
+        let mut verdict = Verdict::Allow;
+
+        for stage in &self.stages {
+            verdict = self.merge_stage(verdict, stage.as_ref(), stage.inspect_response(request, response).await, request);
+            if verdict.is_blocked() {
+                break;
+            }
+        }
+
+        log_verdict("Response to", request, &verdict);
+        verdict
+    }
+
+    fn merge_stage(&self, verdict: Verdict, stage: &dyn InspectionStage, result: Result<Verdict, CoreError>, request: &RequestContext) -> Verdict {
+        let e = match result {
+            Ok(stage_verdict) => return verdict.merge(stage_verdict),
+            Err(e) => e,
+        };
+        self.state.stage_failed(stage.name());
+        log::error!("Inspection stage '{}' failed for request {}: {}", stage.name(), request.id, e);
+        match stage.on_failure() {
+            FailureMode::Open => verdict,
+            FailureMode::Closed => verdict.merge(Verdict::block(
+                stage.name(),
+                format!("inspection stage '{}' failed", stage.name()),
+            )),
+        }
+    }
+}
+
+fn log_verdict(subject: &str, request: &RequestContext, verdict: &Verdict) {
+    match verdict {
+        Verdict::Allow => {}
+        Verdict::Block { rules, status, reason } => log::warn!(
+            "{} {} {} {} blocked with {} by [{}]: {}",
+            subject, request.id, request.method, request.uri, status, rules.join(", "), reason
+        ),
+        other => log::info!(
+            "{} {} {} {} matched [{}] ({})",
+            subject, request.id, request.method, request.uri, other.rules().join(", "), other.name()
+        ),
+    }
+}
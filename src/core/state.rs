@@ -22,26 +22,58 @@
 //
 // Authors: I. Zeqiri, E. Gjergji
 
-use std::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, AtomicBool, Ordering};
+use dashmap::DashMap;
+use tokio::sync::watch;
+use zark_waf_common::inspection::Verdict;
+use zark_waf_config_manager::config::FailureMode;
 
 
 pub struct CoreState {
     pub is_running: AtomicBool,
+    // flipped once by stop(); connectors wait on it to stop taking new work
+    shutdown: watch::Sender<bool>,
     pub active_connections: AtomicUsize,
-    pub module_states: HashMap<String, ModuleState>,
-    pub plugin_states: HashMap<String, PluginState>,
+    pub module_states: DashMap<String, ModuleState>,
+    pub plugin_states: DashMap<String, PluginState>,
+    pub inspections: InspectionCounters,
+    // inspections each stage failed, by stage name
+    pub stage_failures: DashMap<String, u64>,
+}
+
+// number of inspections (requests and responses) per resulting verdict
+#[derive(Default)]
+pub struct InspectionCounters {
+    pub allowed: AtomicU64,
+    pub logged: AtomicU64,
+    pub modified: AtomicU64,
+    pub blocked: AtomicU64,
+}
+
+impl InspectionCounters {
+    pub fn record(&self, verdict: &Verdict) {
+        let counter = match verdict {
+            Verdict::Allow => &self.allowed,
+            Verdict::Log { .. } => &self.logged,
+            Verdict::Modify { .. } => &self.modified,
+            Verdict::Block { .. } => &self.blocked,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 pub struct ModuleState {
     pub is_active: bool,
-    pub last_execution: std::time::Instant,
+    // None until the module inspected its first request
+    pub last_execution: Option<std::time::Instant>,
+    pub on_failure: FailureMode,
     // Add more module-specific state as needed
 }
 
 pub struct PluginState {
     pub is_loaded: bool,
-    pub last_execution: std::time::Instant,
+    pub last_execution: Option<std::time::Instant>,
+    pub on_failure: FailureMode,
     // Add more plugin-specific state as needed
 }
 
@@ -49,9 +81,12 @@ impl CoreState {
     pub fn new() -> Self {
         Self {
             is_running: AtomicBool::new(true),
+            shutdown: watch::Sender::new(false),
             active_connections: AtomicUsize::new(0),
-            module_states: HashMap::new(),
-            plugin_states: HashMap::new(),
+            module_states: DashMap::new(),
+            plugin_states: DashMap::new(),
+            inspections: InspectionCounters::default(),
+            stage_failures: DashMap::new(),
         }
     }
 
@@ -61,6 +96,17 @@ impl CoreState {
 
     pub fn stop(&self) {
         self.is_running.store(false, Ordering::Relaxed);
+        self.shutdown.send_replace(true);
+    }
+
+    // resolves once stop() has been called, immediately if it already was
+    pub async fn stopped(&self) {
+        let mut shutdown = self.shutdown.subscribe();
+        let _ = shutdown.wait_for(|stopped| *stopped).await;
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::Relaxed)
     }
 
     pub fn increment_connections(&self) -> usize {
@@ -71,11 +117,35 @@ impl CoreState {
         self.active_connections.fetch_sub(1, Ordering::Relaxed)
     }
 
-    pub fn update_module_state(&mut self, name: String, state: ModuleState) {
+    pub fn update_module_state(&self, name: String, state: ModuleState) {
         self.module_states.insert(name, state);
     }
 
-    pub fn update_plugin_state(&mut self, name: String, state: PluginState) {
+    pub fn update_plugin_state(&self, name: String, state: PluginState) {
         self.plugin_states.insert(name, state);
     }
+
+    pub fn remove_module_state(&self, name: &str) {
+        self.module_states.remove(name);
+    }
+
+    pub fn remove_plugin_state(&self, name: &str) {
+        self.plugin_states.remove(name);
+    }
+
+    pub fn stage_failed(&self, stage: &str) {
+        *self.stage_failures.entry(stage.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn module_executed(&self, name: &str) {
+        if let Some(mut state) = self.module_states.get_mut(name) {
+            state.last_execution = Some(std::time::Instant::now());
+        }
+    }
+
+    pub fn plugin_executed(&self, name: &str) {
+        if let Some(mut state) = self.plugin_states.get_mut(name) {
+            state.last_execution = Some(std::time::Instant::now());
+        }
+    }
 }
\ No newline at end of file
@@ -23,45 +23,41 @@
 // Authors: I. Zeqiri, E. Gjergji
 
 
+mod admin;
+mod cli;
+mod connectors;
 mod core;
 
+use std::process::ExitCode;
+
 use clap::Parser;
-use log::{error, info};
-use crate::core::ZarkWafCore;
+use log::error;
+use crate::cli::Command;
 
 #[derive(Parser)]
 #[clap(version = env!("CARGO_PKG_VERSION"), author = "I. Zeqiri, E. Gjergji")]
 struct Opts {
-    #[clap(short, long, default_value = "config/config.json")]
+    #[clap(short, long, default_value = "config/config.json", global = true)]
     config: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
     // Initialize logging
     env_logger::init();
 
     // Parse command line arguments
     let opts: Opts = Opts::parse();
 
-    info!("Starting ZARK-WAF...");
-
-    // Initialize the core
-    let core = match ZarkWafCore::new(&opts.config).await {
-        Ok(core) => core,
+    // Without a subcommand, start the WAF
+    let command = opts.command.unwrap_or(Command::Run);
+    match cli::execute(command, &opts.config).await {
+        Ok(code) => code,
         Err(e) => {
-            error!("Failed to initialize ZARK-WAF core: {}", e);
-            return Err(e.into());
+            error!("ZARK-WAF: {}", e);
+            ExitCode::FAILURE
         }
-    };
-    
-
-    // Run the core
-    if let Err(e) = core.run().await {
-        error!("ZARK-WAF core encountered an error: {}", e);
-        return Err(e.into());
     }
-
-    info!("ZARK-WAF shutting down...");
-    Ok(())
-}
\ No newline at end of file
+}